@@ -0,0 +1,131 @@
+// -*- coding:utf-8-unix -*-
+
+//! The access log file, for where the stdout of the process is not kept. When
+//! the file grows over the size limit, it is renamed to `<file>.1`, replacing
+//! the previous one, and a new file is started.
+
+use logger;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// 10 MiB.
+pub const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Appends lines to the file, rotating it by size. Not synchronized; share it
+/// behind a `Mutex`.
+pub struct AccessLogFile {
+    path: PathBuf,
+    max_bytes: u64,
+    file: Option<File>,
+    size: u64,
+    // to report a broken file once, not on every request
+    failing: bool,
+}
+
+impl AccessLogFile {
+    /// The file is opened on the first write.
+    pub fn new<P: AsRef<Path>>(path: P, max_bytes: u64) -> AccessLogFile {
+        AccessLogFile {
+            path: path.as_ref().to_path_buf(),
+            max_bytes,
+            file: None,
+            size: 0,
+            failing: false,
+        }
+    }
+
+    /// Writes the line to the file. Errors are printed to stdout, once until
+    /// the writes succeed again, as there is no better place to report them.
+    pub fn log(&mut self, line: &str) {
+        match self.write_line(line) {
+            Ok(()) => self.failing = false,
+            Err(e) => {
+                // reopen it on the next write
+                self.file = None;
+                if !self.failing {
+                    logger::warn(&format!("Could not write the access log. Logging to stdout \
+                                           only. Error: {}, File: {}",
+                                          e,
+                                          self.path.display()));
+                    self.failing = true;
+                }
+            }
+        }
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if needs_rotation(self.size, len, self.max_bytes) {
+            self.file = None;
+            fs::rename(&self.path, rotated_path(&self.path))?;
+            self.open()?;
+        }
+        if let Some(ref mut file) = self.file {
+            writeln!(file, "{}", line)?;
+            self.size += len;
+        }
+        Ok(())
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+}
+
+/// Returns true if the next line would take the file over `max_bytes`. A file
+/// that is still empty is never rotated, so a line longer than the limit gets
+/// a file of its own.
+fn needs_rotation(size: u64, line_len: u64, max_bytes: u64) -> bool {
+    size > 0 && size + line_len > max_bytes
+}
+
+/// e.g. "log/access.log" -> "log/access.log.1"
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{needs_rotation, rotated_path, AccessLogFile};
+
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn rotation_threshold() {
+        assert!(!needs_rotation(0, 10, 100));
+        assert!(!needs_rotation(90, 10, 100));
+        assert!(needs_rotation(91, 10, 100));
+        // a line over the limit still goes into an empty file
+        assert!(!needs_rotation(0, 200, 100));
+    }
+
+    #[test]
+    fn rotated_file_name() {
+        assert_eq!(PathBuf::from("log/access.log.1"), rotated_path(Path::new("log/access.log")));
+    }
+
+    #[test]
+    fn rotates_by_size() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("access.log");
+        let mut log = AccessLogFile::new(&path, 10);
+        log.log("line 1");
+        log.log("line 2");
+        log.log("line 3");
+        assert_eq!("line 3\n", fs::read_to_string(&path).unwrap());
+        assert_eq!("line 2\n", fs::read_to_string(dir.path().join("access.log.1")).unwrap());
+    }
+}
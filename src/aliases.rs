@@ -0,0 +1,110 @@
+// -*- coding:utf-8-unix -*-
+
+//! Short URLs like `/guide` for pages under a version, from the `ALIASES` env
+//! var. They are redirected, so the relative links in the target page work.
+
+use hyper::header::Location;
+use hyper::uri::RequestUri::AbsolutePath;
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::status::StatusCode;
+
+use std::collections::HashMap;
+
+use {has_version, SharedVersions};
+
+/// Redirects the alias paths to their targets with `302 Found`, keeping the
+/// query. An alias under a version, which would hide its pages, is passed on,
+/// also when the version is only found by a rescan. Register it in front of
+/// the routes and the static files.
+pub struct Aliases {
+    targets: HashMap<String, String>,
+    versions: SharedVersions,
+}
+
+impl Aliases {
+    /// `aliases` are (alias, target) pairs of absolute paths.
+    pub fn new(aliases: &[(String, String)], versions: SharedVersions) -> Aliases {
+        Aliases {
+            targets: aliases.iter().cloned().collect(),
+            versions,
+        }
+    }
+}
+
+impl<D> Middleware<D> for Aliases {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        let target = match req.origin.uri {
+            AbsolutePath(ref uri) => {
+                let (path, query) = uri.split_at(uri.find('?').unwrap_or(uri.len()));
+                self.targets
+                    .get(path)
+                    .filter(|_| !has_version(&self.versions.read().unwrap(), first_segment(path)))
+                    .map(|target| format!("{}{}", target, query))
+            }
+            _ => None,
+        };
+        match target {
+            Some(target) => {
+                res.set(Location(target));
+                res.send((StatusCode::Found, ""))
+            }
+            None => res.next_middleware(),
+        }
+    }
+}
+
+/// Returns the (alias, target) pairs from `spec`, a comma separated list like
+/// "/guide=/1.10/guide/index.html,/book=/1.10/book/". Both must be absolute
+/// paths, and the alias can't be `/`.
+pub fn parse_aliases(spec: &str) -> Result<Vec<(String, String)>, String> {
+    let mut aliases = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let parsed = entry.split_once('=')
+            .map(|(alias, target)| (alias.trim(), target.trim()))
+            .filter(|&(alias, target)| {
+                alias.starts_with('/') && alias != "/" && !alias.contains('?') &&
+                target.starts_with('/')
+            });
+        match parsed {
+            Some((alias, target)) => aliases.push((alias.to_string(), target.to_string())),
+            None => return Err(format!("Invalid ALIASES entry '{}'", entry)),
+        }
+    }
+    Ok(aliases)
+}
+
+/// Returns the first segment of the alias. e.g. "/1.10/guide" -> "1.10"
+pub fn first_segment(alias: &str) -> &str {
+    alias.trim_start_matches('/').split('/').next().unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{first_segment, parse_aliases};
+
+    #[test]
+    fn alias_list() {
+        assert_eq!(Ok(vec![("/guide".to_string(), "/1.10/guide/index.html".to_string()),
+                           ("/book".to_string(), "/1.10/book/".to_string())]),
+                   parse_aliases(" /guide = /1.10/guide/index.html , /book=/1.10/book/,"));
+        assert_eq!(Ok(vec![]), parse_aliases(""));
+    }
+
+    #[test]
+    fn invalid_aliases() {
+        assert!(parse_aliases("/guide").is_err());
+        assert!(parse_aliases("guide=/1.10/guide/").is_err());
+        assert!(parse_aliases("/guide=1.10/guide/").is_err());
+        assert!(parse_aliases("/=/1.10/").is_err());
+    }
+
+    #[test]
+    fn first_segments() {
+        assert_eq!("1.10", first_segment("/1.10/guide"));
+        assert_eq!("guide", first_segment("/guide"));
+    }
+}
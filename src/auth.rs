@@ -0,0 +1,92 @@
+// -*- coding:utf-8-unix -*-
+
+//! Optional HTTP Basic authentication for the whole site.
+
+use hyper::header::{Authorization, Basic};
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::status::StatusCode;
+
+/// Answers `401 Unauthorized` to the requests without the user name and
+/// password. `/healthz` stays open so that probes don't need the credentials.
+/// Register it in front of the routes.
+pub struct BasicAuth {
+    user: String,
+    pass: String,
+}
+
+impl BasicAuth {
+    pub fn new(user: &str, pass: &str) -> BasicAuth {
+        BasicAuth {
+            user: user.to_string(),
+            pass: pass.to_string(),
+        }
+    }
+
+    fn is_authorized(&self, credentials: Option<&Authorization<Basic>>) -> bool {
+        match credentials {
+            Some(&Authorization(Basic { ref username, password: Some(ref password) })) => {
+                // evaluate both, so the timing doesn't tell which one was wrong
+                let user_ok = constant_time_eq(username.as_bytes(), self.user.as_bytes());
+                let pass_ok = constant_time_eq(password.as_bytes(), self.pass.as_bytes());
+                user_ok & pass_ok
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<D> Middleware<D> for BasicAuth {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        if req.path_without_query() == Some("/healthz") ||
+           self.is_authorized(req.origin.headers.get::<Authorization<Basic>>()) {
+            return res.next_middleware();
+        }
+        res.headers_mut()
+            .set_raw("WWW-Authenticate", vec![b"Basic realm=\"Documents\"".to_vec()]);
+        res.error(StatusCode::Unauthorized, "Authentication required")
+    }
+}
+
+/// Compares the bytes in a time that only depends on their lengths.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{constant_time_eq, BasicAuth};
+
+    use hyper::header::{Authorization, Basic};
+
+    fn basic(username: &str, password: Option<&str>) -> Authorization<Basic> {
+        Authorization(Basic {
+            username: username.to_string(),
+            password: password.map(|p| p.to_string()),
+        })
+    }
+
+    #[test]
+    fn compare_bytes() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret!"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn credentials() {
+        let auth = BasicAuth::new("docs", "s3cret");
+        assert!(auth.is_authorized(Some(&basic("docs", Some("s3cret")))));
+        assert!(!auth.is_authorized(Some(&basic("docs", Some("wrong")))));
+        assert!(!auth.is_authorized(Some(&basic("admin", Some("s3cret")))));
+        assert!(!auth.is_authorized(Some(&basic("docs", None))));
+        assert!(!auth.is_authorized(None));
+    }
+}
@@ -0,0 +1,111 @@
+// -*- coding:utf-8-unix -*-
+
+//! Serving the whole site under a path prefix (e.g. `/docs`) behind a reverse
+//! proxy. The prefix is removed from the request URI before routing, so the
+//! routes and mounts don't need to know about it, and put back on the
+//! redirects.
+
+use head::Body;
+
+use hyper::header::Location;
+use hyper::uri::RequestUri::AbsolutePath;
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::status::StatusCode;
+
+/// Strips the prefix from the request URIs, and answers `404 Not Found` to the
+/// requests outside of it. `/docs` itself is redirected to `/docs/`. Register
+/// it in front of the routes.
+pub struct BasePath {
+    prefix: String,
+}
+
+impl BasePath {
+    /// `prefix` should be normalized with `normalize`. e.g. "/docs"
+    pub fn new(prefix: &str) -> BasePath {
+        BasePath { prefix: prefix.to_string() }
+    }
+}
+
+impl<D> Middleware<D> for BasePath {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        let stripped = match req.origin.uri {
+            AbsolutePath(ref uri) => strip_prefix(&self.prefix, uri),
+            _ => None,
+        };
+        match stripped {
+            Some(ref uri) if uri.is_empty() || uri.starts_with('?') => {
+                // relative links on the home page need the trailing slash
+                res.set(Location(format!("{}/{}", self.prefix, uri)));
+                res.send((StatusCode::MovedPermanently, ""))
+            }
+            Some(uri) => {
+                req.origin.uri = AbsolutePath(uri);
+                let prefix = self.prefix.clone();
+                res.on_send(move |res| {
+                    let location = res.headers().get::<Location>().map(|loc| loc.0.clone());
+                    if let Some(location) = location {
+                        if location.starts_with('/') {
+                            res.set(Location(format!("{}{}", prefix, location)));
+                        }
+                    }
+                });
+                res.next_middleware()
+            }
+            None => {
+                // not a page of the site, so not the site's 404 page either
+                res.set(StatusCode::NotFound);
+                res.send(Body("Not Found"))
+            }
+        }
+    }
+}
+
+/// Returns the prefix as "/docs": with a leading slash and without a trailing
+/// one. Returns an empty string for no prefix ("" or "/").
+pub fn normalize(prefix: &str) -> String {
+    let trimmed = prefix.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
+/// Returns the URI without the prefix, if it is under the prefix.
+/// e.g. "/docs/1.10/" -> "/1.10/", "/docs" -> "", "/docsx" -> None
+fn strip_prefix(prefix: &str, uri: &str) -> Option<String> {
+    let rest = uri.strip_prefix(prefix)?;
+    if rest.is_empty() || rest.starts_with('/') || rest.starts_with('?') {
+        Some(rest.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, strip_prefix};
+
+    #[test]
+    fn normalize_prefixes() {
+        assert_eq!("/docs", normalize("/docs"));
+        assert_eq!("/docs", normalize("docs/"));
+        assert_eq!("/a/b", normalize(" /a/b/ "));
+        assert_eq!("", normalize("/"));
+        assert_eq!("", normalize(""));
+    }
+
+    #[test]
+    fn strip_prefixes() {
+        assert_eq!(Some("/1.10/".to_string()), strip_prefix("/docs", "/docs/1.10/"));
+        assert_eq!(Some("/".to_string()), strip_prefix("/docs", "/docs/"));
+        assert_eq!(Some("".to_string()), strip_prefix("/docs", "/docs"));
+        assert_eq!(Some("?q=1".to_string()), strip_prefix("/docs", "/docs?q=1"));
+        assert_eq!(None, strip_prefix("/docs", "/docsx/"));
+        assert_eq!(None, strip_prefix("/docs", "/1.10/"));
+    }
+}
@@ -0,0 +1,38 @@
+// -*- coding:utf-8-unix -*-
+
+//! A limit on the request body size, checked before any handler reads it.
+
+use hyper::header::ContentLength;
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::status::StatusCode;
+
+/// 1 MiB. The site only serves GET requests, so this is generous.
+pub const DEFAULT_MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// Answers `413 Payload Too Large` for the requests whose `Content-Length` is
+/// over the limit, without reading the body. Requests without a body pass.
+/// Chunked bodies have no `Content-Length`, so they are not checked.
+pub struct MaxBodySize {
+    max_bytes: u64,
+}
+
+impl MaxBodySize {
+    pub fn new(max_bytes: u64) -> MaxBodySize {
+        MaxBodySize { max_bytes }
+    }
+}
+
+impl<D> Middleware<D> for MaxBodySize {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        match req.origin.headers.get::<ContentLength>() {
+            Some(&ContentLength(len)) if len > self.max_bytes => {
+                res.error(StatusCode::PayloadTooLarge, "Payload Too Large")
+            }
+            _ => res.next_middleware(),
+        }
+    }
+}
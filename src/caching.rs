@@ -0,0 +1,136 @@
+// -*- coding:utf-8-unix -*-
+
+//! Cache validators (`ETag` and `Last-Modified`) and freshness
+//! (`Cache-Control`) for static files.
+
+use hyper::header::{CacheControl, CacheDirective, ETag, EntityTag, HttpDate, IfModifiedSince,
+                    IfNoneMatch, LastModified};
+use hyper::method::Method;
+
+use nickel::{Halt, Middleware, MiddlewareResult, Request, Response};
+use nickel::status::StatusCode;
+
+use static_files;
+
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use time;
+
+/// Sets `ETag` and `Last-Modified` on the static files under the root path, and
+/// answers `304 Not Modified` when the client already has the current version.
+/// Register it in front of the middleware serving the files.
+pub struct CacheValidation {
+    root_path: PathBuf,
+}
+
+impl CacheValidation {
+    pub fn new<P: AsRef<Path>>(root_path: P) -> CacheValidation {
+        CacheValidation { root_path: root_path.as_ref().to_path_buf() }
+    }
+}
+
+impl<D> Middleware<D> for CacheValidation {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        match req.origin.method {
+            Method::Get | Method::Head => {}
+            _ => return res.next_middleware(),
+        }
+        let metadata = match static_files::requested_file(&self.root_path, req)
+            .and_then(|path| fs::metadata(path).ok()) {
+            Some(metadata) => metadata,
+            None => return res.next_middleware(),
+        };
+
+        let etag = entity_tag(&metadata);
+        let mtime = modified_secs(&metadata);
+        let not_modified = is_not_modified(req, &etag, mtime);
+
+        res.set(ETag(etag));
+        if let Some(mtime) = mtime {
+            res.set(LastModified(http_date(mtime)));
+        }
+
+        if not_modified {
+            res.set(StatusCode::NotModified);
+            let stream = res.start()?;
+            Ok(Halt(stream))
+        } else {
+            res.next_middleware()
+        }
+    }
+}
+
+/// Sets `Cache-Control: public, max-age=N` on the static files under the root
+/// path. Register it in front of `CacheValidation` so that `304 Not Modified`
+/// responses carry it too.
+pub struct StaticMaxAge {
+    root_path: PathBuf,
+    max_age: u32,
+}
+
+impl StaticMaxAge {
+    pub fn new<P: AsRef<Path>>(root_path: P, max_age: u32) -> StaticMaxAge {
+        StaticMaxAge {
+            root_path: root_path.as_ref().to_path_buf(),
+            max_age,
+        }
+    }
+}
+
+impl<D> Middleware<D> for StaticMaxAge {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        match req.origin.method {
+            Method::Get | Method::Head => {}
+            _ => return res.next_middleware(),
+        }
+        if static_files::requested_file(&self.root_path, req).is_some() {
+            res.set(CacheControl(vec![CacheDirective::Public,
+                                      CacheDirective::MaxAge(self.max_age)]));
+        }
+        res.next_middleware()
+    }
+}
+
+/// A weak tag made of the file size and mtime. It is weak because the same
+/// file can be sent with or without gzip compression.
+fn entity_tag(metadata: &Metadata) -> EntityTag {
+    EntityTag::weak(format!("{:x}-{:x}", metadata.len(), modified_secs(metadata).unwrap_or(0)))
+}
+
+/// Returns the mtime in seconds since the epoch. HTTP dates have no sub-second
+/// precision.
+fn modified_secs(metadata: &Metadata) -> Option<u64> {
+    metadata.modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+}
+
+fn http_date(secs: u64) -> HttpDate {
+    HttpDate(time::at_utc(time::Timespec::new(secs as i64, 0)))
+}
+
+/// `If-None-Match` takes precedence over `If-Modified-Since` when both are sent.
+fn is_not_modified<D>(req: &Request<D>, etag: &EntityTag, mtime: Option<u64>) -> bool {
+    let headers = &req.origin.headers;
+    if let Some(if_none_match) = headers.get::<IfNoneMatch>() {
+        return match *if_none_match {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Items(ref tags) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        };
+    }
+    match (headers.get::<IfModifiedSince>(), mtime) {
+        (Some(&IfModifiedSince(HttpDate(ref since))), Some(mtime)) => {
+            mtime as i64 <= since.to_timespec().sec
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,335 @@
+// -*- coding:utf-8-unix -*-
+
+//! On-the-fly gzip compression for static files. Files above a size
+//! threshold are compressed while they are sent, so memory use doesn't grow
+//! with the file size. Uncompressed files are always sent by `StaticFiles`.
+//!
+//! Files with a precompressed sibling, like `book.html.br` or `book.html.gz`,
+//! are served from the sibling instead, without compressing anything.
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use hyper::header::{AcceptEncoding, ContentEncoding, ContentType, Encoding, QualityItem};
+use hyper::method::Method;
+use hyper::mime::{Mime, SubLevel, TopLevel};
+
+use head::{self, Body};
+
+use hyper::header::ContentLength;
+
+use nickel::{Action, Middleware, MiddlewareResult, Request, Response};
+
+use static_files;
+
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Serves the static files under the root path gzip compressed when the client
+/// accepts it. Requests for files that are not worth compressing (e.g. images)
+/// are passed on to the next middleware, which should be a `StaticFiles` for
+/// the same root path.
+pub struct GzipStaticFiles {
+    root_path: PathBuf,
+    stream_threshold: u64,
+    level: Compression,
+}
+
+impl GzipStaticFiles {
+    /// Files larger than `stream_threshold` bytes are sent chunked while they
+    /// are compressed, instead of being compressed in memory first. `level` is
+    /// the gzip level, from 1 (fastest) to 9 (smallest).
+    pub fn new<P: AsRef<Path>>(root_path: P, stream_threshold: u64, level: u32) -> GzipStaticFiles {
+        GzipStaticFiles {
+            root_path: root_path.as_ref().to_path_buf(),
+            stream_threshold,
+            level: Compression::new(level),
+        }
+    }
+}
+
+impl<D> Middleware<D> for GzipStaticFiles {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        if req.origin.method != Method::Get || !accepts_gzip(req) {
+            return res.next_middleware();
+        }
+        let path = match static_files::requested_file(&self.root_path, req) {
+            Some(path) => path,
+            None => return res.next_middleware(),
+        };
+        let mime = static_files::media_type(&path);
+        if !is_compressible(&mime) {
+            return res.next_middleware();
+        }
+
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(_) => return res.next_middleware(),
+        };
+        let is_large = fs::metadata(&path).map(|m| m.len() > self.stream_threshold);
+        if let Ok(true) = is_large {
+            set_gzip_headers(&mut res, mime);
+            if head::is_head(&res) {
+                // the length the body would have, without keeping it in memory
+                let mut count = ByteCount(0);
+                if gzip_to(&mut file, &mut count, self.level).is_err() {
+                    return res.next_middleware();
+                }
+                res.set(ContentLength(count.0));
+                return Ok(Action::Halt(res.start()?));
+            }
+            let mut stream = res.start()?;
+            return match gzip_to(&mut file, &mut stream, self.level) {
+                Ok(()) => Ok(Action::Halt(stream)),
+                // the headers are sent already, so all we can do is to cut it short
+                Err(e) => stream.bail(format!("Failed to send file: {}", e)),
+            };
+        }
+
+        let mut body = Vec::new();
+        match gzip_to(&mut file, &mut body, self.level) {
+            Ok(()) => {
+                set_gzip_headers(&mut res, mime);
+                res.send(Body(body))
+            }
+            // let the next middleware try to serve it uncompressed
+            Err(_) => res.next_middleware(),
+        }
+    }
+}
+
+/// Serves the precompressed sibling of the requested file, e.g. `book.html.br`
+/// for `book.html`, when the client accepts its encoding. Brotli is preferred
+/// over gzip when both are accepted equally. Requests without such a sibling
+/// are passed on, so mount it in front of `GzipStaticFiles` for the same root.
+pub struct PrecompressedFiles {
+    root_path: PathBuf,
+}
+
+impl PrecompressedFiles {
+    pub fn new<P: AsRef<Path>>(root_path: P) -> PrecompressedFiles {
+        PrecompressedFiles { root_path: root_path.as_ref().to_path_buf() }
+    }
+}
+
+impl<D> Middleware<D> for PrecompressedFiles {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        if req.origin.method != Method::Get {
+            return res.next_middleware();
+        }
+        let accepted = match req.origin.headers.get::<AcceptEncoding>() {
+            Some(AcceptEncoding(encodings)) => preferred_encodings(encodings),
+            None => return res.next_middleware(),
+        };
+        let path = match static_files::requested_file(&self.root_path, req) {
+            Some(path) => path,
+            None => return res.next_middleware(),
+        };
+        let found = accepted.into_iter()
+            .map(|(encoding, ext)| (encoding, sibling_path(&path, ext)))
+            .find(|(_, sibling)| fs::metadata(sibling).map(|m| m.is_file()).unwrap_or(false));
+        match found {
+            Some((encoding, sibling)) => {
+                // the type of the original file, not of the .br or .gz one
+                res.set(ContentType(static_files::media_type(&path)));
+                res.set(ContentEncoding(vec![encoding]));
+                res.headers_mut().set_raw("Vary", vec![b"Accept-Encoding".to_vec()]);
+                head::send_file(res, &sibling)
+            }
+            None => res.next_middleware(),
+        }
+    }
+}
+
+/// The encodings there can be precompressed files for, with their extensions,
+/// in the order of preference.
+fn precompressed_encodings() -> Vec<(Encoding, &'static str)> {
+    vec![(Encoding::EncodingExt("br".to_string()), "br"), (Encoding::Gzip, "gz")]
+}
+
+/// Returns the precompressed encodings that the client accepts, the highest
+/// quality first.
+fn preferred_encodings(accepted: &[QualityItem<Encoding>]) -> Vec<(Encoding, &'static str)> {
+    let mut encodings: Vec<_> = precompressed_encodings()
+        .into_iter()
+        .filter_map(|(encoding, ext)| {
+            accepted.iter()
+                .find(|e| e.item == encoding)
+                .filter(|e| e.quality.0 > 0)
+                .map(|e| (e.quality, encoding, ext))
+        })
+        .collect();
+    // stable, so the order of preference stays for the same quality
+    encodings.sort_by_key(|&(quality, _, _)| Reverse(quality));
+    encodings.into_iter().map(|(_, encoding, ext)| (encoding, ext)).collect()
+}
+
+/// e.g. "1.10/book.html" -> "1.10/book.html.br"
+fn sibling_path(path: &Path, ext: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(ext);
+    PathBuf::from(name)
+}
+
+fn set_gzip_headers<D>(res: &mut Response<D>, mime: Mime) {
+    res.set(ContentType(mime));
+    res.set(ContentEncoding(vec![Encoding::Gzip]));
+    res.headers_mut().set_raw("Vary", vec![b"Accept-Encoding".to_vec()]);
+}
+
+fn accepts_gzip<D>(req: &Request<D>) -> bool {
+    match req.origin.headers.get::<AcceptEncoding>() {
+        Some(AcceptEncoding(encodings)) => {
+            encodings.iter().any(|e| e.item == Encoding::Gzip && e.quality.0 > 0)
+        }
+        None => false,
+    }
+}
+
+/// Text based types compress well. Others like images and archives are
+/// compressed already.
+fn is_compressible(mime: &Mime) -> bool {
+    match *mime {
+        Mime(TopLevel::Text, _, _) => true,
+        Mime(TopLevel::Application, SubLevel::Javascript, _) |
+        Mime(TopLevel::Application, SubLevel::Json, _) |
+        Mime(TopLevel::Application, SubLevel::Xml, _) => true,
+        Mime(TopLevel::Image, SubLevel::Ext(ref sub), _) => sub == "svg+xml",
+        _ => false,
+    }
+}
+
+/// Counts the bytes written to it, and drops them.
+struct ByteCount(u64);
+
+impl Write for ByteCount {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Compresses everything from the reader into the writer at the level. The
+/// data goes through in small blocks, so it is never all in memory at once.
+fn gzip_to<R: Read, W: Write>(reader: &mut R, writer: &mut W, level: Compression)
+                              -> io::Result<()> {
+    let mut encoder = GzEncoder::new(writer, level);
+    io::copy(reader, &mut encoder)?;
+    encoder.finish().map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gzip_to, is_compressible, preferred_encodings, sibling_path};
+
+    use flate2::Compression;
+    use flate2::read::GzDecoder;
+
+    use hyper::header::{q, qitem, Encoding, QualityItem};
+
+    use std::io::{self, Read, Write};
+    use std::path::{Path, PathBuf};
+
+    /// Remembers the largest single write.
+    struct MaxWrite {
+        total: usize,
+        largest: usize,
+    }
+
+    impl Write for MaxWrite {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.total += buf.len();
+            self.largest = self.largest.max(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn compressible_types() {
+        assert!(is_compressible(&"text/html".parse().unwrap()));
+        assert!(is_compressible(&"text/css".parse().unwrap()));
+        assert!(is_compressible(&"application/javascript".parse().unwrap()));
+        assert!(is_compressible(&"image/svg+xml".parse().unwrap()));
+        assert!(!is_compressible(&"image/png".parse().unwrap()));
+        assert!(!is_compressible(&"application/zip".parse().unwrap()));
+    }
+
+    #[test]
+    fn precompressed_encoding_preference() {
+        let br = Encoding::EncodingExt("br".to_string());
+        let exts = |accepted: &[QualityItem<Encoding>]| {
+            preferred_encodings(accepted).into_iter().map(|(_, ext)| ext).collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["br", "gz"], exts(&[qitem(Encoding::Gzip), qitem(br.clone())]));
+        assert_eq!(vec!["gz", "br"],
+                   exts(&[qitem(Encoding::Gzip), QualityItem::new(br.clone(), q(0.5))]));
+        assert_eq!(vec!["gz"], exts(&[qitem(Encoding::Gzip), QualityItem::new(br, q(0.0))]));
+        assert!(exts(&[qitem(Encoding::Deflate)]).is_empty());
+    }
+
+    #[test]
+    fn sibling_paths() {
+        assert_eq!(PathBuf::from("1.10/book.html.br"),
+                   sibling_path(Path::new("1.10/book.html"), "br"));
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        let text = "The Rust Programming Language\n".repeat(1000);
+        let mut compressed = Vec::new();
+        gzip_to(&mut text.as_bytes(), &mut compressed, Compression::default()).unwrap();
+        assert!(compressed.len() < text.len());
+
+        let mut decompressed = String::new();
+        GzDecoder::new(&compressed[..]).read_to_string(&mut decompressed).unwrap();
+        assert_eq!(text, decompressed);
+    }
+
+    #[test]
+    fn gzip_levels() {
+        let text = "The Rust Programming Language, impl Trait for Vec<T>\n".repeat(1000);
+        let mut sizes = Vec::new();
+        for level in 1..10 {
+            let mut compressed = Vec::new();
+            gzip_to(&mut text.as_bytes(), &mut compressed, Compression::new(level)).unwrap();
+            let mut decompressed = String::new();
+            GzDecoder::new(&compressed[..]).read_to_string(&mut decompressed).unwrap();
+            assert_eq!(text, decompressed, "level {}", level);
+            sizes.push(compressed.len());
+        }
+        assert!(sizes[8] <= sizes[0], "{:?}", sizes);
+    }
+
+    #[test]
+    fn large_input_is_written_in_small_blocks() {
+        // 4 MiB of bytes that hardly compress
+        let mut state = 1u32;
+        let data: Vec<u8> = (0..4 << 20)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let mut output = MaxWrite { total: 0, largest: 0 };
+        gzip_to(&mut &data[..], &mut output, Compression::default()).unwrap();
+        assert!(output.total > 2 << 20, "{}", output.total);
+        assert!(output.largest <= 64 << 10, "{}", output.largest);
+    }
+}
@@ -0,0 +1,108 @@
+// -*- coding:utf-8-unix -*-
+
+//! A cap on the requests handled at a time, `MAX_CONCURRENT`, so that a burst
+//! of slow requests can't take all the server threads. The requests over the
+//! cap get `503 Service Unavailable` with `Retry-After` rather than waiting.
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::status::StatusCode;
+
+use plugin::Extensible;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use typemap::Key;
+
+/// The seconds the clients are asked to wait before trying again.
+const RETRY_AFTER_SECS: &str = "1";
+
+/// A counting semaphore that doesn't block: a permit is either there or not.
+#[derive(Debug)]
+struct Permits {
+    max: usize,
+    in_use: AtomicUsize,
+}
+
+impl Permits {
+    fn new(max: usize) -> Permits {
+        Permits {
+            max,
+            in_use: AtomicUsize::new(0),
+        }
+    }
+
+    /// Takes a permit, or returns false if all `max` are taken.
+    fn try_acquire(&self) -> bool {
+        self.in_use
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                if n < self.max { Some(n + 1) } else { None }
+            })
+            .is_ok()
+    }
+
+    fn release(&self) {
+        self.in_use.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A permit taken by a request, given back when nickel drops the request once
+/// the response is ended. So a streamed body holds it until its last byte.
+struct Permit(Arc<Permits>);
+
+impl Key for Permit {
+    type Value = Permit;
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// Lets up to `max` requests in at a time, each until its response is sent.
+/// `/healthz` is not counted, so the router doesn't take a busy dyno for a
+/// dead one. Register it after `BasePath`, in front of the routes.
+pub struct ConcurrencyLimit {
+    permits: Arc<Permits>,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max: usize) -> ConcurrencyLimit {
+        ConcurrencyLimit { permits: Arc::new(Permits::new(max)) }
+    }
+}
+
+impl<D> Middleware<D> for ConcurrencyLimit {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        if req.path_without_query() == Some("/healthz") {
+            return res.next_middleware();
+        }
+        if !self.permits.try_acquire() {
+            res.headers_mut().set_raw("Retry-After", vec![RETRY_AFTER_SECS.as_bytes().to_vec()]);
+            return res.send((StatusCode::ServiceUnavailable, "Too many requests in flight"));
+        }
+
+        req.extensions_mut().insert::<Permit>(Permit(self.permits.clone()));
+        res.next_middleware()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Permits;
+
+    #[test]
+    fn permits_up_to_the_max() {
+        let permits = Permits::new(2);
+        assert!(permits.try_acquire());
+        assert!(permits.try_acquire());
+        assert!(!permits.try_acquire());
+        permits.release();
+        assert!(permits.try_acquire());
+        assert!(!permits.try_acquire());
+    }
+}
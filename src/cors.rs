@@ -0,0 +1,125 @@
+// -*- coding:utf-8-unix -*-
+
+//! CORS headers for the JSON API under `/api/`, with `CORS_ALLOW_ORIGIN`, so
+//! that pages on other origins can call it. The pages and static files don't
+//! get them.
+
+use hyper::method::Method;
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::status::StatusCode;
+
+/// The API only has GET routes.
+const ALLOW_METHODS: &str = "GET, HEAD, OPTIONS";
+
+/// How long browsers may keep a preflight answer, in seconds.
+const MAX_AGE_SECS: u32 = 600;
+
+/// Sets `Access-Control-Allow-Origin` on the responses of the `/api/` routes,
+/// and answers their `OPTIONS` preflight requests with `204 No Content`.
+/// Register it after `BasePath` and in front of `BasicAuth`, as browsers send
+/// the preflight requests without credentials.
+pub struct Cors {
+    allowed_origins: Vec<String>,
+}
+
+impl Cors {
+    /// `allow_origin` is "*" for any origin, or a comma separated list like
+    /// "https://app.example.com,https://admin.example.com".
+    pub fn new(allow_origin: &str) -> Cors {
+        Cors {
+            allowed_origins: allow_origin.split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Returns the `Access-Control-Allow-Origin` value for the request's
+    /// `Origin`, if it is allowed. A list of origins can't be sent, so a
+    /// matching origin is sent back by itself.
+    fn allow_origin(&self, origin: Option<&str>) -> Option<String> {
+        if self.allowed_origins.iter().any(|allowed| allowed == "*") {
+            return Some("*".to_string());
+        }
+        origin.filter(|origin| self.allowed_origins.iter().any(|allowed| allowed == origin))
+            .map(str::to_string)
+    }
+}
+
+impl<D> Middleware<D> for Cors {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        if !req.path_without_query().map(is_api_path).unwrap_or(false) {
+            return res.next_middleware();
+        }
+        let origin = raw_header(req, "Origin");
+        let allow_origin = self.allow_origin(origin.as_deref());
+        let is_list = !self.allowed_origins.iter().any(|allowed| allowed == "*");
+        let is_preflight = req.origin.method == Method::Options &&
+                           raw_header(req, "Access-Control-Request-Method").is_some();
+
+        if let Some(ref allow_origin) = allow_origin {
+            res.headers_mut()
+                .set_raw("Access-Control-Allow-Origin", vec![allow_origin.as_bytes().to_vec()]);
+        }
+        if is_list {
+            // the answer depends on the origin, so caches must keep them apart
+            res.headers_mut().set_raw("Vary", vec![b"Origin".to_vec()]);
+        }
+        if !is_preflight {
+            return res.next_middleware();
+        }
+
+        if allow_origin.is_some() {
+            let allow_headers = raw_header(req, "Access-Control-Request-Headers")
+                .unwrap_or_else(|| "Accept".to_string());
+            let headers = res.headers_mut();
+            headers.set_raw("Access-Control-Allow-Methods",
+                            vec![ALLOW_METHODS.as_bytes().to_vec()]);
+            headers.set_raw("Access-Control-Allow-Headers", vec![allow_headers.into_bytes()]);
+            headers.set_raw("Access-Control-Max-Age",
+                            vec![MAX_AGE_SECS.to_string().into_bytes()]);
+        }
+        res.send((StatusCode::NoContent, ""))
+    }
+}
+
+/// Returns true for `/api` and the paths under it.
+fn is_api_path(path: &str) -> bool {
+    path == "/api" || path.starts_with("/api/")
+}
+
+fn raw_header<D>(req: &Request<D>, name: &str) -> Option<String> {
+    req.origin.headers.get_raw(name)
+        .and_then(|values| values.first())
+        .and_then(|value| String::from_utf8(value.clone()).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_api_path, Cors};
+
+    #[test]
+    fn api_paths() {
+        assert!(is_api_path("/api/versions"));
+        assert!(is_api_path("/api"));
+        assert!(!is_api_path("/apis"));
+        assert!(!is_api_path("/1.10/api/index.html"));
+    }
+
+    #[test]
+    fn allowed_origins() {
+        let any = Cors::new("*");
+        assert_eq!(Some("*".to_string()), any.allow_origin(Some("https://a.example.com")));
+        assert_eq!(Some("*".to_string()), any.allow_origin(None));
+
+        let list = Cors::new("https://a.example.com, https://b.example.com");
+        assert_eq!(Some("https://b.example.com".to_string()),
+                   list.allow_origin(Some("https://b.example.com")));
+        assert_eq!(None, list.allow_origin(Some("https://evil.example.com")));
+        assert_eq!(None, list.allow_origin(None));
+    }
+}
@@ -0,0 +1,88 @@
+// -*- coding:utf-8-unix -*-
+
+//! The file to serve for the directories under the doc roots, `index.html`
+//! unless `DEFAULT_DOCUMENT` names another one like `README.html`.
+//! `StaticFilesHandler` only serves files, so the directory requests are
+//! rewritten to the file in front of it.
+
+use hyper::uri::RequestUri::AbsolutePath;
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+
+use static_files;
+
+use std::path::{Component, Path, PathBuf};
+
+pub const DEFAULT_DOCUMENT: &str = "index.html";
+
+/// Rewrites the requests for the directories under the doc roots to the
+/// default document in them, e.g. `/1.10/book/` to `/1.10/book/index.html`.
+/// When there is no such file, the request ends up with the 404 page. Register
+/// it after the redirects and in front of the static files.
+pub struct DefaultDocument {
+    root_paths: Vec<PathBuf>,
+    name: String,
+}
+
+impl DefaultDocument {
+    pub fn new<P: AsRef<Path>>(root_paths: &[P], name: &str) -> DefaultDocument {
+        DefaultDocument {
+            root_paths: root_paths.iter().map(|p| p.as_ref().to_path_buf()).collect(),
+            name: name.to_string(),
+        }
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        let relative_path = Path::new(path.trim_matches('/'));
+        static_files::is_safe_path(relative_path) &&
+        self.root_paths.iter().any(|root| root.join(relative_path).is_dir())
+    }
+}
+
+impl<D> Middleware<D> for DefaultDocument {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        let rewritten = match req.origin.uri {
+            AbsolutePath(ref uri) => {
+                let (path, query) = uri.split_at(uri.find('?').unwrap_or(uri.len()));
+                // "/" is the menu
+                if path != "/" && self.is_dir(path) {
+                    Some(format!("{}/{}{}", path.trim_end_matches('/'), self.name, query))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        if let Some(uri) = rewritten {
+            req.origin.uri = AbsolutePath(uri);
+        }
+        res.next_middleware()
+    }
+}
+
+/// Returns the `DEFAULT_DOCUMENT` value if it is a plain file name.
+pub fn parse_default_document(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(name.to_string()),
+        _ => Err(format!("Invalid DEFAULT_DOCUMENT value '{}': expected a file name", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_default_document;
+
+    #[test]
+    fn document_names() {
+        assert_eq!(Ok("README.html".to_string()), parse_default_document(" README.html "));
+        assert!(parse_default_document("").is_err());
+        assert!(parse_default_document("docs/index.html").is_err());
+        assert!(parse_default_document("..").is_err());
+        assert!(parse_default_document("/index.html").is_err());
+    }
+}
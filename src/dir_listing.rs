@@ -0,0 +1,236 @@
+// -*- coding:utf-8-unix -*-
+
+//! A plain HTML listing of the directories under the versions that have no
+//! default document, with `ENABLE_DIR_LISTING=true`, to browse the doc bundles.
+//! Also the file lists of `/api/versions/:version/files`.
+
+use head::Body;
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::mimes::MediaType;
+
+use logger;
+
+use static_files;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use {escape_xml, SharedVersions};
+
+/// Lists the requested directory when it is under a version and has no
+/// default document. Other requests are passed on. Register it after the
+/// redirects and in front of `DefaultDocument`.
+pub struct DirListing {
+    root_paths: Vec<PathBuf>,
+    versions: SharedVersions,
+    default_document: String,
+}
+
+impl DirListing {
+    pub fn new<P: AsRef<Path>>(root_paths: &[P],
+                               versions: SharedVersions,
+                               default_document: &str)
+                               -> DirListing {
+        DirListing {
+            root_paths: root_paths.iter().map(|p| p.as_ref().to_path_buf()).collect(),
+            versions,
+            default_document: default_document.to_string(),
+        }
+    }
+
+    /// Returns the directory to list for the path, if any. It must be inside
+    /// the version dir also after following the symlinks, so a link can't
+    /// expose the files out of the doc roots.
+    fn dir_to_list(&self, path: &str) -> Option<PathBuf> {
+        let relative_path = Path::new(path.trim_matches('/'));
+        let version = relative_path.iter().next()?.to_str()?;
+        if !static_files::is_safe_path(relative_path) ||
+           !self.versions.read().unwrap().iter().any(|ver| ver.dir_name == version) {
+            return None;
+        }
+        let root = self.root_paths.iter().find(|root| root.join(relative_path).is_dir())?;
+        let dir = root.join(relative_path);
+        if dir.join(&self.default_document).is_file() {
+            return None;
+        }
+        let version_dir = fs::canonicalize(root.join(version)).ok()?;
+        match fs::canonicalize(&dir) {
+            Ok(ref real_dir) if real_dir.starts_with(&version_dir) => Some(dir),
+            _ => None,
+        }
+    }
+}
+
+impl<D> Middleware<D> for DirListing {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        let path = match req.path_without_query() {
+            Some(path) if path != "/" => path.to_string(),
+            _ => return res.next_middleware(),
+        };
+        let listing = self.dir_to_list(&path).map(|dir| listing_html(&path, &dir));
+        match listing {
+            Some(Ok(html)) => {
+                res.set(MediaType::Html);
+                res.send(Body(html))
+            }
+            Some(Err(e)) => {
+                logger::warn(&format!("Could not list {}: {}", path, e));
+                res.next_middleware()
+            }
+            None => res.next_middleware(),
+        }
+    }
+}
+
+/// Returns the listing page of the dir for the request path. The links are
+/// relative, so they work under a `BASE_PATH` and with or without the
+/// trailing slash. Hidden files are left out.
+fn listing_html(path: &str, dir: &Path) -> io::Result<String> {
+    let names = dir_names(dir)?;
+
+    // "/1.10/book" needs "book/" in front of the links, "/1.10/book/" doesn't
+    let (base, parent) = if path.ends_with('/') {
+        (String::new(), "../")
+    } else {
+        (format!("{}/", encode_segment(path.rsplit('/').next().unwrap_or(""))), "./")
+    };
+    let title = escape_xml(&format!("Index of {}", path));
+    let mut html = format!("<!DOCTYPE html>\n<html>\n<head><title>{0}</title></head>\n\
+                            <body>\n<h1>{0}</h1>\n<ul>\n<li><a href=\"{1}\">../</a></li>\n",
+                           title,
+                           parent);
+    for name in names {
+        let href = format!("{}{}", base, encode_segment(name.trim_end_matches('/')));
+        let slash = if name.ends_with('/') { "/" } else { "" };
+        html.push_str(&format!("<li><a href=\"{}{}\">{}</a></li>\n",
+                               href,
+                               slash,
+                               escape_xml(&name)));
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    Ok(html)
+}
+
+/// Returns the names in the dir, sorted, with a `/` after the directories.
+/// Hidden files and the names that are not UTF-8 are left out.
+fn dir_names(dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        let is_dir = fs::metadata(entry.path()).map(|m| m.is_dir()).unwrap_or(false);
+        names.push(if is_dir { format!("{}/", name) } else { name });
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Returns the files and the directories in the version dir, as paths relative
+/// to it, e.g. ["book/", "book/index.html", "index.html"]. With `recursive`,
+/// the subdirectories are listed too, but not the symlinked ones, so the list
+/// stays inside the version dir.
+pub fn version_files(version_dir: &Path, recursive: bool) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut pending = vec![String::new()];
+    while let Some(prefix) = pending.pop() {
+        for name in dir_names(&version_dir.join(&prefix))? {
+            let path = format!("{}{}", prefix, name);
+            // without the `/`, which would follow the link
+            let is_link = fs::symlink_metadata(version_dir.join(path.trim_end_matches('/')))
+                .map(|m| m.file_type().is_symlink())
+                .unwrap_or(true);
+            if recursive && path.ends_with('/') && !is_link {
+                pending.push(path.clone());
+            }
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Percent-encodes the file name for a URL path, e.g. "a b.html" ->
+/// "a%20b.html".
+fn encode_segment(name: &str) -> String {
+    let mut encoded = String::new();
+    for b in name.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_segment, listing_html, version_files};
+
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn encoded_segments() {
+        assert_eq!("a%20b.html", encode_segment("a b.html"));
+        assert_eq!("%3Cx%3E%22", encode_segment("<x>\""));
+        assert_eq!("%E3%81%82", encode_segment("あ"));
+    }
+
+    #[test]
+    fn listing_links() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("a b.html"), "").unwrap();
+        fs::write(dir.path().join(".hidden"), "").unwrap();
+
+        let html = listing_html("/1.10/book/", dir.path()).unwrap();
+        assert!(html.contains("<title>Index of /1.10/book/</title>"));
+        assert!(html.contains("<a href=\"../\">../</a>"));
+        assert!(html.contains("<a href=\"a%20b.html\">a b.html</a>"));
+        assert!(html.contains("<a href=\"src/\">src/</a>"));
+        assert!(!html.contains(".hidden"));
+
+        let html = listing_html("/1.10/book", dir.path()).unwrap();
+        assert!(html.contains("<a href=\"./\">../</a>"));
+        assert!(html.contains("<a href=\"book/src/\">src/</a>"));
+    }
+
+    #[test]
+    fn files_of_a_version() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("book/src")).unwrap();
+        fs::write(dir.path().join("index.html"), "").unwrap();
+        fs::write(dir.path().join("book/index.html"), "").unwrap();
+        fs::write(dir.path().join("book/src/a.md"), "").unwrap();
+        fs::write(dir.path().join("book/.hidden"), "").unwrap();
+
+        assert_eq!(vec!["book/", "index.html"], version_files(dir.path(), false).unwrap());
+        assert_eq!(vec!["book/", "book/index.html", "book/src/", "book/src/a.md", "index.html"],
+                   version_files(dir.path(), true).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_dirs_are_not_walked() {
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        fs::write(outside.path().join("secret.txt"), "").unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("out")).unwrap();
+
+        assert_eq!(vec!["out/"], version_files(dir.path(), true).unwrap());
+    }
+}
@@ -0,0 +1,157 @@
+// -*- coding:utf-8-unix -*-
+
+//! The bodies of the error responses. The handlers only raise the errors with
+//! `response.error(status, message)` (or a `(status, body)` tuple), and
+//! `ErrorResponses` writes them in the format the client asks for: JSON like
+//! `{"error":"not_found","path":"/x"}` for clients that prefer
+//! `application/json`, and the 404 page or the message for the others. With
+//! `DISABLE_HTML=true`, every client gets the JSON.
+
+use head;
+
+use hyper::header::Accept;
+
+use nickel::{Action, Continue, ErrorHandler, Halt, Middleware, MiddlewareResult, NickelError,
+             Request, Response};
+use nickel::mimes::MediaType;
+use nickel::status::StatusCode;
+
+use logger;
+
+use rustc_serialize::json::{self, ToJson};
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use {make_not_found_data, prefers_json, render_template, unknown_version, SharedVersions,
+     NOT_FOUND_TEMPLATE, VERSION_NOT_FOUND_TEMPLATE};
+
+/// Register it both with `utilize`, in front of the routes, so the JSON error
+/// responses get the JSON content type, and with `handle_error`, to write the
+/// bodies.
+pub struct ErrorResponses {
+    versions: SharedVersions,
+    base_path: String,
+    json_only: bool,
+}
+
+impl ErrorResponses {
+    /// `json_only` sends the JSON errors whatever the client accepts, and
+    /// never renders the 404 templates.
+    pub fn new(versions: SharedVersions, base_path: &str, json_only: bool) -> ErrorResponses {
+        ErrorResponses {
+            versions,
+            base_path: base_path.to_string(),
+            json_only,
+        }
+    }
+
+    fn wants_json<D>(&self, req: &Request<D>) -> bool {
+        self.json_only || prefers_json(req.origin.headers.get::<Accept>())
+    }
+
+    /// Renders the "not found" page, or the "version not found" page with the
+    /// available versions when the path is under a version that doesn't exist.
+    /// `path` is without the `base_path`, which the pages get as
+    /// `{{base_path}}` for their links. The path comes from the client, so the
+    /// templates must only output it, and the version in it, with the escaping
+    /// `{{path}}`, never with `{{{path}}}`.
+    fn not_found_html(&self, path: &str) -> Vec<u8> {
+        let vers = self.versions.read().unwrap();
+        let mut data = make_not_found_data(&format!("{}{}", self.base_path, path), &vers);
+        data.insert("base_path".to_string(), self.base_path.to_json());
+        let template = match unknown_version(&vers, path) {
+            Some(version) => {
+                data.insert("unknown_version".to_string(), version.to_json());
+                VERSION_NOT_FOUND_TEMPLATE
+            }
+            None => NOT_FOUND_TEMPLATE,
+        };
+        render_template(template, &data).unwrap_or_else(|e| {
+            logger::error(&e);
+            b"Not Found".to_vec()
+        })
+    }
+}
+
+impl<D> Middleware<D> for ErrorResponses {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        if self.wants_json(req) {
+            res.on_send(|res| {
+                if is_error(res.status()) {
+                    res.set(MediaType::Json);
+                }
+            });
+        }
+        res.next_middleware()
+    }
+}
+
+impl<D> ErrorHandler<D> for ErrorResponses {
+    fn handle_error(&self, err: &mut NickelError<D>, req: &mut Request<D>) -> Action {
+        let status = match err.stream {
+            // the headers are sent already, so a HEAD response is complete
+            Some(ref res) if head::is_head(res) => return Halt(()),
+            Some(ref res) => res.status(),
+            None => return Continue(()),
+        };
+        let path = req.path_without_query().unwrap_or("");
+        let body = if self.wants_json(req) {
+            error_json(status, &format!("{}{}", self.base_path, path)).into_bytes()
+        } else if status == StatusCode::NotFound {
+            self.not_found_html(path)
+        } else {
+            err.message.as_bytes().to_vec()
+        };
+        if let Some(ref mut res) = err.stream {
+            // the client may be gone, and there is nobody else to tell
+            let _ = res.write_all(&body);
+        }
+        Halt(())
+    }
+}
+
+fn is_error(status: StatusCode) -> bool {
+    status.to_u16() >= 400
+}
+
+/// Returns the error for the JSON clients. e.g. {"error":"not_found","path":"/x"}
+pub fn error_json(status: StatusCode, path: &str) -> String {
+    let mut map = BTreeMap::new();
+    map.insert("error".to_string(), error_code(status).to_json());
+    map.insert("path".to_string(), path.to_json());
+    json::encode(&map).unwrap()
+}
+
+/// Returns the status as a snake case word. e.g. 404 -> "not_found"
+fn error_code(status: StatusCode) -> String {
+    match status.canonical_reason() {
+        Some(reason) => reason.to_lowercase().replace([' ', '-'], "_"),
+        None => "error".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{error_code, error_json};
+
+    use nickel::status::StatusCode;
+
+    #[test]
+    fn error_codes() {
+        assert_eq!("not_found", error_code(StatusCode::NotFound));
+        assert_eq!("unauthorized", error_code(StatusCode::Unauthorized));
+        assert_eq!("internal_server_error", error_code(StatusCode::InternalServerError));
+        assert_eq!("service_unavailable", error_code(StatusCode::ServiceUnavailable));
+        assert_eq!("error", error_code(StatusCode::Unregistered(499)));
+    }
+
+    #[test]
+    fn json_body() {
+        assert_eq!(r#"{"error":"not_found","path":"/x\"y"}"#,
+                   error_json(StatusCode::NotFound, "/x\"y"));
+    }
+}
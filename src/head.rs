@@ -0,0 +1,110 @@
+// -*- coding:utf-8-unix -*-
+
+//! HEAD requests for every route and the static files, answered like the GET
+//! request for the same path but without the body.
+
+use hyper::header::{ContentLength, ContentType, TransferEncoding};
+use hyper::method::Method;
+
+use nickel::{Halt, Middleware, MiddlewareResult, Request, Responder, Response};
+use nickel::status::StatusCode;
+
+use plugin::Extensible;
+
+use static_files::media_type;
+
+use std::any::Any;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use typemap::Key;
+
+/// Turns HEAD requests into GET requests, so the routes registered with `get`
+/// answer them. Register it in front of the routes.
+///
+/// The bodies are left out where they are sent, by `Body` and `send_file`,
+/// which declare the `Content-Length` of the GET response. The responses
+/// without one, e.g. the redirects and the errors, get `Content-Length: 0`, as
+/// hyper would otherwise end them with the last chunk of a chunked body.
+pub struct HeadRequests;
+
+impl HeadRequests {
+    pub fn new() -> HeadRequests {
+        HeadRequests
+    }
+}
+
+impl<D> Middleware<D> for HeadRequests {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        if req.origin.method == Method::Head {
+            req.origin.method = Method::Get;
+            res.extensions_mut().insert::<Head>(());
+            res.on_send(|res| {
+                if !res.headers().has::<ContentLength>() {
+                    res.headers_mut().remove::<TransferEncoding>();
+                    res.set(ContentLength(0));
+                }
+            });
+        }
+        res.next_middleware()
+    }
+}
+
+/// Marks the responses to HEAD requests.
+struct Head;
+
+impl Key for Head {
+    type Value = ();
+}
+
+/// Returns true if the response is to a HEAD request, so it must not have a
+/// body.
+pub fn is_head<D, T: Any>(res: &Response<D, T>) -> bool {
+    res.extensions().contains::<Head>()
+}
+
+/// A body sent with its `Content-Length`, and left out for HEAD requests. The
+/// content type defaults to HTML, as for the strings nickel sends.
+pub struct Body<T>(pub T);
+
+impl<D, T: AsRef<[u8]>> Responder<D> for Body<T> {
+    fn respond<'a>(self, mut res: Response<'a, D>) -> MiddlewareResult<'a, D> {
+        let body = self.0.as_ref();
+        res.set(ContentLength(body.len() as u64));
+        let mut stream = res.start()?;
+        if is_head(&stream) {
+            return Ok(Halt(stream));
+        }
+        match stream.write_all(body) {
+            Ok(()) => Ok(Halt(stream)),
+            Err(e) => stream.bail(format!("Failed to send: {}", e)),
+        }
+    }
+}
+
+/// Sends the file with its `Content-Length`, or only the headers for a HEAD
+/// request. Unlike nickel's `send_file`, the response isn't chunked.
+pub fn send_file<'a, D>(mut res: Response<'a, D>, path: &Path) -> MiddlewareResult<'a, D> {
+    let opened = File::open(path).and_then(|file| Ok((file.metadata()?.len(), file)));
+    let (len, mut file) = match opened {
+        Ok(opened) => opened,
+        Err(e) => {
+            return res.error(StatusCode::InternalServerError,
+                             format!("Failed to send file '{:?}': {}", path, e))
+        }
+    };
+    res.set_header_fallback(|| ContentType(media_type(path)));
+    res.set(ContentLength(len));
+    let mut stream = res.start()?;
+    if is_head(&stream) {
+        return Ok(Halt(stream));
+    }
+    match io::copy(&mut file, &mut stream) {
+        Ok(_) => Ok(Halt(stream)),
+        Err(e) => stream.bail(format!("Failed to send file: {}", e)),
+    }
+}
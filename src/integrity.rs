@@ -0,0 +1,80 @@
+// -*- coding:utf-8-unix -*-
+
+//! SHA-256 digests of the static files, for `/api/integrity`, so that clients
+//! can check the files they got. The digests are kept until the file changes.
+
+use sha2::{Digest, Sha256};
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// The digests of the files by path, with the modification time they were
+/// computed for.
+#[derive(Default)]
+pub struct DigestCache {
+    digests: Mutex<HashMap<PathBuf, (SystemTime, String)>>,
+}
+
+impl DigestCache {
+    /// Returns the SHA-256 of the file as a lowercase hex string. It is only
+    /// computed again when the file's modification time changes.
+    pub fn sha256_hex(&self, path: &Path) -> io::Result<String> {
+        let modified = fs::metadata(path)?.modified()?;
+        if let Some((time, digest)) = self.digests.lock().unwrap().get(path) {
+            if *time == modified {
+                return Ok(digest.clone());
+            }
+        }
+        // not under the lock, so other files can be served meanwhile
+        let digest = to_hex(&file_sha256(path)?);
+        self.digests.lock().unwrap().insert(path.to_path_buf(), (modified, digest.clone()));
+        Ok(digest)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.digests.lock().unwrap().len()
+    }
+}
+
+fn file_sha256(path: &Path) -> io::Result<[u8; 32]> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        match file.read(&mut buf)? {
+            0 => return Ok(hasher.finalize().into()),
+            n => hasher.update(&buf[..n]),
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DigestCache;
+
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn cached_digests() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("index.html");
+        fs::write(&path, "abc").unwrap();
+        let cache = DigestCache::default();
+        let digest = cache.sha256_hex(&path).unwrap();
+        assert_eq!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad", digest);
+        assert_eq!(digest, cache.sha256_hex(&path).unwrap());
+        assert_eq!(1, cache.len());
+        assert!(cache.sha256_hex(&dir.path().join("missing.html")).is_err());
+    }
+}
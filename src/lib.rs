@@ -0,0 +1,5555 @@
+// -*- coding:utf-8-unix -*-
+
+//! The doc server. `build_server` wires the routes and middleware for the
+//! versions found by `get_versions`; the binary in `main.rs` only reads the
+//! settings and listens.
+
+#![cfg_attr(feature="clippy", feature(plugin))]
+#![cfg_attr(feature="clippy", plugin(clippy))]
+
+// `NickelError` is large, but every handler has to return it as is.
+#![allow(clippy::result_large_err)]
+
+extern crate flate2;
+extern crate hyper;
+
+#[macro_use]
+extern crate lazy_static;
+
+#[macro_use]
+extern crate nickel;
+
+extern crate mustache;
+extern crate plugin;
+extern crate regex;
+extern crate rustc_serialize;
+extern crate sha2;
+extern crate time;
+extern crate typemap;
+
+#[cfg(test)]
+extern crate tempfile;
+
+mod access_log;
+mod aliases;
+mod auth;
+mod base_path;
+mod body_limit;
+mod caching;
+mod compression;
+mod concurrency;
+mod cors;
+mod default_document;
+mod dir_listing;
+mod errors;
+mod head;
+mod integrity;
+mod logger;
+mod maintenance;
+mod metrics;
+mod mime_types;
+mod proxy;
+mod range;
+mod redirects;
+mod request_id;
+mod security;
+mod shutdown;
+mod startup;
+mod static_files;
+mod stats;
+mod timeout;
+mod trailing_slash;
+
+use access_log::AccessLogFile;
+
+use aliases::Aliases;
+
+use auth::BasicAuth;
+
+use base_path::BasePath;
+
+use body_limit::MaxBodySize;
+
+use caching::{CacheValidation, StaticMaxAge};
+
+use compression::{GzipStaticFiles, PrecompressedFiles};
+
+use concurrency::ConcurrencyLimit;
+
+use cors::Cors;
+
+use default_document::DefaultDocument;
+
+use dir_listing::{version_files, DirListing};
+
+use errors::ErrorResponses;
+
+use head::{Body, HeadRequests};
+
+use integrity::DigestCache;
+
+use logger::{LogFormat, RequestLogger};
+
+use maintenance::Maintenance;
+
+use metrics::{Metrics, MetricsRecorder};
+
+use mime_types::MimeTypes;
+
+use proxy::{ForwardedRedirects, HttpsRedirect};
+
+use range::RangeRequests;
+
+use redirects::Redirects;
+
+use request_id::RequestId;
+
+use security::SecurityHeaders;
+
+use shutdown::ShutdownGuard;
+
+use static_files::StaticFiles;
+
+use stats::{HitCounter, VersionHits};
+
+use timeout::{run_with_timeout, timed_out};
+
+use trailing_slash::{TrailingSlash, TrailingSlashRedirect};
+
+pub use logger::{init as init_logger, log, Level};
+
+pub use shutdown::{wait_for_drain, Shutdown};
+
+pub use startup::StartupError;
+
+use nickel::{Nickel, HttpRouter, MiddlewareResult, Mountable, Options, QueryString, Request,
+             Response};
+use hyper::header::{Accept, CacheControl, CacheDirective, Location, Quality};
+use hyper::mime::{Mime, SubLevel, TopLevel};
+use hyper::uri::RequestUri::AbsolutePath;
+
+use nickel::mimes::MediaType;
+use nickel::status::StatusCode;
+
+use regex::Regex;
+
+use rustc_serialize::Encodable;
+use rustc_serialize::json::{self, ToJson};
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::ToSocketAddrs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DOC_ROOT: &str = "public";
+/// The shared CSS and JS for all versions, served under `ASSETS_PATH`.
+const ASSETS_DIR: &str = "assets";
+const ASSETS_PATH: &str = "/assets";
+
+const LISTEN_ADDRESS: &str = "0.0.0.0";
+const DEFAULT_PORT: &str = "6767";
+
+/// The dir names of the named release channels, shown above the numbered
+/// versions in the menu.
+const CHANNELS: &[&str] = &["nightly", "beta"];
+
+const DEFAULT_BIND_RETRIES: u32 = 3;
+/// The wait before the 2nd bind attempt. It grows linearly with the attempts.
+pub const BIND_BACKOFF_MS: u64 = 500;
+
+const DEFAULT_ROBOTS_TXT: &str = "User-agent: *\nDisallow:\n";
+/// One hour. Version directories rarely change once published.
+const DEFAULT_STATIC_MAX_AGE: u32 = 3600;
+/// The largest page of `/api/versions?limit=`.
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// 1 MiB. Smaller files are gzip compressed in memory before they are sent.
+const DEFAULT_STREAM_THRESHOLD: u64 = 1024 * 1024;
+
+/// The gzip level without `GZIP_LEVEL`, zlib's balance of speed and size.
+const DEFAULT_GZIP_LEVEL: u32 = 6;
+
+/// How often `WATCH_DOC_ROOT` rescans the doc root, in seconds.
+const DEFAULT_WATCH_INTERVAL: u64 = 10;
+
+/// Heroku sends SIGKILL 30 seconds after SIGTERM.
+const DEFAULT_DRAIN_TIMEOUT: u32 = 10;
+
+/// Optional file in a version directory with extra fields for the menu.
+const META_FILE: &str = "meta.json";
+/// Optional files in a version directory with its release notes, in the order
+/// they are looked for.
+const NOTES_FILES: &[&str] = &["RELEASE.md", "notes.txt"];
+
+const HOME_TEMPLATE: &str = "assets/home.mustache";
+/// The `VERSION_LABEL_FORMAT` for the labels in the menu, the version as is.
+const DEFAULT_LABEL_FORMAT: &str = "{version}";
+/// Holds a directory per `THEME`, each with its own `home.mustache`.
+const THEMES_DIR: &str = "assets";
+const NOT_FOUND_TEMPLATE: &str = "assets/404.mustache";
+/// The 404 page for the paths under a version that doesn't exist.
+const VERSION_NOT_FOUND_TEMPLATE: &str = "assets/version_404.mustache";
+/// The 503 page for the `MAINTENANCE` mode.
+const MAINTENANCE_TEMPLATE: &str = "assets/maintenance.mustache";
+
+lazy_static! {
+    // NOTE: Dir names can be like 1.10, 1.10.0 or v1.10
+    // anchored at both ends, so e.g. "1.2.3.4" is not read as 1.2.3
+    static ref RE_SEM_VER: Regex = Regex::new(r"^(v?)(\d+)\.(\d+)(?:\.(\d+))?$").unwrap();
+}
+
+/// Calls `bind` up to `attempts` times (at least once) until it succeeds,
+/// sleeping `backoff` times the attempt number between the attempts. Returns
+/// the last error if all attempts failed.
+pub fn retry_bind<T, F>(attempts: u32, backoff: Duration, mut bind: F) -> io::Result<T>
+    where F: FnMut() -> io::Result<T>
+{
+    let mut attempt = 1;
+    loop {
+        match bind() {
+            Ok(bound) => return Ok(bound),
+            Err(e) => {
+                if attempt >= attempts {
+                    return Err(e);
+                }
+                let wait = backoff * attempt;
+                logger::warn(&format!("Failed to bind (attempt {} of {}): {}. Retrying in {}ms.",
+                                      attempt,
+                                      attempts,
+                                      e,
+                                      wait.as_millis()));
+                thread::sleep(wait);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Returns the versions one per line, for the `--list-versions` flag.
+pub fn format_version_list(vers: &[Version]) -> String {
+    vers.iter().map(|ver| format!("{}\n", ver.display)).collect()
+}
+
+/// Returns the effective configuration for the startup log. e.g.
+///
+/// ```text
+/// Doc root: public
+/// Listening on: 0.0.0.0:6767
+/// Versions: 2 (1.10, 1.9)
+/// Templates: assets/home.mustache, assets/404.mustache, assets/version_404.mustache
+/// ```
+pub fn startup_summary(config: &Config, address: &str, port: u16, vers: &[Version]) -> String {
+    let names: Vec<&str> = vers.iter().map(|ver| ver.display.as_str()).collect();
+    format!("Doc root: {}\n\
+             Listening on: {}:{}\n\
+             Versions: {} ({})\n\
+             Templates: {}, {}, {}\n",
+            config.doc_root,
+            address,
+            port,
+            vers.len(),
+            names.join(", "),
+            config.home_template,
+            NOT_FOUND_TEMPLATE,
+            VERSION_NOT_FOUND_TEMPLATE)
+}
+
+/// Returns true if the `QUIET` env var is "1" or "true", to leave out the
+/// startup summary.
+pub fn is_quiet() -> bool {
+    env::var("QUIET").map(|v| v == "1" || v.eq_ignore_ascii_case("true")).unwrap_or(false)
+}
+
+/// Creates the server with all routes and mounts registered, ready to
+/// `listen`. It does not scan the doc root: `versions` is the initial menu,
+/// usually from `get_versions(&config.doc_root)`, and only
+/// `/api/versions/refresh` and the `WATCH_DOC_ROOT` watcher rescan it.
+pub fn build_server(config: &Config, versions: Vec<Version>) -> Nickel {
+    let started = Instant::now();
+    let doc_root = config.doc_root.as_str();
+    let versions: SharedVersions = Arc::new(RwLock::new(versions));
+
+    if let Some(interval) = config.watch_interval {
+        watch_doc_root(doc_root, &config.scan, interval, versions.clone());
+    }
+
+    let mut server = Nickel::new();
+    server.options = Options::default().thread_count(config.server_threads);
+
+    // take the X-Request-Id from the router, or make one, for the log lines and
+    // the response. must be the first middleware.
+    server.utilize(RequestId::new());
+
+    // log every request. must be right after RequestId.
+    match config.access_log_file {
+        Some(ref path) => {
+            let format = config.log_format;
+            let file = Mutex::new(AccessLogFile::new(path, config.access_log_max_bytes));
+            server.utilize(RequestLogger::with_output(move |entry| {
+                let line = entry.format(format);
+                println!("{}", line);
+                file.lock().unwrap().log(&line);
+            }));
+        }
+        None => server.utilize(RequestLogger::new(config.log_format)),
+    }
+
+    // count the responses by status class for /metrics
+    let metrics = Arc::new(Metrics::default());
+    server.utilize(MetricsRecorder::new(metrics.clone()));
+
+    // turn new requests away once a shutdown has started, and count the ones
+    // in flight so the shutdown can wait for them
+    server.utilize(ShutdownGuard::new(config.shutdown.clone()));
+
+    // nosniff, DENY framing and CONTENT_SECURITY_POLICY on every response,
+    // including static files and errors. they are set when a response is sent.
+    server.utilize(SecurityHeaders::new(config.content_security_policy.clone()));
+
+    // answer HEAD requests like GETs, without the body
+    server.utilize(HeadRequests::new());
+
+    // JSON error bodies for the clients that prefer JSON, and the 404 page for
+    // the others
+    let json_only = config.disable_html;
+    server.utilize(ErrorResponses::new(versions.clone(), &config.base_path, json_only));
+    server.handle_error(ErrorResponses::new(versions.clone(), &config.base_path, json_only));
+
+    // "413 Payload Too Large" for bodies over MAX_BODY_BYTES
+    server.utilize(MaxBodySize::new(config.max_body_bytes));
+
+    // "301 Moved Permanently" to https:// for the requests over plain HTTP,
+    // with FORCE_HTTPS
+    if config.force_https {
+        server.utilize(HttpsRedirect::new(config.trust_proxy));
+    }
+
+    // absolute redirects to the URL the client sees, with TRUST_PROXY
+    if config.trust_proxy {
+        server.utilize(ForwardedRedirects::new());
+    }
+
+    // serve everything under BASE_PATH (e.g. /docs), as if it were the root
+    if !config.base_path.is_empty() {
+        server.utilize(BasePath::new(&config.base_path));
+    }
+
+    // "503 Service Unavailable" for everything but /healthz with MAINTENANCE, or
+    // while the MAINTENANCE_FILE exists
+    if config.maintenance || config.maintenance_file.is_some() {
+        server.utilize(Maintenance::new(config.maintenance, config.maintenance_file.as_deref()));
+    }
+
+    // "503 Service Unavailable" with Retry-After for the requests over
+    // MAX_CONCURRENT in flight, but /healthz
+    if let Some(max) = config.max_concurrent {
+        server.utilize(ConcurrencyLimit::new(max));
+    }
+
+    // let the pages on the CORS_ALLOW_ORIGIN origins call /api/*
+    if let Some(ref allow_origin) = config.cors_allow_origin {
+        server.utilize(Cors::new(allow_origin));
+    }
+
+    // ask for the BASIC_AUTH_USER and BASIC_AUTH_PASS, if they are set
+    if let Some((ref user, ref pass)) = config.basic_auth {
+        server.utilize(BasicAuth::new(user, pass));
+    }
+
+    // count the requests under each version for /api/stats
+    let hits = Arc::new(VersionHits::new());
+    server.utilize(HitCounter::new(hits.clone(), versions.clone()));
+
+    // the home (menu) page, or a redirect to DEFAULT_VERSION if it exists.
+    // API clients sending "Accept: application/json" get the version list instead,
+    // and so does everyone with DISABLE_HTML.
+    let cache = versions.clone();
+    let home_template = config.home_template.clone();
+    let default_version = config.default_version.clone();
+    let request_timeout = config.request_timeout;
+    let base_path = config.base_path.clone();
+    let label_format = config.version_label_format.clone();
+    let menu_limit = config.menu_limit;
+    let disable_html = config.disable_html;
+    // compiled once here, so the first visitor doesn't wait for it. the
+    // template is compiled on every request only if this fails, to report why.
+    let compiled_home = if disable_html {
+        None
+    } else {
+        compile_template(&home_template).ok().map(Arc::new)
+    };
+    server.get("/",
+               handler(move |request, mut response| {
+        let versions = cache.read().unwrap();
+        response.headers_mut().set_raw("Vary", vec![b"Accept".to_vec()]);
+        if disable_html || prefers_json(request.origin.headers.get::<Accept>()) {
+            response.set(MediaType::Json);
+            return response.send(Body(make_versions_json(&versions)));
+        }
+        if let Some(ref version) = default_version {
+            if has_version(&versions, version) {
+                response.set(Location(format!("/{}/", version)));
+                return response.send((StatusCode::Found, ""));
+            }
+        }
+        // ?all shows the versions over MENU_LIMIT too
+        let limit = if request.query().get("all").is_some() { None } else { menu_limit };
+        let (shown, has_more) = newest_versions(&versions, limit);
+        let mut menu_data = make_menu_data(&shown, &label_format);
+        menu_data.insert("has_more".to_string(), has_more.to_json());
+        menu_data.insert("base_path".to_string(), base_path.to_json());
+        // the menu changes whenever a version is added, so always revalidate it
+        response.set(CacheControl(vec![CacheDirective::NoCache]));
+        response.set(MediaType::Html);
+        let (template, compiled) = (home_template.clone(), compiled_home.clone());
+        let rendered = run_with_timeout(request_timeout, move || match compiled {
+            Some(compiled) => render_compiled(&compiled, &template, &menu_data),
+            None => render_template(&template, &menu_data),
+        });
+        match rendered {
+            Err(_) => timed_out(response),
+            Ok(Ok(body)) => response.send(Body(body)),
+            Ok(Err(e)) => {
+                logger::error(&e);
+                response.set(StatusCode::InternalServerError);
+                response.send(Body(fallback_menu_html(&versions)))
+            }
+        }
+    }));
+
+    // the version list as YAML, for the YAML based pipelines. registered first,
+    // as "/api/versions" would match it too, with "yaml" as the format.
+    let cache = versions.clone();
+    server.get(Regex::new(r"^/api/versions\.yaml(?:\?.*)?$").unwrap(),
+               middleware! { |_, mut response|
+        response.headers_mut().set_raw("Content-Type", vec![b"application/yaml".to_vec()]);
+        Body(make_versions_yaml(&cache.read().unwrap()))
+    });
+
+    // the version list for scripts and SPAs. e.g. ["1.10","1.9","1.6"]
+    // with ?limit= or ?offset=, a page of it. e.g. /api/versions?limit=2&offset=1 ->
+    // {"limit":2,"offset":1,"total":3,"versions":["1.9","1.6"]}
+    let cache = versions.clone();
+    server.get("/api/versions",
+               middleware! { |request, mut response|
+        let query = request.query();
+        let (limit, offset) = (query.get("limit"), query.get("offset"));
+        if limit.is_none() && offset.is_none() {
+            response.set(MediaType::Json);
+            return response.send(Body(make_versions_json(&cache.read().unwrap())));
+        }
+        match make_versions_page(&cache.read().unwrap(), limit, offset) {
+            Ok(page) => {
+                response.set(MediaType::Json);
+                Body(page)
+            }
+            Err(e) => return response.error(StatusCode::BadRequest, e),
+        }
+    });
+
+    // the versions containing ?q=, for type-ahead. e.g. /api/search?q=1.1
+    // a regex route, as the string routes don't allow dots in the query.
+    let cache = versions.clone();
+    server.get(Regex::new(r"^/api/search(?:\?.*)?$").unwrap(),
+               middleware! { |request, mut response|
+        let query = request.query().get("q").unwrap_or("").to_string();
+        response.set(MediaType::Json);
+        Body(make_versions_json(&search_versions(&cache.read().unwrap(), &query)))
+    });
+
+    // the number of versions for monitoring dashboards. e.g. {"count":3}
+    let cache = versions.clone();
+    server.get("/api/version-count",
+               middleware! { |_, mut response|
+        response.set(MediaType::Json);
+        Body(format!(r#"{{"count":{}}}"#, cache.read().unwrap().len()))
+    });
+
+    // the uptime, the number of versions and the APP_VERSION, for ops dashboards.
+    // e.g. {"app_version":"0.1.0","uptime_secs":3600.5,"versions":3}
+    let cache = versions.clone();
+    let app_version = config.app_version.clone();
+    server.get("/api/info",
+               middleware! { |_, mut response|
+        response.set(MediaType::Json);
+        Body(make_info_json(started.elapsed(), cache.read().unwrap().len(), &app_version))
+    });
+
+    // the requests per version since the start. e.g. {"1.10":3,"1.9":0}
+    let cache = versions.clone();
+    server.get("/api/stats",
+               middleware! { |_, mut response|
+        let displays: Vec<String> =
+            cache.read().unwrap().iter().map(|ver| ver.display.clone()).collect();
+        response.set(MediaType::Json);
+        Body(hits.to_json_string(&displays))
+    });
+
+    // rescans the doc root, so new doc folders show up without a redeploy.
+    // returns the new version list.
+    let cache = versions.clone();
+    let dir = doc_root.to_string();
+    let scan = config.scan.clone();
+    let request_timeout = config.request_timeout;
+    server.get("/api/versions/refresh",
+               middleware! { |_, mut response|
+        let (dir, scan, shared) = (dir.clone(), scan.clone(), cache.clone());
+        match run_with_timeout(request_timeout, move || refresh_versions(&dir, &scan, &shared)) {
+            Ok(Ok(())) => {
+                response.set(MediaType::Json);
+                Body(make_versions_json(&cache.read().unwrap()))
+            }
+            Ok(Err(e)) => {
+                return response.error(StatusCode::InternalServerError,
+                                      format!("An error occured while scanning the doc root \
+                                               directory. Error: {}",
+                                              e))
+            }
+            Err(_) => return timed_out(response),
+        }
+    });
+
+    // whether a typed version is one of the versions, for the forms. e.g.
+    // /api/versions/validate?v=V1.10 -> {"exists":true}
+    // registered before /api/versions/:version, which it would match too.
+    let cache = versions.clone();
+    let keep_v_prefix = config.scan.keep_v_prefix;
+    server.get(Regex::new(r"^/api/versions/validate(?:\?.*)?$").unwrap(),
+               middleware! { |request, mut response|
+        let input = match request.query().get("v") {
+            Some(v) if !v.trim().is_empty() => v.to_string(),
+            _ => return response.error(StatusCode::BadRequest, "The v param is required"),
+        };
+        let exists = is_known_version(&cache.read().unwrap(), &input, keep_v_prefix);
+        response.set(MediaType::Json);
+        Body(format!(r#"{{"exists":{}}}"#, exists))
+    });
+
+    // the details of a version, with the fields of its meta.json. e.g.
+    // /api/versions/1.10 -> {"notes":"...","release_date":"2016-07-07","version":"1.10"}
+    // registered after /api/versions/refresh, which it would match too.
+    let cache = versions.clone();
+    let base_path = config.base_path.clone();
+    server.get(Regex::new(r"^/api/versions/(?P<version>[^/?]+)(?:\?.*)?$").unwrap(),
+               middleware! { |request, mut response|
+        let version = request.param("version").unwrap_or("").to_string();
+        let details = cache.read().unwrap()
+            .iter()
+            .find(|ver| ver.display == version)
+            .map(|ver| json::encode(&version_object(ver)).unwrap());
+        response.set(MediaType::Json);
+        match details {
+            Some(body) => Body(body),
+            None => {
+                let path = format!("{}{}", base_path, request.path_without_query().unwrap_or(""));
+                // sent as is, so the error handler doesn't make it a page
+                response.set(StatusCode::NotFound);
+                return response.send(Body(errors::error_json(StatusCode::NotFound, &path)));
+            }
+        }
+    });
+
+    // the files in a version dir, for a file browser. e.g.
+    // /api/versions/1.10/files -> ["book/","index.html"]
+    // with ?recursive=true, the files in the subdirectories too.
+    let cache = versions.clone();
+    let roots: Vec<PathBuf> = split_doc_roots(doc_root).into_iter().map(PathBuf::from).collect();
+    let base_path = config.base_path.clone();
+    server.get(Regex::new(r"^/api/versions/(?P<version>[^/?]+)/files(?:\?.*)?$").unwrap(),
+               middleware! { |request, mut response|
+        let version = request.param("version").unwrap_or("").to_string();
+        let recursive = request.query()
+            .get("recursive")
+            .is_some_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+        // only the known versions, so the param can't name another dir
+        let version_dir = cache.read().unwrap()
+            .iter()
+            .find(|ver| ver.display == version)
+            .and_then(|ver| {
+                roots.iter().map(|root| root.join(&ver.dir_name)).find(|dir| dir.is_dir())
+            });
+        response.set(MediaType::Json);
+        match version_dir.map(|dir| version_files(&dir, recursive)) {
+            Some(Ok(files)) => Body(json::encode(&files).unwrap()),
+            Some(Err(e)) => {
+                logger::error(&format!("Could not list the files of {}: {}", version, e));
+                return response.error(StatusCode::InternalServerError,
+                                      "Could not list the files");
+            }
+            None => {
+                let path = format!("{}{}", base_path, request.path_without_query().unwrap_or(""));
+                // sent as is, so the error handler doesn't make it a page
+                response.set(StatusCode::NotFound);
+                return response.send(Body(errors::error_json(StatusCode::NotFound, &path)));
+            }
+        }
+    });
+
+    // the SHA-256 of a static file, for integrity checks. e.g.
+    // /api/integrity?path=/1.10/index.html -> {"path":"/1.10/index.html","sha256":"..."}
+    // a regex route, as the string routes don't allow dots in the query.
+    let digests = Arc::new(DigestCache::default());
+    let roots: Vec<PathBuf> = split_doc_roots(doc_root).into_iter().map(PathBuf::from).collect();
+    let base_path = config.base_path.clone();
+    server.get(Regex::new(r"^/api/integrity(?:\?.*)?$").unwrap(),
+               middleware! { |request, mut response|
+        let path = request.query().get("path").unwrap_or("").to_string();
+        response.set(MediaType::Json);
+        match make_integrity_json(&roots, &digests, &path) {
+            Ok(body) => Body(body),
+            Err(status) => {
+                let path = format!("{}{}", base_path, request.path_without_query().unwrap_or(""));
+                // sent as is, so the error handler doesn't make it a page
+                response.set(status);
+                return response.send(Body(errors::error_json(status, &path)));
+            }
+        }
+    });
+
+    // crawler rules. from the ROBOTS_TXT env var, robots.txt in the doc root, or
+    // the default that allows everything.
+    let robots = config.robots_txt.clone();
+    let dir = doc_root.to_string();
+    server.get("/robots.txt",
+               middleware! { |_, mut response|
+        response.set(MediaType::Txt);
+        Body(robots_txt(robots.as_deref(), &dir))
+    });
+
+    // the index page of every version, for search engines
+    let cache = versions.clone();
+    let base_path = config.base_path.clone();
+    let trust_proxy = config.trust_proxy;
+    server.get("/sitemap.xml",
+               middleware! { |request, mut response|
+        let base_url = match proxy::site_url(&request.origin.headers, trust_proxy) {
+            Some(site_url) => site_url + &base_path,
+            None => return response.error(StatusCode::BadRequest, "Missing Host header"),
+        };
+        response.set(MediaType::Xml);
+        Body(make_sitemap(&base_url, &cache.read().unwrap()))
+    });
+
+    // the counters for Prometheus
+    let cache = versions.clone();
+    server.get("/metrics",
+               middleware! { |_, mut response|
+        response.headers_mut()
+            .set_raw("Content-Type", vec![b"text/plain; version=0.0.4".to_vec()]);
+        Body(metrics.render(cache.read().unwrap().len()))
+    });
+
+    // liveness probe for Heroku and load balancers. must not touch the file system.
+    // registered before the static files mount so a file named "healthz" can't shadow it.
+    server.get("/healthz",
+               middleware! { |_, mut response|
+        response.set(MediaType::Json);
+        Body(r#"{"status":"ok"}"#)
+    });
+
+    // redirect to the newest version, keeping the rest of the path.
+    // e.g. /latest/book/index.html -> /1.10/book/index.html
+    let cache = versions.clone();
+    server.get(Regex::new(r"^/latest(?P<path>/[^?]*)?(?:\?.*)?$").unwrap(),
+               handler(move |request, mut response| {
+        let versions = cache.read().unwrap();
+        match latest_path(&versions, request.param("path")) {
+            Some(path) => {
+                response.set(Location(path));
+                response.send((StatusCode::Found, ""))
+            }
+            None => response.error(StatusCode::NotFound, "Not Found"),
+        }
+    }));
+
+    // redirect a major number to the newest version with it. e.g. /1 -> /1.10/
+    let cache = versions.clone();
+    server.get(Regex::new(r"^/(?P<major>\d+)/?(?:\?.*)?$").unwrap(),
+               handler(move |request, mut response| {
+        let majors = newest_by_major(&cache.read().unwrap());
+        let newest = request.param("major")
+            .and_then(|major| major.parse::<u32>().ok())
+            .and_then(|major| majors.get(&major));
+        match newest {
+            Some(display) => {
+                response.set(Location(format!("/{}/", display)));
+                response.send((StatusCode::Found, ""))
+            }
+            None => response.error(StatusCode::NotFound, "Not Found"),
+        }
+    }));
+
+    // redirect the ALIASES short URLs to their pages. e.g. /guide ->
+    // /1.10/guide/index.html
+    for alias in shadowing_aliases(&config.aliases, &versions.read().unwrap()) {
+        logger::warn(&format!("Skipping the alias {} as it would hide the version {}.",
+                              alias,
+                              aliases::first_segment(alias)));
+    }
+    server.utilize(Aliases::new(&config.aliases, versions.clone()));
+
+    // redirect the moved pages in the REDIRECTS file to their new paths
+    if !config.redirects.is_empty() {
+        server.utilize(Redirects::new(&config.redirects));
+    }
+
+    // redirect the directories to the TRAILING_SLASH form, e.g. /1.10/book to
+    // /1.10/book/
+    let trailing_slash = config.trailing_slash;
+    server.utilize(TrailingSlashRedirect::new(&split_doc_roots(doc_root), trailing_slash));
+
+    // version directories: redirect /1.10 to /1.10/ (or the other way around,
+    // by TRAILING_SLASH). TrailingSlashRedirect misses the ones whose dir names
+    // were normalized. other paths are left to the following middleware.
+    let cache = versions.clone();
+    server.get(Regex::new(r"^/(?P<version>[^/?]+)(?P<slash>/)?(?:\?.*)?$").unwrap(),
+               handler(move |request, mut response| {
+        let version = request.param("version").unwrap_or("").to_string();
+        if !has_version(&cache.read().unwrap(), &version) {
+            return response.next_middleware();
+        }
+        let path = format!("/{}{}", version, request.param("slash").unwrap_or(""));
+        if let Some(target) = trailing_slash.redirect_target(&path, true) {
+            response.set(Location(target));
+            return response.send((StatusCode::MovedPermanently, ""));
+        }
+        response.next_middleware()
+    }));
+
+    // browsers ask for it on every page. when there is none, answer "204 No
+    // Content" rather than the 404 page.
+    let favicon = match config.favicon {
+        Some(ref path) => PathBuf::from(path),
+        None => Path::new(split_doc_roots(doc_root).first().cloned().unwrap_or(""))
+            .join("favicon.ico"),
+    };
+    server.get("/favicon.ico",
+               handler(move |_, response| {
+        if favicon.is_file() {
+            head::send_file(response, &favicon)
+        } else {
+            response.send(StatusCode::NoContent)
+        }
+    }));
+
+    // serve the versions whose dir names were normalized (e.g. "1.10 ") from
+    // their dirs on disk
+    let cache = versions.clone();
+    server.utilize(handler(move |request, response| {
+        if let AbsolutePath(ref uri) = request.origin.uri {
+            if let Some(dir_uri) = to_dir_uri(&cache.read().unwrap(), uri) {
+                request.origin.uri = AbsolutePath(dir_uri);
+            }
+        }
+        response.next_middleware()
+    }));
+
+    // list the directories under the versions that have no DEFAULT_DOCUMENT,
+    // with ENABLE_DIR_LISTING
+    if config.dir_listing {
+        server.utilize(DirListing::new(&split_doc_roots(doc_root),
+                                       versions.clone(),
+                                       &config.default_document));
+    }
+
+    // serve the directories with their DEFAULT_DOCUMENT, which StaticFiles
+    // doesn't do. e.g. /1.10/ -> /1.10/index.html
+    server.utilize(DefaultDocument::new(&split_doc_roots(doc_root), &config.default_document));
+
+    // content types for .wasm, .mjs and the MIME_TYPES extensions
+    server.utilize(MimeTypes::new(config.mime_types.clone()));
+
+    // the static files. with multiple doc roots, each one is searched in order.
+    for root in split_doc_roots(doc_root) {
+        // let clients and CDNs keep static files for STATIC_MAX_AGE seconds.
+        // mounted first so "304 Not Modified" responses carry it too.
+        server.mount("/", StaticMaxAge::new(root, config.static_max_age));
+
+        // set cache validators on static files, and answer "304 Not Modified" when
+        // the client has the current version
+        server.mount("/", CacheValidation::new(root));
+
+        // answer the Range requests with "206 Partial Content", for resumable
+        // downloads. before the compression, as the ranges are of the file as is.
+        server.mount("/", RangeRequests::new(root));
+
+        // serve the precompressed .br or .gz sibling of a file when there is one
+        // and the client accepts it
+        server.mount("/", PrecompressedFiles::new(root));
+
+        // serve text files under the doc root gzip compressed when the client
+        // accepts it. files above STREAM_THRESHOLD are compressed as they are sent.
+        server.mount("/", GzipStaticFiles::new(root, config.stream_threshold, config.gzip_level));
+
+        // set the doc root folder ("public" by default) as the document root
+        server.mount("/", StaticFiles::new(root));
+    }
+
+    // the shared assets, e.g. /assets/site.css -> assets/site.css. after the doc
+    // roots, so they keep their files, and not over a version.
+    let assets_path = &config.assets_path;
+    match assets_version(assets_path, &versions.read().unwrap()) {
+        Some(version) => {
+            logger::warn(&format!("Not serving {} under ASSETS_PATH {} as it is the path of \
+                                   the version {}.",
+                                  config.assets_dir,
+                                  assets_path,
+                                  version))
+        }
+        None => {
+            server.mount(format!("{}/", assets_path),
+                         StaticFiles::new(&config.assets_dir))
+        }
+    }
+
+    // unknown API routes are "not found" in JSON, whatever the client accepts.
+    // e.g. {"error":"not_found","path":"/api/missing"}
+    let base_path = config.base_path.clone();
+    server.mount("/api/",
+                 handler(move |request, mut response| {
+        // the mount took "/api" off the path
+        let path = format!("{}/api{}", base_path, request.path_without_query().unwrap_or(""));
+        response.set(MediaType::Json);
+        // sent as is, so the error handler doesn't make it a page
+        response.set(StatusCode::NotFound);
+        response.send(Body(errors::error_json(StatusCode::NotFound, &path)))
+    }));
+
+    // if there is no matching page in the previous mount, return "not found" page.
+    server.mount("/",
+                 handler(move |_, response| response.error(StatusCode::NotFound, "Not Found")));
+
+    server
+}
+
+/// Pins down the signature of a handler closure so that it can return a
+/// `MiddlewareResult` (e.g. from `response.render()`) directly. `middleware!`
+/// would need an early `return` for that, which no longer type-checks cleanly
+/// on recent compilers.
+fn handler<F>(f: F) -> F
+    where F: for<'r, 'mw, 'conn> Fn(&'r mut Request<'mw, 'conn>, Response<'mw>)
+                                    -> MiddlewareResult<'mw> + Send + Sync
+{
+    f
+}
+
+
+// NOTE: &str.to_string() vs &str.to_owned()
+//
+// In Rust 1.9 or newer, both methods should yeild the same performance,
+// therefore to_string() will be more preferable than to_owned() for clarity.
+// In older releases, you should replace these &str.to_string() calls with
+// &str.to_owned() for better performance.
+
+
+/// The server settings.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// One or more doc root dirs separated by commas. e.g. "public,archive"
+    /// When they have the same version or file, the first one wins.
+    pub doc_root: String,
+    pub home_template: String,
+    /// The `VERSION_LABEL_FORMAT` env var for the version labels in the
+    /// menu. e.g. "Version {version}"
+    pub version_label_format: String,
+    pub log_format: LogFormat,
+    /// The `ACCESS_LOG_FILE` env var. The request log lines also go to this
+    /// file, which is rotated when it grows over `access_log_max_bytes`.
+    pub access_log_file: Option<String>,
+    pub access_log_max_bytes: u64,
+    /// The `ROBOTS_TXT` env var. Takes priority over `robots.txt` in the doc root.
+    pub robots_txt: Option<String>,
+    /// The `max-age` in seconds for the static files under the doc root.
+    pub static_max_age: u32,
+    /// Static files larger than this many bytes are gzip compressed while they
+    /// are sent, rather than in memory first.
+    pub stream_threshold: u64,
+    /// The `GZIP_LEVEL` env var. The level of the on-the-fly gzip compression,
+    /// from 1 (fastest) to 9 (smallest).
+    pub gzip_level: u32,
+    /// The content types by file extension (e.g. "wasm") that win over the
+    /// ones nickel picks. The defaults plus the `MIME_TYPES` env var.
+    pub mime_types: HashMap<String, Mime>,
+    /// The (alias, target) pairs from the `ALIASES` env var. e.g.
+    /// ("/guide", "/1.10/guide/index.html")
+    pub aliases: Vec<(String, String)>,
+    /// The (old, new) paths from the `REDIRECTS` file.
+    pub redirects: Vec<(String, String)>,
+    /// Whether the directory URLs end with a slash, from the `TRAILING_SLASH`
+    /// env var. The other form is redirected.
+    pub trailing_slash: TrailingSlash,
+    /// The file served for the directories, from the `DEFAULT_DOCUMENT` env
+    /// var. e.g. "README.html"
+    pub default_document: String,
+    /// The `ENABLE_DIR_LISTING` env var. When true, the directories under the
+    /// versions without a default document are listed rather than not found.
+    pub dir_listing: bool,
+    /// The `DEFAULT_VERSION` env var. When set, `/` redirects to this version
+    /// instead of showing the menu.
+    pub default_version: Option<String>,
+    /// The `CONTENT_SECURITY_POLICY` env var, sent as is on every response.
+    pub content_security_policy: Option<String>,
+    /// How long to wait for the requests in flight on SIGTERM, in seconds.
+    pub drain_timeout: u32,
+    /// The `FAVICON` env var. The favicon file to serve instead of
+    /// `favicon.ico` in the doc root.
+    pub favicon: Option<String>,
+    /// The user name and password from the `BASIC_AUTH_USER` and
+    /// `BASIC_AUTH_PASS` env vars. The site is public when they are not set.
+    pub basic_auth: Option<(String, String)>,
+    /// The time limit for rendering the menu and rescanning the doc root, from
+    /// the `REQUEST_TIMEOUT_MS` env var. Static files have no limit.
+    pub request_timeout: Option<Duration>,
+    /// The `MAX_BODY_BYTES` env var. Requests with a larger `Content-Length`
+    /// are rejected.
+    pub max_body_bytes: u64,
+    /// How often to rescan the doc root for added or removed versions. Set by
+    /// `WATCH_DOC_ROOT` and `WATCH_INTERVAL_SECS`. No rescans when `None`.
+    pub watch_interval: Option<Duration>,
+    /// How the versions are found in the doc root and ordered. The
+    /// `VERSION_SORT`, `KEEP_V_PREFIX`, `FOLLOW_SYMLINKS`, `MIN_VERSION` and
+    /// `VERSION_EXCLUDE` env vars.
+    pub scan: ScanOptions,
+    /// The `BASE_PATH` env var, normalized to e.g. "/docs". Everything is
+    /// served under it. Empty for the root.
+    pub base_path: String,
+    /// The `TRUST_PROXY` env var. When true, the `X-Forwarded-Proto` and
+    /// `X-Forwarded-Host` headers are used for the absolute URLs.
+    pub trust_proxy: bool,
+    /// The `FORCE_HTTPS` env var. When true, the requests the proxy got over
+    /// plain HTTP are redirected to HTTPS.
+    pub force_https: bool,
+    /// The `CORS_ALLOW_ORIGIN` env var, "*" or a comma separated list of
+    /// origins. No CORS headers when `None`.
+    pub cors_allow_origin: Option<String>,
+    /// The `MAINTENANCE` env var. When true, every page but `/healthz` is the
+    /// maintenance page.
+    pub maintenance: bool,
+    /// The `MAINTENANCE_FILE` env var. The maintenance mode is on while the
+    /// file exists.
+    pub maintenance_file: Option<String>,
+    /// Shared with the signal handler in `main`, which starts the shutdown.
+    pub shutdown: Arc<Shutdown>,
+    /// The `SERVER_THREADS` env var. The number of threads handling the
+    /// requests, or hyper's default of 1.25 per core when `None`.
+    pub server_threads: Option<usize>,
+    /// The `DISABLE_HTML` env var. When true, `/` is the JSON version list, the
+    /// errors are JSON, and the menu and 404 templates are not needed.
+    pub disable_html: bool,
+    /// The `MENU_LIMIT` env var. The menu shows only this many of the newest
+    /// versions, with a link to all of them. All are shown when `None`.
+    pub menu_limit: Option<usize>,
+    /// The `MAX_CONCURRENT` env var. The requests over this many in flight are
+    /// turned away with `503`. No limit when `None`.
+    pub max_concurrent: Option<usize>,
+    /// The `APP_VERSION` env var, e.g. a release or commit, for `/api/info`.
+    /// The crate version when it is not set.
+    pub app_version: String,
+    /// The `ASSETS_DIR` env var. The shared assets outside the versions.
+    pub assets_dir: String,
+    /// The `ASSETS_PATH` env var, normalized to e.g. "/assets". `assets_dir` is
+    /// served under it.
+    pub assets_path: String,
+}
+
+impl Config {
+    /// Returns the default settings for the doc root.
+    pub fn new(doc_root: &str) -> Config {
+        Config {
+            doc_root: doc_root.to_string(),
+            home_template: HOME_TEMPLATE.to_string(),
+            version_label_format: DEFAULT_LABEL_FORMAT.to_string(),
+            log_format: LogFormat::Plain,
+            access_log_file: None,
+            access_log_max_bytes: access_log::DEFAULT_MAX_BYTES,
+            robots_txt: None,
+            static_max_age: DEFAULT_STATIC_MAX_AGE,
+            stream_threshold: DEFAULT_STREAM_THRESHOLD,
+            gzip_level: DEFAULT_GZIP_LEVEL,
+            mime_types: mime_types::parse_mime_types("").unwrap(),
+            aliases: Vec::new(),
+            redirects: Vec::new(),
+            trailing_slash: TrailingSlash::Add,
+            default_document: default_document::DEFAULT_DOCUMENT.to_string(),
+            dir_listing: false,
+            default_version: None,
+            content_security_policy: None,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            favicon: None,
+            basic_auth: None,
+            request_timeout: None,
+            max_body_bytes: body_limit::DEFAULT_MAX_BODY_BYTES,
+            watch_interval: None,
+            scan: ScanOptions::default(),
+            base_path: String::new(),
+            trust_proxy: false,
+            force_https: false,
+            cors_allow_origin: None,
+            maintenance: false,
+            maintenance_file: None,
+            shutdown: Arc::new(Shutdown::default()),
+            server_threads: None,
+            disable_html: false,
+            menu_limit: None,
+            max_concurrent: None,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            assets_dir: ASSETS_DIR.to_string(),
+            assets_path: ASSETS_PATH.to_string(),
+        }
+    }
+
+    /// Returns the settings from the env vars, falling back to the defaults.
+    /// Returns an error for env vars with invalid values.
+    pub fn from_env() -> Result<Config, String> {
+        Ok(Config {
+            home_template: resolve_home_template()?,
+            version_label_format: env::var("VERSION_LABEL_FORMAT")
+                .unwrap_or_else(|_| DEFAULT_LABEL_FORMAT.to_string()),
+            log_format: get_log_format(),
+            access_log_file: env::var("ACCESS_LOG_FILE").ok().filter(|v| !v.is_empty()),
+            access_log_max_bytes: get_access_log_max_bytes()?,
+            robots_txt: env::var("ROBOTS_TXT").ok(),
+            static_max_age: get_static_max_age()?,
+            stream_threshold: get_stream_threshold()?,
+            gzip_level: parse_gzip_level(env::var("GZIP_LEVEL").ok().as_deref()),
+            mime_types: mime_types::parse_mime_types(&env::var("MIME_TYPES").unwrap_or_default())?,
+            aliases: aliases::parse_aliases(&env::var("ALIASES").unwrap_or_default())?,
+            redirects: get_redirects()?,
+            trailing_slash: get_trailing_slash()?,
+            default_document: match env::var("DEFAULT_DOCUMENT") {
+                Ok(name) => default_document::parse_default_document(&name)?,
+                Err(_) => default_document::DEFAULT_DOCUMENT.to_string(),
+            },
+            dir_listing: env::var("ENABLE_DIR_LISTING")
+                .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+            default_version: env::var("DEFAULT_VERSION").ok().filter(|v| !v.is_empty()),
+            content_security_policy: env::var("CONTENT_SECURITY_POLICY")
+                .ok()
+                .filter(|v| !v.is_empty()),
+            drain_timeout: get_drain_timeout()?,
+            favicon: env::var("FAVICON").ok().filter(|v| !v.is_empty()),
+            basic_auth: get_basic_auth()?,
+            request_timeout: get_request_timeout()?,
+            max_body_bytes: get_max_body_bytes()?,
+            watch_interval: get_watch_interval()?,
+            scan: ScanOptions::from_env(),
+            base_path: base_path::normalize(&env::var("BASE_PATH").unwrap_or_default()),
+            trust_proxy: env::var("TRUST_PROXY")
+                .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+            force_https: env::var("FORCE_HTTPS")
+                .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+            cors_allow_origin: env::var("CORS_ALLOW_ORIGIN").ok().filter(|v| !v.is_empty()),
+            maintenance: env::var("MAINTENANCE")
+                .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+            maintenance_file: env::var("MAINTENANCE_FILE").ok().filter(|v| !v.is_empty()),
+            server_threads: parse_server_threads(env::var("SERVER_THREADS").ok().as_deref()),
+            disable_html: env::var("DISABLE_HTML")
+                .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+            menu_limit: get_menu_limit()?,
+            max_concurrent: get_max_concurrent()?,
+            app_version: env::var("APP_VERSION")
+                .ok()
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| env!("CARGO_PKG_VERSION").to_string()),
+            assets_dir: env::var("ASSETS_DIR")
+                .ok()
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| ASSETS_DIR.to_string()),
+            assets_path: get_assets_path()?,
+            ..Config::new(&resolve_doc_root())
+        })
+    }
+}
+
+/// Reads the file named by the `REDIRECTS` env var. No redirects when it is
+/// not set, but a file that can't be read is an error.
+fn get_redirects() -> Result<Vec<(String, String)>, String> {
+    match env::var("REDIRECTS") {
+        Ok(ref path) if !path.is_empty() => {
+            redirects::load_redirects(path)
+                .map_err(|e| format!("Could not read the REDIRECTS file '{}': {}", path, e))
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Returns the `ASSETS_PATH` env var normalized, or "/assets" when it is not
+/// set. It can't be `/`, which would take the paths of the versions.
+fn get_assets_path() -> Result<String, String> {
+    match env::var("ASSETS_PATH") {
+        Ok(path) => {
+            let normalized = base_path::normalize(&path);
+            if normalized.is_empty() {
+                Err(format!("Invalid ASSETS_PATH value '{}': it can't be the root", path))
+            } else {
+                Ok(normalized)
+            }
+        }
+        Err(_) => Ok(ASSETS_PATH.to_string()),
+    }
+}
+
+/// Returns the `DOC_ROOT` env var, or "public" when it is not set.
+pub fn resolve_doc_root() -> String {
+    env::var("DOC_ROOT").unwrap_or_else(|_| DOC_ROOT.to_string())
+}
+
+/// Returns the `HOME_TEMPLATE` env var, or the home template of the `THEME`
+/// env var, or "assets/home.mustache" when neither is set.
+fn resolve_home_template() -> Result<String, String> {
+    if let Ok(path) = env::var("HOME_TEMPLATE") {
+        return Ok(path);
+    }
+    match env::var("THEME") {
+        Ok(ref theme) if !theme.is_empty() => theme_template(Path::new(THEMES_DIR), theme),
+        _ => Ok(HOME_TEMPLATE.to_string()),
+    }
+}
+
+/// Returns the home template of the theme. e.g. "dark" ->
+/// "assets/dark/home.mustache". It is an error if the theme has none.
+fn theme_template(themes_dir: &Path, theme: &str) -> Result<String, String> {
+    let path = themes_dir.join(theme).join("home.mustache");
+    if theme.contains(['/', '\\']) || theme.starts_with('.') || !path.is_file() {
+        return Err(format!("Invalid THEME value '{}': {} does not exist", theme, path.display()));
+    }
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Checks that the template file exists and compiles, so that a broken template
+/// is reported at startup rather than on the first request.
+pub fn check_template(path: &str) -> Result<(), String> {
+    compile_template(path).map(|_| ())
+}
+
+/// Checks that each of the doc root dirs exists.
+pub fn check_doc_roots(doc_root: &str) -> Result<(), String> {
+    match split_doc_roots(doc_root).into_iter().find(|dir| !Path::new(dir).is_dir()) {
+        Some(dir) => Err(format!("The doc root directory does not exist. Dir: {}", dir)),
+        None => Ok(()),
+    }
+}
+
+/// Checks that the listen address is an IP address or a host name that
+/// resolves.
+pub fn check_listen_address(address: &str) -> Result<(), String> {
+    match (address, 0).to_socket_addrs() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Invalid LISTEN_ADDRESS value '{}': {}", address, e)),
+    }
+}
+
+/// Runs the startup checks for the settings, and returns all the problems
+/// found rather than stopping at the first. For the `--check` flag, so that a
+/// broken deploy fails in CI before it serves traffic.
+pub fn check_setup(config: &Config, address: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Err(e) = check_listen_address(address) {
+        problems.push(e);
+    }
+    match check_doc_roots(&config.doc_root) {
+        Err(e) => problems.push(e),
+        Ok(()) => {
+            if let Err(e) = get_versions(&config.doc_root, &config.scan) {
+                problems.push(format!("An error occured while scanning the doc root \
+                                       directory. Error: {}, Dir: {}",
+                                      e,
+                                      config.doc_root));
+            }
+        }
+    }
+    let mut templates = if config.disable_html {
+        Vec::new()
+    } else {
+        vec![config.home_template.as_str(), NOT_FOUND_TEMPLATE, VERSION_NOT_FOUND_TEMPLATE]
+    };
+    if config.maintenance || config.maintenance_file.is_some() {
+        templates.push(MAINTENANCE_TEMPLATE);
+    }
+    for template in templates {
+        if let Err(e) = check_template(template) {
+            problems.push(e);
+        }
+    }
+    problems
+}
+
+fn compile_template(path: &str) -> Result<mustache::Template, String> {
+    if !Path::new(path).is_file() {
+        return Err(format!("The template file does not exist. File: {}", path));
+    }
+    // the mustache parser panics on some syntax errors
+    match panic::catch_unwind(|| mustache::compile_path(path)) {
+        Ok(Ok(template)) => Ok(template),
+        Ok(Err(e)) => {
+            Err(format!("Failed to compile the template. Error: {:?}, File: {}", e, path))
+        }
+        Err(_) => Err(format!("Failed to compile the template. File: {}", path)),
+    }
+}
+
+/// Renders the template with the data. Unlike `Response::render`, which fails
+/// with an empty 500 response, the errors are returned so that the caller can
+/// send something readable instead.
+fn render_template<T: Encodable>(path: &str, data: &T) -> Result<Vec<u8>, String> {
+    render_compiled(&compile_template(path)?, path, data)
+}
+
+/// Renders the template compiled from `path` with the data, like
+/// `render_template` without compiling it again.
+fn render_compiled<T: Encodable>(template: &mustache::Template,
+                                 path: &str,
+                                 data: &T)
+                                 -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    match panic::catch_unwind(AssertUnwindSafe(|| template.render(&mut body, data))) {
+        Ok(Ok(())) => Ok(body),
+        Ok(Err(e)) => Err(format!("Failed to render the template. Error: {:?}, File: {}", e, path)),
+        Err(_) => Err(format!("Failed to render the template. File: {}", path)),
+    }
+}
+
+/// Renders the home (menu) page of `HOME_TEMPLATE` for the versions, without
+/// a server. e.g. for snapshot tests and static exports.
+pub fn render_menu_html(versions: &[Version]) -> io::Result<String> {
+    let body = render_template(HOME_TEMPLATE, &make_menu_data(versions, DEFAULT_LABEL_FORMAT))
+        .map_err(io::Error::other)?;
+    String::from_utf8(body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Writes the site as static files, for the `--export` flag: the menu as
+/// `<out_dir>/index.html`, and a copy of each version directory under its
+/// display name (e.g. "1.10 " as `<out_dir>/1.10`). With multiple doc roots,
+/// the files of the first one win, as when they are served. Returns the
+/// number of versions exported.
+pub fn export_site(doc_root: &str, scan: &ScanOptions, out_dir: &Path) -> io::Result<usize> {
+    let versions = get_versions(doc_root, scan)?;
+    fs::create_dir_all(out_dir)?;
+    fs::write(out_dir.join("index.html"), render_menu_html(&versions)?)?;
+    for ver in &versions {
+        for root in split_doc_roots(doc_root) {
+            let dir = Path::new(root).join(&ver.dir_name);
+            if dir.is_dir() {
+                copy_new_files(&dir, &out_dir.join(&ver.display))?;
+            }
+        }
+    }
+    Ok(versions.len())
+}
+
+/// Copies the files under `from` to `to`, leaving the ones already there.
+fn copy_new_files(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.path().is_dir() {
+            copy_new_files(&entry.path(), &target)?;
+        } else if !target.exists() {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the `LISTEN_ADDRESS` env var, or "0.0.0.0" when it is not set.
+/// e.g. "127.0.0.1" for local development
+pub fn get_listen_address() -> String {
+    env::var("LISTEN_ADDRESS").unwrap_or_else(|_| LISTEN_ADDRESS.to_string())
+}
+
+/// Returns the request log format from the `LOG_FORMAT` env var ("plain" or
+/// "json"). Defaults to plain.
+fn get_log_format() -> LogFormat {
+    env::var("LOG_FORMAT").map(|name| LogFormat::from_name(&name)).unwrap_or(LogFormat::Plain)
+}
+
+/// Returns the `SERVER_THREADS` value if it is a positive integer. Otherwise
+/// hyper's default is used, with a warning for an invalid value.
+fn parse_server_threads(value: Option<&str>) -> Option<usize> {
+    let value = value?.trim();
+    match value.parse() {
+        Ok(threads) if threads > 0 => Some(threads),
+        _ => {
+            logger::warn(&format!("Invalid SERVER_THREADS value '{}': expected a positive \
+                                   integer. Using the default.",
+                                  value));
+            None
+        }
+    }
+}
+
+/// Returns the `GZIP_LEVEL` value, clamped to 1-9 with a warning. A value that
+/// is not a number gets the default, also with a warning.
+fn parse_gzip_level(value: Option<&str>) -> u32 {
+    let value = match value {
+        Some(value) => value.trim(),
+        None => return DEFAULT_GZIP_LEVEL,
+    };
+    match value.parse::<i64>() {
+        Ok(level) if (1..=9).contains(&level) => level as u32,
+        Ok(level) => {
+            let clamped = level.clamp(1, 9) as u32;
+            logger::warn(&format!("Invalid GZIP_LEVEL value '{}': expected 1 to 9. Using {}.",
+                                  value,
+                                  clamped));
+            clamped
+        }
+        Err(_) => {
+            logger::warn(&format!("Invalid GZIP_LEVEL value '{}': expected 1 to 9. Using the \
+                                   default.",
+                                  value));
+            DEFAULT_GZIP_LEVEL
+        }
+    }
+}
+
+/// For Heroku deployment
+pub fn get_server_port() -> Result<u16, String> {
+    parse_port(&env::var("PORT").unwrap_or_else(|_| DEFAULT_PORT.to_string()))
+}
+
+/// Returns the port to listen on: the `--port` flag in `args` (e.g.
+/// `["--port", "8080"]` or `["--port=8080"]`) if given, or else the `PORT` env
+/// var, or else 6767. An invalid value is an error, not a fall back to the
+/// next one.
+pub fn resolve_port(args: &[String]) -> Result<u16, String> {
+    match port_flag(args)? {
+        Some(port) => Ok(port),
+        None => get_server_port(),
+    }
+}
+
+/// Returns the value of the `--port` flag, if there is one.
+fn port_flag(args: &[String]) -> Result<Option<u16>, String> {
+    for (i, arg) in args.iter().enumerate() {
+        let value = if arg == "--port" {
+            match args.get(i + 1) {
+                Some(value) => value.as_str(),
+                None => return Err("--port needs a port number. e.g. --port 8080".to_string()),
+            }
+        } else if let Some(value) = arg.strip_prefix("--port=") {
+            value
+        } else {
+            continue;
+        };
+        return value.parse()
+            .map(Some)
+            .map_err(|e| format!("Invalid --port value '{}': {}", value, e));
+    }
+    Ok(None)
+}
+
+fn parse_port(port: &str) -> Result<u16, String> {
+    port.parse().map_err(|e| format!("Invalid PORT value '{}': {}", port, e))
+}
+
+/// Returns the address and port to listen on. The `BIND` env var (e.g.
+/// "127.0.0.1:8080"), when set, wins over `LISTEN_ADDRESS` and `PORT`. The
+/// `--port` flag in `args` wins over both for the port.
+pub fn get_bind_address(args: &[String]) -> Result<(String, u16), String> {
+    match env::var("BIND") {
+        Ok(bind) => {
+            let (address, port) = parse_bind(&bind)?;
+            Ok((address, port_flag(args)?.unwrap_or(port)))
+        }
+        Err(_) => Ok((get_listen_address(), resolve_port(args)?)),
+    }
+}
+
+/// Splits "host:port" into the host and the port. IPv6 addresses must be in
+/// brackets, which are removed. e.g. "[::1]:8080" -> ("::1", 8080)
+pub fn parse_bind(s: &str) -> Result<(String, u16), String> {
+    let invalid = |reason: &str| format!("Invalid BIND value '{}': {}", s, reason);
+    let (host, port) = s.trim().rsplit_once(':').ok_or_else(|| invalid("expected host:port"))?;
+    let host = if host.starts_with('[') && host.ends_with(']') {
+        &host[1..host.len() - 1]
+    } else if host.contains(':') {
+        return Err(invalid("IPv6 addresses must be in brackets, e.g. [::1]:8080"));
+    } else {
+        host
+    };
+    if host.is_empty() {
+        return Err(invalid("the host is missing"));
+    }
+    let port = port.parse().map_err(|e| invalid(&format!("{}", e)))?;
+    Ok((host.to_string(), port))
+}
+
+fn get_static_max_age() -> Result<u32, String> {
+    match env::var("STATIC_MAX_AGE") {
+        Ok(n) => n.parse().map_err(|e| format!("Invalid STATIC_MAX_AGE value '{}': {}", n, e)),
+        Err(_) => Ok(DEFAULT_STATIC_MAX_AGE),
+    }
+}
+
+fn get_access_log_max_bytes() -> Result<u64, String> {
+    match env::var("ACCESS_LOG_MAX_BYTES") {
+        Ok(n) => {
+            n.parse().map_err(|e| format!("Invalid ACCESS_LOG_MAX_BYTES value '{}': {}", n, e))
+        }
+        Err(_) => Ok(access_log::DEFAULT_MAX_BYTES),
+    }
+}
+
+fn get_stream_threshold() -> Result<u64, String> {
+    match env::var("STREAM_THRESHOLD") {
+        Ok(n) => n.parse().map_err(|e| format!("Invalid STREAM_THRESHOLD value '{}': {}", n, e)),
+        Err(_) => Ok(DEFAULT_STREAM_THRESHOLD),
+    }
+}
+
+fn get_trailing_slash() -> Result<TrailingSlash, String> {
+    match env::var("TRAILING_SLASH") {
+        Ok(name) => {
+            TrailingSlash::from_name(&name).ok_or_else(|| {
+                format!("Invalid TRAILING_SLASH value '{}': expected add, remove or ignore", name)
+            })
+        }
+        Err(_) => Ok(TrailingSlash::Add),
+    }
+}
+
+fn get_basic_auth() -> Result<Option<(String, String)>, String> {
+    match (env::var("BASIC_AUTH_USER"), env::var("BASIC_AUTH_PASS")) {
+        (Ok(user), Ok(pass)) => Ok(Some((user, pass))),
+        (Err(_), Err(_)) => Ok(None),
+        _ => Err("BASIC_AUTH_USER and BASIC_AUTH_PASS must be set together".to_string()),
+    }
+}
+
+fn get_request_timeout() -> Result<Option<Duration>, String> {
+    match env::var("REQUEST_TIMEOUT_MS") {
+        Ok(ms) => {
+            ms.parse()
+                .map(|ms| Some(Duration::from_millis(ms)))
+                .map_err(|e| format!("Invalid REQUEST_TIMEOUT_MS value '{}': {}", ms, e))
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// Returns the `MENU_LIMIT` env var. It must be a positive number.
+fn get_menu_limit() -> Result<Option<usize>, String> {
+    match env::var("MENU_LIMIT") {
+        Ok(n) => {
+            match n.parse() {
+                Ok(0) => Err(format!("Invalid MENU_LIMIT value '{}': it must be positive", n)),
+                Ok(limit) => Ok(Some(limit)),
+                Err(e) => Err(format!("Invalid MENU_LIMIT value '{}': {}", n, e)),
+            }
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// Returns the `MAX_CONCURRENT` env var. It must be a positive number.
+fn get_max_concurrent() -> Result<Option<usize>, String> {
+    match env::var("MAX_CONCURRENT") {
+        Ok(n) => {
+            match n.parse() {
+                Ok(0) => Err(format!("Invalid MAX_CONCURRENT value '{}': it must be positive", n)),
+                Ok(max) => Ok(Some(max)),
+                Err(e) => Err(format!("Invalid MAX_CONCURRENT value '{}': {}", n, e)),
+            }
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// Returns the `WATCH_INTERVAL_SECS` interval if `WATCH_DOC_ROOT` is "1" or
+/// "true".
+fn get_watch_interval() -> Result<Option<Duration>, String> {
+    let watch = env::var("WATCH_DOC_ROOT")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    if !watch {
+        return Ok(None);
+    }
+    match env::var("WATCH_INTERVAL_SECS") {
+        Ok(n) => {
+            match n.parse() {
+                Ok(0) => Err(format!("Invalid WATCH_INTERVAL_SECS value '{}': must be > 0", n)),
+                Ok(secs) => Ok(Some(Duration::from_secs(secs))),
+                Err(e) => Err(format!("Invalid WATCH_INTERVAL_SECS value '{}': {}", n, e)),
+            }
+        }
+        Err(_) => Ok(Some(Duration::from_secs(DEFAULT_WATCH_INTERVAL))),
+    }
+}
+
+fn get_max_body_bytes() -> Result<u64, String> {
+    match env::var("MAX_BODY_BYTES") {
+        Ok(n) => n.parse().map_err(|e| format!("Invalid MAX_BODY_BYTES value '{}': {}", n, e)),
+        Err(_) => Ok(body_limit::DEFAULT_MAX_BODY_BYTES),
+    }
+}
+
+fn get_drain_timeout() -> Result<u32, String> {
+    match env::var("DRAIN_TIMEOUT") {
+        Ok(n) => n.parse().map_err(|e| format!("Invalid DRAIN_TIMEOUT value '{}': {}", n, e)),
+        Err(_) => Ok(DEFAULT_DRAIN_TIMEOUT),
+    }
+}
+
+pub fn get_bind_retries() -> Result<u32, String> {
+    match env::var("BIND_RETRIES") {
+        Ok(n) => n.parse().map_err(|e| format!("Invalid BIND_RETRIES value '{}': {}", n, e)),
+        Err(_) => Ok(DEFAULT_BIND_RETRIES),
+    }
+}
+
+
+/// What a version directory is for: a numbered release, or a named channel
+/// like "nightly". Channels sort above all numbered releases, and by name among
+/// themselves.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Release {
+    /// major, minor and patch numbers. e.g. 1.10.2
+    Numbered(u32, u32, u32),
+    /// e.g. "nightly", "beta"
+    Channel(String),
+}
+
+/// A doc version found in the doc root directory. Numbered versions are ordered
+/// by major, then minor, then patch number. The metadata does not take part in
+/// comparisons.
+#[derive(Clone, Debug)]
+pub struct Version {
+    release: Release,
+    /// The version string as it appears in the dir name. e.g. "1.10", "1.10.2"
+    display: String,
+    /// The dir name on disk. Usually the same as `display`, but it may have
+    /// whitespace or other casing. e.g. "1.10 "
+    dir_name: String,
+    /// Extra fields from the optional `meta.json` in the version directory.
+    /// e.g. {"release_date": "2016-07-07", "eol": false}
+    meta: json::Object,
+    /// The first line of the optional `RELEASE.md` or `notes.txt` in the
+    /// version directory.
+    notes: Option<String>,
+    /// The version a symlinked channel dir points at, with `FOLLOW_SYMLINKS`.
+    /// e.g. "1.10" for `stable -> 1.10`
+    alias_of: Option<String>,
+}
+
+impl Version {
+    pub fn new(major: u32, minor: u32, patch: u32, display: &str) -> Version {
+        Version {
+            release: Release::Numbered(major, minor, patch),
+            display: display.to_string(),
+            dir_name: display.to_string(),
+            meta: json::Object::new(),
+            notes: None,
+            alias_of: None,
+        }
+    }
+
+    fn channel(name: &str) -> Version {
+        Version {
+            release: Release::Channel(name.to_string()),
+            display: name.to_string(),
+            dir_name: name.to_string(),
+            meta: json::Object::new(),
+            notes: None,
+            alias_of: None,
+        }
+    }
+
+    /// Returns the channel name if this is a named channel.
+    fn channel_name(&self) -> Option<&str> {
+        match self.release {
+            Release::Channel(ref name) => Some(name),
+            Release::Numbered(..) => None,
+        }
+    }
+
+    /// The `sort_weight` from `meta.json`, or 0. e.g. 1 for an LTS release
+    fn sort_weight(&self) -> i64 {
+        self.meta.get("sort_weight").and_then(|w| w.as_i64()).unwrap_or(0)
+    }
+
+    /// Compares by the `sort_weight`, then the version. This is the order that
+    /// picks the newest, so that a pinned version, which tops the menu, is the
+    /// newest everywhere.
+    fn cmp_weighted(&self, other: &Version) -> Ordering {
+        self.sort_weight().cmp(&other.sort_weight()).then_with(|| self.cmp(other))
+    }
+
+    fn key(&self) -> (&Release, &str) {
+        (&self.release, &self.display)
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Version) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Version) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+
+/// The versions shared by the handlers. Can be swapped by `refresh_versions`.
+type SharedVersions = Arc<RwLock<Vec<Version>>>;
+
+/// Rescans the doc root and replaces the shared versions. They are left as they
+/// are if the scan fails.
+fn refresh_versions(dir: &str, scan: &ScanOptions, versions: &SharedVersions) -> io::Result<()> {
+    let new_versions = get_versions(dir, scan)?;
+    *versions.write().unwrap() = new_versions;
+    Ok(())
+}
+
+/// Rescans the doc root and replaces the shared versions if they changed.
+/// Returns true if they did.
+fn rescan_if_changed(dir: &str, scan: &ScanOptions, versions: &SharedVersions) -> io::Result<bool> {
+    let new_versions = get_versions(dir, scan)?;
+    if *versions.read().unwrap() == new_versions {
+        return Ok(false);
+    }
+    *versions.write().unwrap() = new_versions;
+    Ok(true)
+}
+
+/// Starts a thread rescanning the doc root every `interval`, for the
+/// `WATCH_DOC_ROOT` env var. Failed scans keep the versions as they are.
+fn watch_doc_root(dir: &str, scan: &ScanOptions, interval: Duration, versions: SharedVersions) {
+    let (dir, scan) = (dir.to_string(), scan.clone());
+    thread::spawn(move || {
+        loop {
+            thread::sleep(interval);
+            match rescan_if_changed(&dir, &scan, &versions) {
+                Ok(true) => {
+                    let vers = versions.read().unwrap();
+                    let names: Vec<&str> = vers.iter().map(|ver| ver.display.as_str()).collect();
+                    logger::info(&format!("The versions in the doc root changed. Versions: {}",
+                                          names.join(", ")));
+                }
+                Ok(false) => (),
+                Err(e) => {
+                    logger::error(&format!("An error occured while rescanning the doc root \
+                                            directory. Error: {}, Dir: {}",
+                                           e,
+                                           dir))
+                }
+            }
+        }
+    });
+}
+
+/// Returns the dirs in a comma-separated doc root list. e.g. "public,archive"
+pub fn split_doc_roots(doc_root: &str) -> Vec<&str> {
+    doc_root.split(',').map(|dir| dir.trim()).filter(|dir| !dir.is_empty()).collect()
+}
+
+/// Returns vec of versions, newest first. e.g. 1.10.2, 1.10, 1.9, 1.6
+/// Oldest first with `SortOrder::Asc`.
+/// `dir` can be a comma-separated list of doc roots. Their versions are merged
+/// by `merge_versions`, so a version found in more than one of them is taken
+/// from the first.
+/// The versions in `scan.excludes`, and the ones below `scan.min_version`, are
+/// left out. Their files are still served.
+pub fn get_versions(dir: &str, scan: &ScanOptions) -> io::Result<Vec<Version>> {
+    let mut found = Vec::new();
+    for (i, root) in split_doc_roots(dir).into_iter().enumerate() {
+        for ver in list_version_dirs(Path::new(root), scan.keep_v_prefix, scan.follow_symlinks)? {
+            found.push((i, ver));
+        }
+    }
+    let mut versions = merge_versions(found);
+    exclude_versions(&mut versions, &scan.excludes);
+    if let Some(ref floor) = scan.min_version {
+        drop_older_versions(&mut versions, floor);
+    }
+    sort_versions(&mut versions, scan.sort);
+    Ok(versions)
+}
+
+/// Why `scan_version_dirs` left out an entry of the doc root.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SkipReason {
+    /// The entry, or what it links to, can't be read. e.g. a broken symlink
+    Unreadable(String),
+    NotADir,
+    /// The name is not valid UTF-8.
+    BadName,
+    /// The name is neither a version number nor one of the `CHANNELS`.
+    NoSemverMatch,
+    /// The version number is too large for `u32`.
+    ParseOverflow,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SkipReason::Unreadable(ref e) => write!(f, "can't be read: {}", e),
+            SkipReason::NotADir => write!(f, "not a directory"),
+            SkipReason::BadName => write!(f, "the name is not UTF-8"),
+            SkipReason::NoSemverMatch => write!(f, "not a version number or a channel"),
+            SkipReason::ParseOverflow => write!(f, "the version number is too large"),
+        }
+    }
+}
+
+/// The entries of a doc root: the versions, in the directory order, and the
+/// names of the other entries with why they were left out.
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub included: Vec<Version>,
+    pub skipped: Vec<(String, SkipReason)>,
+}
+
+/// Returns vec of versions in the directory order. e.g. 1.9 (patch 0), 1.10.2
+/// The patch number defaults to 0 when the dir name has only two components.
+/// Entries that can't be read are skipped with a message. Only an unreadable
+/// `dir` is an error.
+fn list_version_dirs(dir: &Path,
+                     keep_v_prefix: bool,
+                     follow_symlinks: bool)
+                     -> io::Result<Vec<Version>> {
+    scan_version_dirs(dir, keep_v_prefix, follow_symlinks).map(|report| report.included)
+}
+
+/// Returns the versions in the directory, like `list_version_dirs`, and the
+/// entries skipped. The ones with numbers too large for `u32` and the
+/// unreadable ones are logged as warnings, the others at the debug level.
+pub fn scan_version_dirs(dir: &Path,
+                         keep_v_prefix: bool,
+                         follow_symlinks: bool)
+                         -> io::Result<ScanReport> {
+    let mut report = ScanReport::default();
+    if !fs::metadata(dir)?.is_dir() {
+        return Ok(report);
+    }
+    for entry in fs::read_dir(dir)? {
+        // one bad entry (e.g. a broken symlink) shouldn't hide the other versions
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                logger::warn(&format!("Skipping an entry in {} that can't be read: {}",
+                                      dir.display(),
+                                      e));
+                report.skipped
+                    .push((dir.display().to_string(), SkipReason::Unreadable(e.to_string())));
+                continue;
+            }
+        };
+        let name = entry.file_name().to_string_lossy().into_owned();
+        match version_dir(&entry.path(), keep_v_prefix, follow_symlinks) {
+            Ok(ver) => report.included.push(ver),
+            Err(reason) => {
+                match reason {
+                    SkipReason::Unreadable(_) | SkipReason::ParseOverflow => {
+                        logger::warn(&format!("Skipping {}: {}", entry.path().display(), reason))
+                    }
+                    _ => logger::debug(&format!("Skipping {}: {}", entry.path().display(), reason)),
+                }
+                report.skipped.push((name, reason));
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Returns the version for an entry of the doc root, with its `meta.json` and
+/// release notes, or why it is not one.
+fn version_dir(path: &Path,
+               keep_v_prefix: bool,
+               follow_symlinks: bool)
+               -> Result<Version, SkipReason> {
+    let metadata = fs::metadata(path).map_err(|e| SkipReason::Unreadable(e.to_string()))?;
+    if !metadata.is_dir() {
+        return Err(SkipReason::NotADir);
+    }
+    let alias = if follow_symlinks { symlink_alias(path, keep_v_prefix) } else { None };
+    let mut ver = match alias {
+        Some(ver) => ver,
+        None => version_from_dir_name(path, keep_v_prefix)?,
+    };
+    if let Some(meta) = read_meta(path) {
+        ver.meta = meta;
+    }
+    ver.notes = read_notes(path);
+    Ok(ver)
+}
+
+/// Returns the version for the dir name, if it is a version number or one of
+/// the `CHANNELS`, like `version_from_dir_name`.
+fn parse_version_dir(path: &Path, keep_v_prefix: bool) -> Option<Version> {
+    version_from_dir_name(path, keep_v_prefix).ok()
+}
+
+/// Returns the version for the dir name, if it is a version number or one of
+/// the `CHANNELS`. The name is trimmed and lowercased first, so "1.10 " is
+/// 1.10 and "Nightly" is nightly. A `v` in front of the number ("v1.10") is
+/// dropped from the display string unless `keep_v_prefix` is true.
+fn version_from_dir_name(path: &Path, keep_v_prefix: bool) -> Result<Version, SkipReason> {
+    let dir_name = path.file_name().and_then(|name| name.to_str()).ok_or(SkipReason::BadName)?;
+    let name = dir_name.trim().to_lowercase();
+    let mut version = if let Some(cap) = RE_SEM_VER.captures(&name) {
+        let prefix = if keep_v_prefix { cap.at(1).unwrap() } else { "" };
+        let v1 = cap.at(2).unwrap();
+        let v2 = cap.at(3).unwrap();
+        let (v3, display) = match cap.at(4) {
+            Some(v3) => (v3, format!("{}{}.{}.{}", prefix, v1, v2, v3)),
+            None => ("0", format!("{}{}.{}", prefix, v1, v2)),
+        };
+
+        // `\d+` can still be too many digits
+        match (v1.parse(), v2.parse(), v3.parse()) {
+            (Ok(v1), Ok(v2), Ok(v3)) => Version::new(v1, v2, v3, &display),
+            _ => return Err(SkipReason::ParseOverflow),
+        }
+    } else if CHANNELS.contains(&name.as_str()) {
+        Version::channel(&name)
+    } else {
+        return Err(SkipReason::NoSemverMatch);
+    };
+    version.dir_name = dir_name.to_string();
+    Ok(version)
+}
+
+/// Returns the report of the `--scan-report` flag: for each doc root, the
+/// versions found ("+") and the entries skipped ("-") with why, by name.
+/// e.g.
+///
+/// ```text
+/// public
+///   + 1.10
+///   - assets: not a version number or a channel
+/// ```
+pub fn format_scan_report(doc_root: &str, scan: &ScanOptions) -> io::Result<String> {
+    let mut out = String::new();
+    for root in split_doc_roots(doc_root) {
+        let mut report =
+            scan_version_dirs(Path::new(root), scan.keep_v_prefix, scan.follow_symlinks)?;
+        report.included.sort_by(|a, b| a.dir_name.cmp(&b.dir_name));
+        report.skipped.sort_by(|a, b| a.0.cmp(&b.0));
+        out.push_str(&format!("{}\n", root));
+        for ver in &report.included {
+            if ver.dir_name == ver.display {
+                out.push_str(&format!("  + {}\n", ver.display));
+            } else {
+                out.push_str(&format!("  + {} ({:?})\n", ver.display, ver.dir_name));
+            }
+        }
+        for (name, reason) in &report.skipped {
+            out.push_str(&format!("  - {}: {}\n", name, reason));
+        }
+    }
+    Ok(out)
+}
+
+/// Returns a channel named after the symlink for a link like `stable -> 1.10`,
+/// as an alias of the version it points at. Returns `None` if the path is not a
+/// symlink, if its name is a version or a channel already, or if the target is
+/// not a version dir.
+fn symlink_alias(path: &Path, keep_v_prefix: bool) -> Option<Version> {
+    if !fs::symlink_metadata(path).ok()?.file_type().is_symlink() {
+        return None;
+    }
+    let link_name = path.file_name()?.to_str()?;
+    let name = link_name.trim().to_lowercase();
+    if name.is_empty() || RE_SEM_VER.is_match(&name) || CHANNELS.contains(&name.as_str()) {
+        return None;
+    }
+    let target = parse_version_dir(&fs::canonicalize(path).ok()?, keep_v_prefix)?;
+    let mut version = Version::channel(&name);
+    version.dir_name = link_name.to_string();
+    version.alias_of = Some(target.display);
+    Some(version)
+}
+
+/// Returns the URI with the version in its first segment replaced by the name
+/// of the version's directory, if they differ. e.g. "/1.10/index.html" ->
+/// "/1.10 /index.html"
+fn to_dir_uri(vers: &[Version], uri: &str) -> Option<String> {
+    let rest_start = uri[1..].find(['/', '?']).map_or(uri.len(), |i| i + 1);
+    let display = &uri[1..rest_start];
+    vers.iter()
+        .find(|ver| ver.display == display && ver.dir_name != ver.display)
+        .map(|ver| format!("/{}{}", ver.dir_name, &uri[rest_start..]))
+}
+
+/// Reads `meta.json` in the version directory. It should contain a JSON object.
+/// Returns `None` if the file is missing or malformed, as it is optional.
+fn read_meta(version_dir: &Path) -> Option<json::Object> {
+    let path = version_dir.join(META_FILE);
+    let contents = fs::read_to_string(&path).ok()?;
+    match json::Json::from_str(&contents) {
+        Ok(json::Json::Object(meta)) => Some(meta),
+        _ => {
+            logger::warn(&format!("Ignoring {} as it does not contain a JSON object.",
+                                  path.display()));
+            None
+        }
+    }
+}
+
+/// Returns the first non-empty line of `RELEASE.md` or `notes.txt` in the
+/// version directory, without a Markdown heading mark. e.g. "# Rust 1.10" ->
+/// "Rust 1.10". Returns `None` if there is neither.
+fn read_notes(version_dir: &Path) -> Option<String> {
+    NOTES_FILES.iter()
+        .filter_map(|name| fs::read_to_string(version_dir.join(name)).ok())
+        .next()?
+        .lines()
+        .map(|line| line.trim_start_matches('#').trim())
+        .find(|line| !line.is_empty())
+        .map(|line| line.to_string())
+}
+
+/// The order of the versions in the menu and the API.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SortOrder {
+    /// Newest first. The default.
+    #[default]
+    Desc,
+    /// Oldest first.
+    Asc,
+}
+
+/// How `get_versions` finds and orders the versions. Read from the env vars
+/// once, into `Config`, so the scans don't depend on the env at the time.
+#[derive(Clone, Debug, Default)]
+pub struct ScanOptions {
+    /// The `VERSION_SORT` env var.
+    pub sort: SortOrder,
+    /// The `KEEP_V_PREFIX` env var.
+    pub keep_v_prefix: bool,
+    /// The `FOLLOW_SYMLINKS` env var.
+    pub follow_symlinks: bool,
+    /// The `MIN_VERSION` env var.
+    pub min_version: Option<Version>,
+    /// The `VERSION_EXCLUDE` env var.
+    pub excludes: Vec<String>,
+}
+
+impl ScanOptions {
+    pub fn from_env() -> ScanOptions {
+        ScanOptions {
+            sort: get_version_sort(),
+            keep_v_prefix: get_keep_v_prefix(),
+            follow_symlinks: get_follow_symlinks(),
+            min_version: get_min_version(),
+            excludes: get_version_excludes(),
+        }
+    }
+}
+
+/// Returns the `VERSION_SORT` env var ("desc" or "asc", case insensitive).
+/// Unknown values fall back to `Desc`.
+fn get_version_sort() -> SortOrder {
+    match env::var("VERSION_SORT") {
+        Ok(ref order) if order.eq_ignore_ascii_case("asc") => SortOrder::Asc,
+        _ => SortOrder::Desc,
+    }
+}
+
+/// Returns true if the `KEEP_V_PREFIX` env var is "true" or "1", to show "v1.10"
+/// rather than "1.10" for a dir named v1.10.
+fn get_keep_v_prefix() -> bool {
+    env::var("KEEP_V_PREFIX").map(|v| v == "1" || v.eq_ignore_ascii_case("true")).unwrap_or(false)
+}
+
+/// Returns true if the `FOLLOW_SYMLINKS` env var is "true" or "1", to list the
+/// symlinked dirs like `stable -> 1.10` as aliases of the versions they point at.
+fn get_follow_symlinks() -> bool {
+    env::var("FOLLOW_SYMLINKS").map(|v| v == "1" || v.eq_ignore_ascii_case("true")).unwrap_or(false)
+}
+
+/// Returns the oldest version to show in the menu, from the `MIN_VERSION` env
+/// var, e.g. "1.9". It is read like a version dir name. A value that is not a
+/// version number is ignored with a warning.
+fn get_min_version() -> Option<Version> {
+    let floor = env::var("MIN_VERSION").ok().filter(|v| !v.trim().is_empty())?;
+    match parse_version_dir(Path::new(&floor), false) {
+        Some(ver) if ver.channel_name().is_none() => Some(ver),
+        _ => {
+            logger::warn(&format!("Ignoring MIN_VERSION '{}' as it is not a version number \
+                                   like 1.9.",
+                                  floor));
+            None
+        }
+    }
+}
+
+/// Returns the version strings to hide from the menu, from the `VERSION_EXCLUDE`
+/// env var. It is a comma-separated list of dir names, e.g. "1.6,1.7.0".
+/// Spaces around the names are ignored.
+fn get_version_excludes() -> Vec<String> {
+    env::var("VERSION_EXCLUDE").map(|list| parse_version_list(&list)).unwrap_or_default()
+}
+
+fn parse_version_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Drops the versions whose display string is in `excludes`.
+fn exclude_versions(versions: &mut Vec<Version>, excludes: &[String]) {
+    versions.retain(|ver| !excludes.contains(&ver.display));
+}
+
+/// Drops the numbered versions older than `floor`. Channels are kept.
+fn drop_older_versions(versions: &mut Vec<Version>, floor: &Version) {
+    versions.retain(|ver| ver.release >= floor.release);
+}
+
+/// Sorts versions by major, then minor, then patch number, oldest first, or
+/// newest first with `SortOrder::Desc`. A higher `sort_weight` comes first in
+/// either order, so e.g. an LTS release with a weight of 1 tops the menu. The
+/// same versions are in the order of their dir names.
+fn sort_versions(versions: &mut [Version], order: SortOrder) {
+    versions.sort_by(|a, b| {
+        let by_version = match order {
+            SortOrder::Asc => a.cmp(b),
+            SortOrder::Desc => b.cmp(a),
+        };
+        b.sort_weight()
+            .cmp(&a.sort_weight())
+            .then(by_version)
+            .then_with(|| a.dir_name.cmp(&b.dir_name))
+    });
+}
+
+/// Returns the versions found in the doc roots, each with the index of its
+/// root, sorted and without the duplicates. Of the same versions, the one from
+/// the first root is kept, then the one with the smallest dir name (e.g. "1.10"
+/// over "1.10 "), so the result doesn't depend on the order of the dirs on disk.
+/// The versions are the same if they show the same once normalized, e.g.
+/// "v1.10" and "1.10 ", which would make the routes ambiguous. "1.10" and
+/// "1.10.0" are two versions, with their own URLs. The skipped ones get a
+/// warning, also when they are hidden by an earlier root.
+fn merge_versions(mut found: Vec<(usize, Version)>) -> Vec<Version> {
+    found.sort_by(|(root_a, a), (root_b, b)| {
+        a.cmp(b).then(root_a.cmp(root_b)).then_with(|| a.dir_name.cmp(&b.dir_name))
+    });
+    // the same display strings are next to each other, as they are sorted by it
+    found.dedup_by(|(root, ver), (kept_root, kept)| {
+        if ver.display != kept.display {
+            return false;
+        }
+        if root == kept_root {
+            logger::warn(&format!("Skipping the directory {:?}, as it is the same version as \
+                                   {:?}",
+                                  ver.dir_name,
+                                  kept.dir_name));
+        } else {
+            logger::warn(&format!("Skipping the directory {:?} of doc root {}, as doc root {} \
+                                   has the same version {:?}",
+                                  ver.dir_name,
+                                  *root + 1,
+                                  *kept_root + 1,
+                                  kept.dir_name));
+        }
+        true
+    });
+    found.into_iter().map(|(_, ver)| ver).collect()
+}
+
+/// Returns the `limit` newest of the versions, in their order, for the menu,
+/// and true if some were left out. The channels count as the newest, as they
+/// sort above the numbered versions, and so do the versions with a higher
+/// `sort_weight`. All of them without a limit.
+fn newest_versions(vers: &[Version], limit: Option<usize>) -> (Vec<Version>, bool) {
+    let limit = match limit {
+        Some(limit) if limit < vers.len() => limit,
+        _ => return (vers.to_vec(), false),
+    };
+    let mut newest_first: Vec<&Version> = vers.iter().collect();
+    newest_first.sort_by(|a, b| b.cmp_weighted(a));
+    let oldest_shown = newest_first[limit.max(1) - 1];
+    let shown = vers.iter().filter(|ver| ver.cmp_weighted(oldest_shown) != Ordering::Less);
+    (shown.cloned().collect(), true)
+}
+
+/// Returns a map for mustache template.
+/// e.g. {"versions", [{"version", "1.10"}, {"version", "1.9"}, {"version", "1.6"}],
+///       "no_versions", false}
+/// Channels like "nightly" also have {"channel", "nightly"} in their map, and
+/// versions with release notes have {"notes", "..."}.
+/// Each version map also has the fields from the version's `meta.json`, and
+/// the `label` for `VERSION_LABEL_FORMAT`, e.g. {"label", "バージョン 1.10"}.
+/// `no_versions` is true when there is no version to show, so the template can
+/// show a placeholder instead of an empty menu.
+/// `majors` has the same version maps grouped by the major number, for a
+/// menu with a section per major release.
+/// e.g. [{"major": "2", "versions": [{"version", "2.0"}]},
+///       {"major": "1", "versions": [{"version", "1.10"}, {"version", "1.9"}]}]
+/// Each channel is a group of its own, with the channel name as the major.
+/// `is_latest` is true only for the version `/latest` goes to, which is the
+/// first one but with `VERSION_SORT=asc`, so the template can badge it.
+fn make_menu_data(vers: &[Version], label_format: &str) -> HashMap<String, json::Json> {
+    let latest = latest(vers);
+    let version_maps: Vec<json::Object> = vers.iter()
+        .map(|ver| {
+            let mut map = version_object(ver);
+            map.insert("label".to_string(), format_label(label_format, &ver.display).to_json());
+            map.insert("is_latest".to_string(), (Some(ver) == latest).to_json());
+            map
+        })
+        .collect();
+    let majors: Vec<json::Object> = group_by_major(vers)
+        .into_iter()
+        .map(|(major, indexes)| {
+            let maps: Vec<json::Object> =
+                indexes.iter().map(|&i| version_maps[i].clone()).collect();
+            let mut group = json::Object::new();
+            group.insert("major".to_string(), major.to_json());
+            group.insert("versions".to_string(), maps.to_json());
+            group
+        })
+        .collect();
+    let mut menu_data = HashMap::new();
+    menu_data.insert("versions".to_string(), version_maps.to_json());
+    menu_data.insert("majors".to_string(), majors.to_json());
+    menu_data.insert("no_versions".to_string(), vers.is_empty().to_json());
+    menu_data
+}
+
+/// Returns the fields of the version from its `meta.json`, plus its "version",
+/// and its "channel" and "notes" if it has them.
+fn version_object(ver: &Version) -> json::Object {
+    let mut map = ver.meta.clone();
+    map.insert("version".to_string(), ver.display.to_json());
+    if let Some(name) = ver.channel_name() {
+        map.insert("channel".to_string(), name.to_json());
+    }
+    if let Some(ref notes) = ver.notes {
+        map.insert("notes".to_string(), notes.to_json());
+    }
+    if let Some(ref alias_of) = ver.alias_of {
+        map.insert("alias_of".to_string(), alias_of.to_json());
+    }
+    map
+}
+
+/// Returns the label of the version for the `VERSION_LABEL_FORMAT`, e.g.
+/// "バージョン {version}" -> "バージョン 1.10". A format without `{version}`, or
+/// with other placeholders, is ignored and the label is the version as is.
+fn format_label(format: &str, version: &str) -> String {
+    if !format.contains("{version}") {
+        return version.to_string();
+    }
+    let rest = format.replace("{version}", "");
+    if rest.contains(['{', '}']) {
+        return version.to_string();
+    }
+    format.replace("{version}", version)
+}
+
+/// Returns the indexes of the versions by major number (or channel name), in
+/// the order the groups first appear. The versions keep their order within
+/// a group. e.g. [1.10, 2.0, 1.9] -> [("1", [0, 2]), ("2", [1])]
+fn group_by_major(vers: &[Version]) -> Vec<(String, Vec<usize>)> {
+    let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+    for (i, ver) in vers.iter().enumerate() {
+        let major = match ver.release {
+            Release::Numbered(major, _, _) => major.to_string(),
+            Release::Channel(ref name) => name.clone(),
+        };
+        match groups.iter_mut().find(|group| group.0 == major) {
+            Some(group) => group.1.push(i),
+            None => groups.push((major, vec![i])),
+        }
+    }
+    groups
+}
+
+/// Returns a map for the 404 mustache template. The versions are included so
+/// the page can show the same navigation as the home page.
+/// e.g. {"path": "/foo.html", "versions": [{"version", "1.10"}, {"version", "1.9"}], ...}
+fn make_not_found_data(path: &str, vers: &[Version]) -> HashMap<String, json::Json> {
+    let mut data = make_menu_data(vers, DEFAULT_LABEL_FORMAT);
+    data.insert("path".to_string(), path.to_json());
+    data
+}
+
+/// Returns the first segment of the path if it looks like a version, or a
+/// channel, but none of the versions has it. e.g. "/9.9/index.html" -> "9.9".
+/// Other missing paths, like "/1.10/missing.html" or "/missing.html", get `None`.
+fn unknown_version<'a>(vers: &[Version], path: &'a str) -> Option<&'a str> {
+    let segment = path.trim_start_matches('/').split('/').next().unwrap_or("");
+    let name = segment.to_lowercase();
+    let looks_like_version = RE_SEM_VER.is_match(&name) || CHANNELS.contains(&name.as_str());
+    if looks_like_version && !has_version(vers, segment) {
+        Some(segment)
+    } else {
+        None
+    }
+}
+
+/// Returns the newest numbered version of each major number, by the
+/// `sort_weight` first like the menu. The channels, and so the symlink
+/// aliases, are not in any major.
+/// e.g. [1.9, 1.10, 2.0] -> {1: "1.10", 2: "2.0"}
+fn newest_by_major(vers: &[Version]) -> HashMap<u32, String> {
+    let mut newest: HashMap<u32, &Version> = HashMap::new();
+    for ver in vers {
+        if let Release::Numbered(major, _, _) = ver.release {
+            let entry = newest.entry(major).or_insert(ver);
+            if ver.cmp_weighted(entry) == Ordering::Greater {
+                *entry = ver;
+            }
+        }
+    }
+    newest.into_iter().map(|(major, ver)| (major, ver.display.clone())).collect()
+}
+
+/// Returns the newest version, i.e. the first in the menu but with
+/// `VERSION_SORT=asc`, not counting the symlink aliases, which sort above the
+/// numbered versions as channels but are only other names of them.
+fn latest(vers: &[Version]) -> Option<&Version> {
+    vers.iter().filter(|ver| ver.alias_of.is_none()).max_by(|a, b| a.cmp_weighted(b))
+}
+
+/// Returns the path under the newest version for the path under `/latest`.
+/// e.g. "/book/index.html" -> "/1.10/book/index.html", None -> "/1.10/index.html"
+/// Returns `None` if there is no version.
+fn latest_path(vers: &[Version], sub_path: Option<&str>) -> Option<String> {
+    latest(vers).map(|ver| {
+        match sub_path {
+            None | Some("") | Some("/") => format!("/{}/index.html", ver.display),
+            Some(path) => format!("/{}{}", ver.display, path),
+        }
+    })
+}
+
+/// Returns true if the `Accept` header ranks JSON above HTML. No header,
+/// `*/*` and browsers' headers all get HTML.
+fn prefers_json(accept: Option<&Accept>) -> bool {
+    let items = match accept {
+        Some(Accept(items)) => items,
+        None => return false,
+    };
+    let quality = |matches: &dyn Fn(&Mime) -> bool| {
+        items.iter().filter(|item| matches(&item.item)).map(|item| item.quality).max()
+    };
+    let json = quality(&|mime| matches!(*mime, Mime(TopLevel::Application, SubLevel::Json, _)));
+    let html = quality(&|mime| {
+        matches!(*mime,
+                 Mime(TopLevel::Text, SubLevel::Html, _) |
+                 Mime(TopLevel::Text, SubLevel::Star, _) |
+                 Mime(TopLevel::Star, _, _))
+    });
+    match json {
+        Some(json) => json > Quality(0) && html.is_none_or(|html| json > html),
+        None => false,
+    }
+}
+
+/// Returns the versions whose display string contains the query, ignoring
+/// case. An empty query returns all versions.
+fn search_versions(vers: &[Version], query: &str) -> Vec<Version> {
+    let query = query.trim().to_lowercase();
+    vers.iter().filter(|ver| ver.display.to_lowercase().contains(&query)).cloned().collect()
+}
+
+/// Returns true if the typed version is one of the versions. It is normalized
+/// like the dir names, so " V1.10" is 1.10. Anything that isn't a version
+/// number or a channel, like "1.x" or "../1.10", is not.
+fn is_known_version(vers: &[Version], input: &str, keep_v_prefix: bool) -> bool {
+    if input.contains('/') || input.contains('\\') {
+        return false;
+    }
+    match parse_version_dir(Path::new(input), keep_v_prefix) {
+        Some(typed) => vers.iter().any(|ver| ver.display == typed.display),
+        None => false,
+    }
+}
+
+/// Returns the version whose pages the `ASSETS_PATH` would be in, if any. e.g.
+/// "/1.10/assets" -> "1.10"
+fn assets_version(assets_path: &str, vers: &[Version]) -> Option<String> {
+    let first = assets_path.trim_start_matches('/').split('/').next()?;
+    vers.iter()
+        .find(|ver| ver.display == first || ver.dir_name == first)
+        .map(|ver| ver.display.clone())
+}
+
+/// Returns true if one of the versions has the given dir name.
+pub fn has_version(vers: &[Version], display: &str) -> bool {
+    vers.iter().any(|ver| ver.display == display)
+}
+
+/// Returns the aliases under a version, which would hide its pages, so they
+/// are skipped.
+fn shadowing_aliases<'a>(aliases: &'a [(String, String)], vers: &[Version]) -> Vec<&'a str> {
+    aliases.iter()
+        .map(|(alias, _)| alias.as_str())
+        .filter(|alias| has_version(vers, aliases::first_segment(alias)))
+        .collect()
+}
+
+/// A plain menu for when the home template can't be rendered.
+fn fallback_menu_html(vers: &[Version]) -> String {
+    let mut html = String::from("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+                                 <title>Documents</title></head><body>\n\
+                                 <p>The menu page could not be rendered. \
+                                 The versions are listed below.</p>\n<ul>\n");
+    for ver in vers {
+        let display = escape_xml(&ver.display);
+        html.push_str(&format!("<li><a href=\"{0}/index.html\">{0}</a></li>\n", display));
+    }
+    html.push_str("</ul>\n</body></html>\n");
+    html
+}
+
+/// Returns the version strings as a JSON array. e.g. ["1.10","1.9","1.6"]
+fn make_versions_json(vers: &[Version]) -> String {
+    let displays: Vec<&str> = vers.iter().map(|ver| ver.display.as_str()).collect();
+    json::encode(&displays).unwrap()
+}
+
+/// Returns the JSON for `/api/info`.
+fn make_info_json(uptime: Duration, version_count: usize, app_version: &str) -> String {
+    let mut info = json::Object::new();
+    info.insert("uptime_secs".to_string(), uptime.as_secs_f64().to_json());
+    info.insert("versions".to_string(), version_count.to_json());
+    info.insert("app_version".to_string(), app_version.to_json());
+    json::encode(&info).unwrap()
+}
+
+/// Returns a page of the version list with the total count, for the `limit`
+/// and `offset` query params. The limit is clamped to `1..=MAX_PAGE_SIZE`,
+/// and an offset past the end gets an empty page. It is an error if they are
+/// not numbers.
+fn make_versions_page(vers: &[Version],
+                      limit: Option<&str>,
+                      offset: Option<&str>)
+                      -> Result<String, String> {
+    let parse = |name: &str, value: Option<&str>, default: usize| match value {
+        Some(v) => {
+            v.trim().parse::<usize>().map_err(|e| format!("Invalid {} value '{}': {}", name, v, e))
+        }
+        None => Ok(default),
+    };
+    let limit = parse("limit", limit, MAX_PAGE_SIZE)?.clamp(1, MAX_PAGE_SIZE);
+    let offset = parse("offset", offset, 0)?.min(vers.len());
+    let displays: Vec<String> =
+        vers[offset..].iter().take(limit).map(|ver| ver.display.clone()).collect();
+
+    let mut page = json::Object::new();
+    page.insert("versions".to_string(), displays.to_json());
+    page.insert("total".to_string(), vers.len().to_json());
+    page.insert("offset".to_string(), offset.to_json());
+    page.insert("limit".to_string(), limit.to_json());
+    Ok(json::encode(&page).unwrap())
+}
+
+/// Returns the JSON for `/api/integrity` with the SHA-256 of the file at
+/// `path` under the doc roots, the first one having it. Returns the error
+/// status for a path that is empty or tries to escape the doc roots, and for a
+/// file that isn't there.
+fn make_integrity_json(roots: &[PathBuf],
+                       digests: &DigestCache,
+                       path: &str)
+                       -> Result<String, StatusCode> {
+    let relative_path = Path::new(path.trim_start_matches('/'));
+    if relative_path.as_os_str().is_empty() || !static_files::is_safe_path(relative_path) {
+        return Err(StatusCode::BadRequest);
+    }
+    let file = roots.iter().map(|root| root.join(relative_path)).find(|file| file.is_file());
+    let digest = match file.map(|file| digests.sha256_hex(&file)) {
+        Some(Ok(digest)) => digest,
+        Some(Err(e)) => {
+            logger::error(&format!("Could not read {} for its digest: {}", path, e));
+            return Err(StatusCode::InternalServerError);
+        }
+        None => return Err(StatusCode::NotFound),
+    };
+    let mut integrity = json::Object::new();
+    integrity.insert("path".to_string(), format!("/{}", relative_path.display()).to_json());
+    integrity.insert("sha256".to_string(), digest.to_json());
+    Ok(json::encode(&integrity).unwrap())
+}
+
+/// Returns the version strings as a YAML sequence. They are quoted, as YAML
+/// would read 1.10 as the number 1.1. A JSON string is a valid YAML one.
+fn make_versions_yaml(vers: &[Version]) -> String {
+    if vers.is_empty() {
+        return "[]\n".to_string();
+    }
+    vers.iter().map(|ver| format!("- {}\n", json::encode(&ver.display).unwrap())).collect()
+}
+
+/// Returns the robots.txt content. `env_robots` wins over `robots.txt` in the
+/// (first) doc root, which wins over the default that allows everything.
+fn robots_txt(env_robots: Option<&str>, doc_root: &str) -> String {
+    if let Some(robots) = env_robots {
+        return robots.to_string();
+    }
+    let first_root = split_doc_roots(doc_root).into_iter().next().unwrap_or("");
+    fs::read_to_string(Path::new(first_root).join("robots.txt"))
+        .unwrap_or_else(|_| DEFAULT_ROBOTS_TXT.to_string())
+}
+
+/// Returns a sitemap (https://www.sitemaps.org/protocol.html) with the index
+/// page of each version.
+fn make_sitemap(base_url: &str, vers: &[Version]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                                <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for ver in vers {
+        xml.push_str(&format!("  <url><loc>{}/{}/index.html</loc></url>\n",
+                              escape_xml(base_url),
+                              escape_xml(&ver.display)));
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assets_version, build_server, check_setup, check_template, compile_template,
+                drop_older_versions, exclude_versions, export_site, format_scan_report,
+                format_version_list, get_access_log_max_bytes, get_assets_path, get_bind_address,
+                get_bind_retries, get_drain_timeout, get_listen_address, get_log_format,
+                get_max_body_bytes, get_max_concurrent, get_menu_limit, get_min_version,
+                get_redirects, get_request_timeout, get_server_port, get_static_max_age,
+                get_stream_threshold, get_trailing_slash, get_versions, get_watch_interval,
+                group_by_major, handler, is_known_version, is_quiet, latest_path, list_version_dirs,
+                make_menu_data, make_sitemap, make_versions_page, make_versions_yaml,
+                merge_versions, newest_by_major, newest_versions, parse_bind, parse_gzip_level,
+                parse_port, parse_server_threads, parse_version_dir, parse_version_list,
+                prefers_json, render_compiled, render_menu_html, rescan_if_changed,
+                resolve_doc_root, resolve_home_template, resolve_port, retry_bind, robots_txt,
+                scan_version_dirs, search_versions, shadowing_aliases, sort_versions,
+                split_doc_roots, startup_summary, theme_template, to_dir_uri, unknown_version,
+                version_object, ConcurrencyLimit, Config, DEFAULT_GZIP_LEVEL, DEFAULT_LABEL_FORMAT,
+                ErrorResponses, HOME_TEMPLATE, ScanOptions, SharedVersions, ShutdownGuard,
+                SkipReason, SortOrder, Version};
+
+    use logger::{LogEntry, LogFormat, RequestLogger};
+
+    use request_id::RequestId;
+
+    use shutdown::{wait_for_drain, Shutdown};
+
+    use timeout::{run_with_timeout, timed_out};
+
+    use trailing_slash::TrailingSlash;
+
+    use flate2::read::GzDecoder;
+
+    use hyper::Client;
+    use hyper::client::RedirectPolicy;
+    use hyper::header::{q, qitem, Accept, AcceptEncoding, AcceptRanges, Authorization, Basic,
+                        CacheControl, CacheDirective, ContentEncoding, ContentLength, ContentType,
+                        ETag, Encoding, Headers, IfModifiedSince, IfNoneMatch, LastModified,
+                        Location, QualityItem, RangeUnit};
+    use hyper::method::Method;
+    use hyper::mime::{Mime, SubLevel, TopLevel};
+    use hyper::status::StatusCode;
+
+    use nickel::{Action, HttpRouter, MiddlewareResult, Nickel, Options, Response};
+    use nickel::mimes::MediaType;
+
+    use rustc_serialize::json;
+
+    use std::cmp::Ordering;
+    use std::env;
+    use std::fs;
+    use std::io::{self, Read, Write};
+    use std::mem;
+    use std::net::{TcpListener, TcpStream};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+    use std::thread;
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    lazy_static! {
+        // env vars are process-wide, so tests touching them must not run concurrently.
+        static ref ENV_LOCK: Mutex<()> = Mutex::new(());
+    }
+
+    /// Sets (or removes) an env var and restores the previous value when dropped.
+    struct EnvGuard {
+        key: &'static str,
+        old_value: Option<String>,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl EnvGuard {
+        fn set(key: &'static str, value: Option<&str>) -> EnvGuard {
+            let lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            let old_value = env::var(key).ok();
+            match value {
+                Some(value) => env::set_var(key, value),
+                None => env::remove_var(key),
+            }
+            EnvGuard {
+                key,
+                old_value,
+                _lock: lock,
+            }
+        }
+    }
+
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            match self.old_value {
+                Some(ref value) => env::set_var(self.key, value),
+                None => env::remove_var(self.key),
+            }
+        }
+    }
+
+    /// Starts the server on a free local port and returns its base URL.
+    fn serve(mut server: Nickel) -> String {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        server.options = mem::take(&mut server.options).output_on_listen(false);
+        thread::spawn(move || server.listen(("127.0.0.1", port)));
+
+        for _ in 0..100 {
+            if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        format!("http://127.0.0.1:{}", port)
+    }
+
+    /// Starts the server for the config, with the versions in its doc root.
+    fn serve_config(config: &Config) -> String {
+        serve(build_server(config, get_versions(&config.doc_root, &config.scan).unwrap()))
+    }
+
+    /// Returns the versions in the doc root, found with the default options.
+    fn versions_in(dir: &str) -> Vec<Version> {
+        get_versions(dir, &ScanOptions::default()).unwrap()
+    }
+
+    /// Sends a GET request and returns the status, content type and body.
+    fn get(url: &str) -> (StatusCode, Option<ContentType>, String) {
+        let (status, headers, body) = get_with_headers(url, Headers::new());
+        (status, headers.get::<ContentType>().cloned(), String::from_utf8(body).unwrap())
+    }
+
+    /// Sends a GET request with the headers and returns the status, headers and raw body.
+    fn get_with_headers(url: &str, headers: Headers) -> (StatusCode, Headers, Vec<u8>) {
+        let mut client = Client::new();
+        client.set_redirect_policy(RedirectPolicy::FollowNone);
+        let mut response = client.get(url).headers(headers).send().unwrap();
+        let mut body = Vec::new();
+        response.read_to_end(&mut body).unwrap();
+        (response.status, response.headers.clone(), body)
+    }
+
+    /// Sends a HEAD request and returns the status, headers and raw body.
+    fn head(url: &str) -> (StatusCode, Headers, Vec<u8>) {
+        let mut client = Client::new();
+        client.set_redirect_policy(RedirectPolicy::FollowNone);
+        let mut response = client.head(url).send().unwrap();
+        let mut body = Vec::new();
+        response.read_to_end(&mut body).unwrap();
+        (response.status, response.headers.clone(), body)
+    }
+
+    /// Sends the request line as is, without the URL encoding of a client, and
+    /// returns the status line and the body.
+    fn raw_get(base_url: &str, path: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(&base_url["http://".len()..]).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path)
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, mut rest) = response.split_at(response.find("\r\n\r\n").unwrap() + 4);
+        let status_line = head.lines().next().unwrap().to_string();
+        if !head.to_lowercase().contains("transfer-encoding: chunked") {
+            return (status_line, rest.to_string());
+        }
+        let mut body = String::new();
+        while let Some(line_end) = rest.find("\r\n") {
+            let size = usize::from_str_radix(&rest[..line_end], 16).unwrap();
+            if size == 0 {
+                break;
+            }
+            body.push_str(&rest[line_end + 2..line_end + 2 + size]);
+            rest = &rest[line_end + 2 + size + 2..];
+        }
+        (status_line, body)
+    }
+
+    /// Creates a doc root with an empty directory for each of the given names.
+    fn make_doc_root(dir_names: &[&str]) -> TempDir {
+        let doc_root = TempDir::new().unwrap();
+        for name in dir_names {
+            fs::create_dir(doc_root.path().join(name)).unwrap();
+        }
+        doc_root
+    }
+
+    #[test]
+    fn sort_three_versions() {
+        let mut versions =
+            vec![Version::new(1, 10, 0, "1.10"),
+                 Version::new(1,  6, 0, "1.6"),
+                 Version::new(1,  9, 0, "1.9")];
+        let expectation =
+            vec![Version::new(1,  6, 0, "1.6"),
+                 Version::new(1,  9, 0, "1.9"),
+                 Version::new(1, 10, 0, "1.10")];
+
+        sort_versions(&mut versions, SortOrder::Asc);
+        assert_eq!(expectation, versions)
+    }
+
+    #[test]
+    fn sort_versions_with_weights() {
+        let weighted = |minor: u32, display: &str, weight: i64| {
+            let mut ver = Version::new(1, minor, 0, display);
+            ver.meta.insert("sort_weight".to_string(), json::Json::I64(weight));
+            ver
+        };
+        let mut versions =
+            vec![Version::new(1, 10, 0, "1.10"),
+                 weighted(6, "1.6", 1),
+                 Version::new(1,  9, 0, "1.9"),
+                 weighted(8, "1.8", -1),
+                 weighted(7, "1.7", 1)];
+
+        sort_versions(&mut versions, SortOrder::Asc);
+        assert_eq!(vec!["1.6", "1.7", "1.9", "1.10", "1.8"],
+                   versions.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+        sort_versions(&mut versions, SortOrder::Desc);
+        assert_eq!(vec!["1.7", "1.6", "1.10", "1.9", "1.8"],
+                   versions.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn pinned_versions_on_top() {
+        let doc_root = make_doc_root(&["1.10", "1.9", "1.6"]);
+        fs::write(doc_root.path().join("1.6/meta.json"), r#"{"sort_weight": 1}"#).unwrap();
+        let versions = versions_in(doc_root.path().to_str().unwrap());
+        assert_eq!(vec!["1.6", "1.10", "1.9"],
+                   versions.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+        // the pinned one is the first everywhere
+        assert_eq!(Some("/1.6/index.html".to_string()), latest_path(&versions, None));
+        assert_eq!(Some(&"1.6".to_string()), newest_by_major(&versions).get(&1));
+        let (shown, has_more) = newest_versions(&versions, Some(2));
+        assert_eq!(vec!["1.6", "1.10"],
+                   shown.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+        assert!(has_more);
+        let data = make_menu_data(&shown, DEFAULT_LABEL_FORMAT);
+        assert_eq!(Some(true), data["versions"][0]["is_latest"].as_boolean());
+
+        // on top with VERSION_SORT=asc too
+        let scan = ScanOptions { sort: SortOrder::Asc, ..ScanOptions::default() };
+        let asc = get_versions(doc_root.path().to_str().unwrap(), &scan).unwrap();
+        assert_eq!(vec!["1.6", "1.9", "1.10"],
+                   asc.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+        assert_eq!(Some("/1.6/index.html".to_string()), latest_path(&asc, None));
+    }
+
+    #[test]
+    fn versions_that_collide_once_normalized() {
+        let doc_root = make_doc_root(&["v1.10", "1.10 ", "1.9"]);
+        let versions = versions_in(doc_root.path().to_str().unwrap());
+        let kept: Vec<(&str, &str)> =
+            versions.iter().map(|ver| (ver.display.as_str(), ver.dir_name.as_str())).collect();
+        assert_eq!(vec![("1.10", "1.10 "), ("1.9", "1.9")], kept);
+
+        // the same numbers, but not the same once normalized
+        let doc_root = make_doc_root(&["1.10.0", "1.10", "1.9"]);
+        let versions = versions_in(doc_root.path().to_str().unwrap());
+        assert_eq!(vec!["1.10.0", "1.10", "1.9"],
+                   versions.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn versions_that_collide_across_the_roots() {
+        let first = make_doc_root(&["1.10 ", "1.10.0"]);
+        let second = make_doc_root(&["v1.10", "1.10", "1.9"]);
+        let dirs = format!("{},{}", first.path().display(), second.path().display());
+        let versions = versions_in(&dirs);
+        let kept: Vec<(&str, &str)> =
+            versions.iter().map(|ver| (ver.display.as_str(), ver.dir_name.as_str())).collect();
+        assert_eq!(vec![("1.10.0", "1.10.0"), ("1.10", "1.10 "), ("1.9", "1.9")], kept);
+    }
+
+    #[test]
+    fn duplicate_versions_from_the_roots() {
+        let from = |root: usize, dir_name: &str, display: &str, minor: u32| {
+            let mut ver = Version::new(1, minor, 0, display);
+            ver.dir_name = dir_name.to_string();
+            ver.notes = Some(format!("root {}", root));
+            (root, ver)
+        };
+        let found = vec![from(1, "1.10", "1.10", 10),
+                         from(0, "1.9", "1.9", 9),
+                         from(0, "1.10 ", "1.10", 10),
+                         from(1, "1.9", "1.9", 9),
+                         from(0, "1.10", "1.10", 10)];
+        let mut reversed = found.clone();
+        reversed.reverse();
+
+        for found in [found, reversed] {
+            let merged = merge_versions(found);
+            let kept: Vec<(&str, &str, Option<&str>)> = merged.iter()
+                .map(|ver| (ver.display.as_str(), ver.dir_name.as_str(), ver.notes.as_deref()))
+                .collect();
+            assert_eq!(vec![("1.9", "1.9", Some("root 0")), ("1.10", "1.10", Some("root 0"))],
+                       kept);
+        }
+    }
+
+    #[test]
+    fn versions_over_the_menu_limit() {
+        let desc = vec![Version::channel("nightly"),
+                        Version::new(1, 10, 0, "1.10"),
+                        Version::new(1, 9, 0, "1.9"),
+                        Version::new(1, 6, 0, "1.6")];
+        let displays = |(vers, has_more): (Vec<Version>, bool)| {
+            (vers.iter().map(|ver| ver.display.clone()).collect::<Vec<_>>(), has_more)
+        };
+        assert_eq!((vec!["nightly".to_string(), "1.10".to_string()], true),
+                   displays(newest_versions(&desc, Some(2))));
+        assert_eq!((vec!["nightly".to_string(), "1.10".to_string(), "1.9".to_string(),
+                         "1.6".to_string()],
+                    false),
+                   displays(newest_versions(&desc, Some(4))));
+        assert_eq!((desc.iter().map(|ver| ver.display.clone()).collect(), false),
+                   displays(newest_versions(&desc, None)));
+
+        // the newest ones also when the oldest come first
+        let mut asc = desc.clone();
+        asc.reverse();
+        assert_eq!((vec!["1.9".to_string(), "1.10".to_string(), "nightly".to_string()], true),
+                   displays(newest_versions(&asc, Some(3))));
+    }
+
+    #[test]
+    fn menu_limit_from_env() {
+        let _guard = EnvGuard::set("MENU_LIMIT", Some("0"));
+        assert!(get_menu_limit().is_err());
+    }
+
+    #[test]
+    fn max_concurrent_from_env() {
+        let _guard = EnvGuard::set("MAX_CONCURRENT", Some("0"));
+        assert!(get_max_concurrent().is_err());
+    }
+
+    #[test]
+    fn requests_over_max_concurrent() {
+        let mut server = Nickel::new();
+        // more threads than the limit, so the requests over it are not queued
+        server.options = Options::default().thread_count(Some(4));
+        server.utilize(ConcurrencyLimit::new(1));
+        server.get("/slow",
+                   handler(|_, response| {
+            thread::sleep(Duration::from_millis(500));
+            response.send("done")
+        }));
+        server.get("/healthz", handler(|_, response| response.send("OK")));
+        server.handle_error(ErrorResponses::new(Arc::new(RwLock::new(Vec::new())), "", false));
+        let base = serve(server);
+
+        let slow_url = format!("{}/slow", base);
+        let slow = thread::spawn(move || get(&slow_url));
+        thread::sleep(Duration::from_millis(100));
+        let (status, headers, body) = get_with_headers(&format!("{}/slow", base), Headers::new());
+        assert_eq!(StatusCode::ServiceUnavailable, status);
+        assert_eq!(Some(&[b"1".to_vec()][..]), headers.get_raw("Retry-After"));
+        assert_eq!(b"Too many requests in flight".to_vec(), body);
+        assert_eq!(StatusCode::Ok, get(&format!("{}/healthz", base)).0);
+
+        assert_eq!((StatusCode::Ok, "done".to_string()),
+                   slow.join().map(|(status, _, body)| (status, body)).unwrap());
+        // the permit is given back just after the client has the response
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(StatusCode::Ok, get(&format!("{}/slow", base)).0);
+    }
+
+    #[test]
+    fn streamed_responses_count_against_max_concurrent() {
+        let mut server = Nickel::new();
+        server.options = Options::default().thread_count(Some(4));
+        server.utilize(ConcurrencyLimit::new(1));
+        server.get("/stream", handler(|_, response| streamed_body(response)));
+        server.handle_error(ErrorResponses::new(Arc::new(RwLock::new(Vec::new())), "", false));
+        let base = serve(server);
+
+        let url = format!("{}/stream", base);
+        let stream = thread::spawn(move || get(&url));
+        thread::sleep(Duration::from_millis(150));
+        // the first response has started, but its body is still being sent
+        let (status, headers, _) = get_with_headers(&format!("{}/stream", base), Headers::new());
+        assert_eq!(StatusCode::ServiceUnavailable, status);
+        assert_eq!(Some(&[b"1".to_vec()][..]), headers.get_raw("Retry-After"));
+        assert_eq!("first second", stream.join().unwrap().2);
+    }
+
+    #[test]
+    fn menu_with_a_limit() {
+        let doc_root = make_doc_root(&["1.6", "1.9", "1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.menu_limit = Some(2);
+        let base_url = serve_config(&config);
+
+        let (_, _, body) = get(&format!("{}/", base_url));
+        assert!(body.contains("Rust 1.10"));
+        assert!(body.contains("Rust 1.9"));
+        assert!(!body.contains("Rust 1.6"));
+        assert!(body.contains("href=\"?all=1\""));
+
+        let (_, _, body) = get(&format!("{}/?all=1", base_url));
+        assert!(body.contains("Rust 1.6"));
+        assert!(!body.contains("href=\"?all=1\""));
+
+        // the API still has all of them
+        assert_eq!(r#"["1.10","1.9","1.6"]"#, get(&format!("{}/api/versions", base_url)).2);
+    }
+
+    #[test]
+    fn sort_versions_with_patch() {
+        let mut versions =
+            vec![Version::new(1, 10, 2, "1.10.2"),
+                 Version::new(1, 10, 0, "1.10"),
+                 Version::new(1,  9, 0, "1.9"),
+                 Version::new(1, 10, 1, "1.10.1")];
+        let expectation =
+            vec![Version::new(1,  9, 0, "1.9"),
+                 Version::new(1, 10, 0, "1.10"),
+                 Version::new(1, 10, 1, "1.10.1"),
+                 Version::new(1, 10, 2, "1.10.2")];
+
+        sort_versions(&mut versions, SortOrder::Asc);
+        assert_eq!(expectation, versions)
+    }
+
+    #[test]
+    fn patch_is_the_last_component() {
+        let parse = |name: &str| parse_version_dir(Path::new(name), false);
+        assert_eq!(Some(Version::new(1, 10, 2, "1.10.2")), parse("1.10.2"));
+        assert_eq!(None, parse("1.2.3.4"));
+        assert_eq!(None, parse("1.10.2."));
+    }
+
+    #[test]
+    fn version_ordering_matches_tuple_comparator() {
+        // the comparator used before `Version` was introduced
+        fn compare(a: &(u32, u32, u32), b: &(u32, u32, u32)) -> Ordering {
+            match a.0.cmp(&b.0) {
+                Ordering::Equal => {
+                    match a.1.cmp(&b.1) {
+                        Ordering::Equal => a.2.cmp(&b.2),
+                        other => other,
+                    }
+                }
+                other => other,
+            }
+        }
+
+        let numbers = vec![(2, 0, 0), (1, 10, 2), (1, 6, 0), (1, 10, 0), (0, 12, 1)];
+        let version = |&(major, minor, patch): &(u32, u32, u32)| {
+            Version::new(major, minor, patch, &format!("{}.{}.{}", major, minor, patch))
+        };
+
+        for a in &numbers {
+            for b in &numbers {
+                assert_eq!(compare(a, b), version(a).cmp(&version(b)), "{:?} vs {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn menu_data() {
+        let versions =
+            vec![Version::new(1, 10, 0, "1.10"),
+                 Version::new(1,  9, 0, "1.9"),
+                 Version::new(1,  6, 0, "1.6")];
+        let data = make_menu_data(&versions, DEFAULT_LABEL_FORMAT);
+
+        let vers = data.get("versions").and_then(|v| v.as_array())
+            .expect("versions should not be None.");
+        for (expected, actual) in versions.iter().zip(vers.iter()) {
+            assert_eq!(Some(expected.display.as_str()),
+                       actual.find("version").and_then(|v| v.as_string()));
+        }
+        assert_eq!(Some(false), data.get("no_versions").and_then(|v| v.as_boolean()));
+    }
+
+    #[test]
+    fn only_the_newest_is_latest() {
+        let versions =
+            vec![Version::new(1, 10, 0, "1.10"),
+                 Version::new(1,  9, 0, "1.9"),
+                 Version::new(1,  6, 0, "1.6")];
+        let is_latest = |vers: &[Version]| -> Vec<bool> {
+            make_menu_data(vers, DEFAULT_LABEL_FORMAT)["versions"].as_array().unwrap()
+                .iter()
+                .map(|ver| ver.find("is_latest").and_then(|v| v.as_boolean()).unwrap())
+                .collect()
+        };
+        assert_eq!(vec![true, false, false], is_latest(&versions));
+
+        let asc: Vec<Version> = versions.iter().rev().cloned().collect();
+        assert_eq!(vec![false, false, true], is_latest(&asc));
+        assert!(is_latest(&[]).is_empty());
+    }
+
+    #[test]
+    fn menu_data_grouped_by_major() {
+        let versions =
+            vec![Version::channel("nightly"),
+                 Version::new(2,  1, 0, "2.1"),
+                 Version::new(2,  0, 0, "2.0"),
+                 Version::new(1, 10, 0, "1.10"),
+                 Version::new(1,  9, 0, "1.9"),
+                 Version::new(0, 12, 0, "0.12")];
+        let data = make_menu_data(&versions, DEFAULT_LABEL_FORMAT);
+
+        let majors: Vec<(&str, Vec<&str>)> = data["majors"].as_array().unwrap()
+            .iter()
+            .map(|group| {
+                let major = group.find("major").and_then(|m| m.as_string()).unwrap();
+                let vers = group.find("versions").and_then(|v| v.as_array()).unwrap()
+                    .iter()
+                    .map(|ver| ver.find("version").and_then(|v| v.as_string()).unwrap())
+                    .collect();
+                (major, vers)
+            })
+            .collect();
+        assert_eq!(vec![("nightly", vec!["nightly"]),
+                        ("2", vec!["2.1", "2.0"]),
+                        ("1", vec!["1.10", "1.9"]),
+                        ("0", vec!["0.12"])],
+                   majors);
+    }
+
+    #[test]
+    fn group_versions_by_major() {
+        let versions =
+            vec![Version::new(1, 10, 0, "1.10"),
+                 Version::new(2,  0, 0, "2.0"),
+                 Version::new(1,  9, 0, "1.9")];
+        assert_eq!(vec![("1".to_string(), vec![0, 2]), ("2".to_string(), vec![1])],
+                   group_by_major(&versions));
+        assert!(group_by_major(&[]).is_empty());
+    }
+
+    #[test]
+    fn menu_html() {
+        let versions = vec![Version::new(1, 10, 0, "1.10"),
+                            Version::new(1, 9, 0, "1.9"),
+                            Version::channel("nightly")];
+        let html = render_menu_html(&versions).unwrap();
+        for ver in &versions {
+            assert!(html.contains(&format!(r#"<a href="{}/index.html">"#, ver.display)),
+                    "{}: {}",
+                    ver.display,
+                    html);
+        }
+    }
+
+    #[test]
+    fn export() {
+        let doc_root = make_doc_root(&["1.9", "1.10 "]);
+        fs::create_dir(doc_root.path().join("1.9/book")).unwrap();
+        fs::write(doc_root.path().join("1.9/book/index.html"), "the 1.9 book").unwrap();
+        fs::write(doc_root.path().join("1.10 /index.html"), "the 1.10 docs").unwrap();
+        let out = TempDir::new().unwrap();
+        let out_dir = out.path().join("site");
+
+        let dir = doc_root.path().to_str().unwrap();
+        let count = export_site(dir, &ScanOptions::default(), &out_dir).unwrap();
+        assert_eq!(2, count);
+        let index = fs::read_to_string(out_dir.join("index.html")).unwrap();
+        assert!(index.contains(r#"<a href="1.10/index.html">"#), "{}", index);
+        assert!(index.contains(r#"<a href="1.9/index.html">"#), "{}", index);
+        let read = |path: &str| fs::read_to_string(out_dir.join(path)).unwrap();
+        assert_eq!("the 1.9 book", read("1.9/book/index.html"));
+        assert_eq!("the 1.10 docs", read("1.10/index.html"));
+    }
+
+    #[test]
+    fn export_without_doc_root() {
+        let out = TempDir::new().unwrap();
+        let missing = out.path().join("missing");
+        let site = out.path().join("site");
+        assert!(export_site(missing.to_str().unwrap(), &ScanOptions::default(), &site).is_err());
+    }
+
+    #[test]
+    fn version_labels() {
+        let versions = vec![Version::new(1, 10, 0, "1.10")];
+        let label = |format: &str| {
+            make_menu_data(&versions, format)["versions"][0]["label"].as_string().map(String::from)
+        };
+        assert_eq!(Some("1.10".to_string()), label(DEFAULT_LABEL_FORMAT));
+        assert_eq!(Some("バージョン 1.10".to_string()), label("バージョン {version}"));
+        assert_eq!(Some("1.10".to_string()), label(""));
+        assert_eq!(Some("1.10".to_string()), label("Version"));
+        assert_eq!(Some("1.10".to_string()), label("{name} {version}"));
+    }
+
+    #[test]
+    fn labels_in_the_menu() {
+        let doc_root = make_doc_root(&["1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.version_label_format = "Version {version}".to_string();
+        let base = serve_config(&config);
+
+        let (_, _, body) = get(&format!("{}/", base));
+        assert!(body.contains("<td>Rust Version 1.10</td>"), "{}", body);
+    }
+
+    #[test]
+    fn menu_data_without_versions() {
+        let data = make_menu_data(&[], DEFAULT_LABEL_FORMAT);
+
+        assert_eq!(Some(0), data.get("versions").and_then(|v| v.as_array()).map(|v| v.len()));
+        assert_eq!(Some(true), data.get("no_versions").and_then(|v| v.as_boolean()));
+    }
+
+    #[test]
+    fn api_versions() {
+        let doc_root = make_doc_root(&["1.9", "1.10", "1.6", "1.10.2", "assets"]);
+        let versions = versions_in(doc_root.path().to_str().unwrap());
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve(build_server(&config, versions.clone()));
+
+        let (status, content_type, body) = get(&format!("{}/api/versions", base_url));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+
+        let actual: Vec<String> = json::decode(&body).unwrap();
+        let expected: Vec<String> = versions.into_iter().map(|ver| ver.display).collect();
+        assert_eq!(vec!["1.10.2", "1.10", "1.9", "1.6"], expected);
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn api_versions_yaml() {
+        let doc_root = make_doc_root(&["1.9", "1.10", "1.10.2"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve_config(&config);
+
+        let (status, headers, body) =
+            get_with_headers(&format!("{}/api/versions.yaml", base_url), Headers::new());
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(&b"application/yaml"[..]),
+                   headers.get_raw("Content-Type").map(|v| &v[0][..]));
+        let body = String::from_utf8(body).unwrap();
+        assert_eq!("- \"1.10.2\"\n- \"1.10\"\n- \"1.9\"\n", body);
+
+        // read it back as a YAML sequence of double quoted strings
+        let actual: Vec<String> = body.lines()
+            .map(|line| json::decode(line.strip_prefix("- ").unwrap()).unwrap())
+            .collect();
+        assert_eq!(vec!["1.10.2", "1.10", "1.9"], actual);
+    }
+
+    #[test]
+    fn versions_yaml_without_versions() {
+        assert_eq!("[]\n", make_versions_yaml(&[]));
+    }
+
+    #[test]
+    fn api_version_details() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        fs::write(doc_root.path().join("1.10/meta.json"), r#"{"release_date": "2016-07-07"}"#)
+            .unwrap();
+        fs::write(doc_root.path().join("1.10/RELEASE.md"), "Slices\n").unwrap();
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base = serve_config(&config);
+
+        let (status, content_type, body) = get(&format!("{}/api/versions/1.10", base));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"{"notes":"Slices","release_date":"2016-07-07","version":"1.10"}"#, body);
+        let (status, _, body) = get(&format!("{}/api/versions/1.9", base));
+        assert_eq!((StatusCode::Ok, r#"{"version":"1.9"}"#), (status, body.as_str()));
+    }
+
+    #[test]
+    fn api_unknown_version() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base = serve_config(&config);
+
+        let (status, content_type, body) = get(&format!("{}/api/versions/9.9", base));
+        assert_eq!(StatusCode::NotFound, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"{"error":"not_found","path":"/api/versions/9.9"}"#, body);
+    }
+
+    #[test]
+    fn api_version_files() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        fs::create_dir(doc_root.path().join("1.10/book")).unwrap();
+        fs::write(doc_root.path().join("1.10/book/index.html"), "").unwrap();
+        fs::write(doc_root.path().join("1.10/index.html"), "").unwrap();
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base = serve_config(&config);
+
+        let (status, content_type, body) = get(&format!("{}/api/versions/1.10/files", base));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"["book/","index.html"]"#, body);
+        let url = format!("{}/api/versions/1.10/files?recursive=true", base);
+        assert_eq!(r#"["book/","book/index.html","index.html"]"#, get(&url).2);
+        assert_eq!("[]", get(&format!("{}/api/versions/1.9/files", base)).2);
+    }
+
+    #[test]
+    fn api_files_of_an_unknown_version() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base = serve_config(&config);
+
+        let (status, content_type, body) = get(&format!("{}/api/versions/9.9/files", base));
+        assert_eq!(StatusCode::NotFound, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"{"error":"not_found","path":"/api/versions/9.9/files"}"#, body);
+        let (status, _, _) = get(&format!("{}/api/versions/%2E%2E/files", base));
+        assert_eq!(StatusCode::NotFound, status);
+    }
+
+    #[test]
+    fn healthz_without_doc_root() {
+        let doc_root = TempDir::new().unwrap();
+        let missing = doc_root.path().join("missing");
+        let config = Config::new(missing.to_str().unwrap());
+        let base_url = serve(build_server(&config, Vec::new()));
+
+        let (status, content_type, body) = get(&format!("{}/healthz", base_url));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"{"status":"ok"}"#, body);
+    }
+
+    #[test]
+    fn doc_root_from_env() {
+        let _guard = EnvGuard::set("DOC_ROOT", Some("/srv/docs"));
+        assert_eq!("/srv/docs", resolve_doc_root());
+    }
+
+    #[test]
+    fn doc_root_default() {
+        let _guard = EnvGuard::set("DOC_ROOT", None);
+        assert_eq!("public", resolve_doc_root());
+    }
+
+    #[test]
+    fn not_found_page() {
+        let doc_root = make_doc_root(&["1.10", "1.9"]);
+        let doc_root = doc_root.path().to_str().unwrap();
+        let config = Config::new(doc_root);
+        let base_url = serve(build_server(&config, versions_in(doc_root)));
+
+        let (status, content_type, body) = get(&format!("{}/1.10/missing.html", base_url));
+        assert_eq!(StatusCode::NotFound, status);
+        assert_eq!(Some(ContentType(MediaType::Html.into())), content_type);
+        assert!(body.contains("/1.10/missing.html"));
+        assert!(body.contains("Rust 1.9"));
+    }
+
+    #[test]
+    fn unknown_version_page() {
+        let doc_root = make_doc_root(&["1.10", "1.9"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base = serve_config(&config);
+
+        let (status, _, body) = get(&format!("{}/9.9/index.html", base));
+        assert_eq!(StatusCode::NotFound, status);
+        assert!(body.contains("<code>9.9</code>"), "{}", body);
+        assert!(body.contains(r#"<a href="/1.10/index.html">Rust 1.10</a>"#), "{}", body);
+
+        // a known version with a missing file gets the generic page
+        let (status, _, body) = get(&format!("{}/1.10/missing.html", base));
+        assert_eq!(StatusCode::NotFound, status);
+        assert!(!body.contains("<code>9.9</code>"), "{}", body);
+        assert!(body.contains("<code>/1.10/missing.html</code>"), "{}", body);
+    }
+
+    #[test]
+    fn summary() {
+        let mut config = Config::new("public");
+        config.home_template = "assets/dark/home.mustache".to_string();
+        let vers = vec![Version::new(1, 10, 0, "1.10"), Version::new(1, 9, 0, "1.9")];
+        assert_eq!("Doc root: public\n\
+                    Listening on: 127.0.0.1:5000\n\
+                    Versions: 2 (1.10, 1.9)\n\
+                    Templates: assets/dark/home.mustache, assets/404.mustache, \
+                    assets/version_404.mustache\n",
+                   startup_summary(&config, "127.0.0.1", 5000, &vers));
+    }
+
+    #[test]
+    fn quiet_from_env() {
+        let _guard = EnvGuard::set("QUIET", Some("true"));
+        assert!(is_quiet());
+    }
+
+    #[test]
+    fn not_quiet_by_default() {
+        let _guard = EnvGuard::set("QUIET", None);
+        assert!(!is_quiet());
+    }
+
+    #[test]
+    fn unknown_versions() {
+        let vers = vec![Version::new(1, 10, 0, "1.10"), Version::channel("nightly")];
+        assert_eq!(Some("9.9"), unknown_version(&vers, "/9.9/index.html"));
+        assert_eq!(Some("v2.0.1"), unknown_version(&vers, "/v2.0.1"));
+        assert_eq!(Some("beta"), unknown_version(&vers, "/beta/"));
+        assert_eq!(None, unknown_version(&vers, "/1.10/missing.html"));
+        assert_eq!(None, unknown_version(&vers, "/nightly/missing.html"));
+        assert_eq!(None, unknown_version(&vers, "/missing.html"));
+        assert_eq!(None, unknown_version(&vers, "/"));
+    }
+
+    #[test]
+    fn not_found_as_json() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base = serve_config(&config);
+
+        let mut headers = Headers::new();
+        headers.set(Accept(vec![qitem(Mime(TopLevel::Application, SubLevel::Json, vec![]))]));
+        let (status, headers, body) =
+            get_with_headers(&format!("{}/1.9/missing.html", base), headers);
+        assert_eq!(StatusCode::NotFound, status);
+        assert_eq!(Some(&ContentType(MediaType::Json.into())), headers.get());
+        assert_eq!(r#"{"error":"not_found","path":"/1.9/missing.html"}"#,
+                   String::from_utf8(body).unwrap());
+    }
+
+    #[test]
+    fn auth_errors_as_json() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.basic_auth = Some(("admin".to_string(), "secret".to_string()));
+        let base = serve_config(&config);
+
+        let mut headers = Headers::new();
+        headers.set(Accept(vec![qitem(Mime(TopLevel::Application, SubLevel::Json, vec![]))]));
+        let (status, _, body) = get_with_headers(&format!("{}/api/versions", base), headers);
+        assert_eq!(StatusCode::Unauthorized, status);
+        assert_eq!(r#"{"error":"unauthorized","path":"/api/versions"}"#,
+                   String::from_utf8(body).unwrap());
+    }
+
+    #[test]
+    fn listen_address_from_env() {
+        let _guard = EnvGuard::set("LISTEN_ADDRESS", Some("127.0.0.1"));
+        assert_eq!("127.0.0.1", get_listen_address());
+    }
+
+    #[test]
+    fn listen_address_default() {
+        let _guard = EnvGuard::set("LISTEN_ADDRESS", None);
+        assert_eq!("0.0.0.0", get_listen_address());
+    }
+
+    #[test]
+    fn server_port() {
+        let _guard = EnvGuard::set("PORT", Some("5000"));
+        assert_eq!(Ok(5000), get_server_port());
+    }
+
+    #[test]
+    fn server_port_default() {
+        let _guard = EnvGuard::set("PORT", None);
+        assert_eq!(Ok(6767), get_server_port());
+    }
+
+    #[test]
+    fn bind_address_from_env() {
+        let _guard = EnvGuard::set("BIND", Some("127.0.0.1:8080"));
+        assert_eq!(Ok(("127.0.0.1".to_string(), 8080)), get_bind_address(&[]));
+        let args = vec!["--port".to_string(), "9000".to_string()];
+        assert_eq!(Ok(("127.0.0.1".to_string(), 9000)), get_bind_address(&args));
+    }
+
+    #[test]
+    fn bind_address_default() {
+        let _guard = EnvGuard::set("BIND", None);
+        assert_eq!(Ok((get_listen_address(), get_server_port().unwrap())), get_bind_address(&[]));
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn port_flag_wins_over_env() {
+        let _guard = EnvGuard::set("PORT", Some("5000"));
+        assert_eq!(Ok(8080), resolve_port(&args(&["--port", "8080"])));
+        assert_eq!(Ok(8081), resolve_port(&args(&["--check", "--port=8081"])));
+        assert_eq!(Ok(5000), resolve_port(&args(&["--check"])));
+    }
+
+    #[test]
+    fn port_default_without_flag_or_env() {
+        let _guard = EnvGuard::set("PORT", None);
+        assert_eq!(Ok(6767), resolve_port(&[]));
+    }
+
+    #[test]
+    fn invalid_port_flags() {
+        assert!(resolve_port(&args(&["--port"])).unwrap_err().contains("--port needs"));
+        assert!(resolve_port(&args(&["--port", "http"])).unwrap_err().contains("'http'"));
+        assert!(resolve_port(&args(&["--port=70000"])).unwrap_err().contains("'70000'"));
+    }
+
+    #[test]
+    fn invalid_port_env_is_an_error_without_flag() {
+        let _guard = EnvGuard::set("PORT", Some("http"));
+        assert!(resolve_port(&[]).unwrap_err().contains("Invalid PORT value 'http'"));
+        assert_eq!(Ok(8080), resolve_port(&args(&["--port", "8080"])));
+    }
+
+    #[test]
+    fn parse_binds() {
+        let bind = |host: &str, port| Ok((host.to_string(), port));
+        assert_eq!(bind("0.0.0.0", 6767), parse_bind("0.0.0.0:6767"));
+        assert_eq!(bind("localhost", 80), parse_bind(" localhost:80 "));
+        assert_eq!(bind("::1", 8080), parse_bind("[::1]:8080"));
+        assert_eq!(bind("::", 8080), parse_bind("[::]:8080"));
+        assert_eq!(bind("fe80::1%eth0", 443), parse_bind("[fe80::1%eth0]:443"));
+    }
+
+    #[test]
+    fn invalid_binds() {
+        for bind in &["", "0.0.0.0", "0.0.0.0:", ":8080", "[]:8080", "::1:8080", "[::1]",
+                      "localhost:http", "localhost:65536", "localhost:-1"] {
+            let result = parse_bind(bind);
+            assert!(result.is_err(), "{}: {:?}", bind, result);
+            assert!(result.unwrap_err().starts_with("Invalid BIND value"));
+        }
+    }
+
+    #[test]
+    fn invalid_server_ports() {
+        assert!(parse_port("").unwrap_err().contains("''"));
+        assert!(parse_port("http").unwrap_err().contains("'http'"));
+        assert!(parse_port("70000").unwrap_err().contains("'70000'"));
+        assert!(parse_port("-1").is_err());
+    }
+
+    #[test]
+    fn directory_listing() {
+        let doc_root = make_doc_root(&["1.10", "other"]);
+        fs::create_dir_all(doc_root.path().join("1.10/book/src")).unwrap();
+        fs::write(doc_root.path().join("1.10/book/ch01.html"), "<p>Ch1</p>").unwrap();
+        fs::write(doc_root.path().join("1.10/index.html"), "<p>Rust 1.10</p>").unwrap();
+        fs::create_dir(doc_root.path().join("other/files")).unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+
+        // not found without ENABLE_DIR_LISTING
+        let base_url = serve(build_server(&config, versions.clone()));
+        let (status, _, _) = get(&format!("{}/1.10/book/", base_url));
+        assert_eq!(StatusCode::NotFound, status);
+
+        config.dir_listing = true;
+        let base_url = serve(build_server(&config, versions));
+        let (status, content_type, body) = get(&format!("{}/1.10/book/", base_url));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType(MediaType::Html.into())), content_type);
+        assert!(body.contains("<a href=\"ch01.html\">ch01.html</a>"), "{}", body);
+        assert!(body.contains("<a href=\"src/\">src/</a>"), "{}", body);
+
+        // the default document wins
+        let (status, _, body) = get(&format!("{}/1.10/", base_url));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!("<p>Rust 1.10</p>", body);
+
+        // only under the versions
+        let (status, _, _) = get(&format!("{}/other/files/", base_url));
+        assert_eq!(StatusCode::NotFound, status);
+    }
+
+    #[test]
+    fn server_info() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.app_version = "v42".to_string();
+        let base_url = serve_config(&config);
+
+        let (status, content_type, body) = get(&format!("{}/api/info", base_url));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        let info = json::Json::from_str(&body).unwrap();
+        assert!(info.find("uptime_secs").and_then(|v| v.as_f64()).unwrap() >= 0.0);
+        assert_eq!(Some(2), info.find("versions").and_then(|v| v.as_u64()));
+        assert_eq!(Some("v42"), info.find("app_version").and_then(|v| v.as_string()));
+    }
+
+    #[test]
+    fn cors_for_the_api() {
+        let doc_root = make_doc_root(&["1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+        let origin = |headers: &mut Headers| {
+            headers.set_raw("Origin", vec![b"https://app.example.com".to_vec()]);
+        };
+        let allow_origin = |headers: &Headers| {
+            headers.get_raw("Access-Control-Allow-Origin").map(|v| v[0].clone())
+        };
+
+        // no CORS headers without CORS_ALLOW_ORIGIN
+        let base_url = serve(build_server(&config, versions.clone()));
+        let mut headers = Headers::new();
+        origin(&mut headers);
+        let (_, res_headers, _) = get_with_headers(&format!("{}/api/versions", base_url), headers);
+        assert_eq!(None, allow_origin(&res_headers));
+
+        config.cors_allow_origin = Some("https://app.example.com".to_string());
+        let base_url = serve(build_server(&config, versions));
+        let mut headers = Headers::new();
+        origin(&mut headers);
+        let (status, res_headers, body) =
+            get_with_headers(&format!("{}/api/versions", base_url), headers.clone());
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(b"https://app.example.com".to_vec()), allow_origin(&res_headers));
+        assert_eq!(b"[\"1.10\"]", &body[..]);
+
+        // not for the pages
+        let (_, res_headers, _) = get_with_headers(&format!("{}/", base_url), headers.clone());
+        assert_eq!(None, allow_origin(&res_headers));
+
+        // preflight
+        headers.set_raw("Access-Control-Request-Method", vec![b"GET".to_vec()]);
+        headers.set_raw("Access-Control-Request-Headers", vec![b"accept".to_vec()]);
+        let res = Client::new()
+            .request(Method::Options, &format!("{}/api/versions", base_url))
+            .headers(headers)
+            .send()
+            .unwrap();
+        assert_eq!(StatusCode::NoContent, res.status);
+        assert_eq!(Some(b"https://app.example.com".to_vec()), allow_origin(&res.headers));
+        assert_eq!(Some(&b"GET, HEAD, OPTIONS"[..]),
+                   res.headers.get_raw("Access-Control-Allow-Methods").map(|v| &v[0][..]));
+        assert_eq!(Some(&b"accept"[..]),
+                   res.headers.get_raw("Access-Control-Allow-Headers").map(|v| &v[0][..]));
+    }
+
+    #[test]
+    fn redirects_moved_pages() {
+        let doc_root = make_doc_root(&["1.10"]);
+        fs::write(doc_root.path().join("1.10/index.html"), "<p>Rust 1.10</p>").unwrap();
+        fs::write(doc_root.path().join("1.10/new.html"), "<p>New</p>").unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.redirects = vec![("/1.10/old.html".to_string(), "/1.10/new.html".to_string())];
+        let base_url = serve_config(&config);
+
+        let (status, headers, _) =
+            get_with_headers(&format!("{}/1.10/old.html?q=1", base_url), Headers::new());
+        assert_eq!(StatusCode::MovedPermanently, status);
+        assert_eq!(Some(&Location("/1.10/new.html?q=1".to_string())), headers.get());
+
+        let (status, _, body) = get(&format!("{}/1.10/index.html", base_url));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!("<p>Rust 1.10</p>", body);
+    }
+
+    #[test]
+    fn redirects_file_from_env() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("redirects.txt");
+        fs::write(&path, "/old.html /new.html
+broken
+").unwrap();
+        {
+            let _guard = EnvGuard::set("REDIRECTS", path.to_str());
+            assert_eq!(Ok(vec![("/old.html".to_string(), "/new.html".to_string())]),
+                       get_redirects());
+        }
+        let missing = dir.path().join("missing.txt");
+        let _guard = EnvGuard::set("REDIRECTS", missing.to_str());
+        assert!(get_redirects().unwrap_err().contains("missing.txt"));
+    }
+
+    #[test]
+    fn not_found_by_area() {
+        let doc_root = make_doc_root(&["1.10"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve_config(&config);
+
+        let (status, content_type, body) = get(&format!("{}/api/missing?q=1", base_url));
+        assert_eq!(StatusCode::NotFound, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"{"error":"not_found","path":"/api/missing"}"#, body);
+
+        let (status, content_type, body) = get(&format!("{}/missing.html", base_url));
+        assert_eq!(StatusCode::NotFound, status);
+        assert_eq!(Some(ContentType(MediaType::Html.into())), content_type);
+        assert!(body.contains("/missing.html"), "{}", body);
+    }
+
+    #[test]
+    fn older_versions_are_dropped() {
+        let mut versions = vec![Version::new(1, 6, 0, "1.6"),
+                                Version::new(1, 9, 0, "1.9"),
+                                Version::new(1, 10, 2, "1.10.2"),
+                                Version::channel("nightly")];
+        drop_older_versions(&mut versions, &Version::new(1, 9, 0, "1.9"));
+        assert_eq!(vec!["1.9", "1.10.2", "nightly"],
+                   versions.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn invalid_min_version_is_ignored() {
+        let _guard = EnvGuard::set("MIN_VERSION", Some("nightly"));
+        assert!(get_min_version().is_none());
+    }
+
+    #[test]
+    fn min_version_hides_older_versions_from_the_menu() {
+        let doc_root = make_doc_root(&["1.6", "1.9", "1.10"]);
+        fs::write(doc_root.path().join("1.6/index.html"), "<p>Rust 1.6</p>").unwrap();
+        let scan = ScanOptions {
+            min_version: Some(Version::new(1, 9, 0, "1.9")),
+            ..ScanOptions::default()
+        };
+        let versions = get_versions(doc_root.path().to_str().unwrap(), &scan).unwrap();
+        assert_eq!(vec!["1.10", "1.9"],
+                   versions.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve(build_server(&config, versions));
+        let (_, _, body) = get(&format!("{}/api/versions", base_url));
+        assert_eq!(r#"["1.10","1.9"]"#, body);
+        let (_, _, body) = get(&format!("{}/", base_url));
+        assert!(body.contains("Rust 1.9") && !body.contains("Rust 1.6"), "{}", body);
+        let (status, _, body) = get(&format!("{}/1.6/index.html", base_url));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!("<p>Rust 1.6</p>", body);
+    }
+
+    #[test]
+    fn versions_pages() {
+        let vers: Vec<Version> = ["1.10", "1.9", "1.8", "1.7", "1.6"]
+            .iter()
+            .map(|display| Version::new(1, display[2..].parse().unwrap(), 0, display))
+            .collect();
+        let page = |limit, offset| {
+            let page = json::Json::from_str(&make_versions_page(&vers, limit, offset).unwrap())
+                .unwrap();
+            let displays: Vec<String> = page["versions"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| v.as_string().unwrap().to_string())
+                .collect();
+            (displays, page["total"].as_u64().unwrap(), page["offset"].as_u64().unwrap())
+        };
+
+        // first page
+        assert_eq!((vec!["1.10".to_string(), "1.9".to_string()], 5, 0), page(Some("2"), None));
+        // middle page
+        assert_eq!((vec!["1.8".to_string(), "1.7".to_string()], 5, 2),
+                   page(Some("2"), Some("2")));
+        // the rest
+        assert_eq!((vec!["1.7".to_string(), "1.6".to_string()], 5, 3), page(None, Some("3")));
+        // out of range
+        assert_eq!((vec![], 5, 5), page(Some("2"), Some("99")));
+        assert_eq!(1, page(Some("0"), None).0.len());
+
+        assert!(make_versions_page(&vers, Some("ten"), None).unwrap_err().contains("'ten'"));
+        assert!(make_versions_page(&vers, None, Some("-1")).is_err());
+    }
+
+    #[test]
+    fn paginated_versions_endpoint() {
+        let doc_root = make_doc_root(&["1.6", "1.9", "1.10"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve_config(&config);
+
+        let (status, content_type, body) =
+            get(&format!("{}/api/versions?limit=1&offset=1", base_url));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"{"limit":1,"offset":1,"total":3,"versions":["1.9"]}"#, body);
+
+        // the plain list without the params
+        assert_eq!(r#"["1.10","1.9","1.6"]"#, get(&format!("{}/api/versions", base_url)).2);
+
+        let (status, _, _) = get(&format!("{}/api/versions?limit=x", base_url));
+        assert_eq!(StatusCode::BadRequest, status);
+    }
+
+    #[test]
+    fn known_versions() {
+        let vers = vec![Version::new(1, 10, 0, "1.10"),
+                        Version::new(1, 9, 2, "1.9.2"),
+                        Version::channel("nightly")];
+        assert!(is_known_version(&vers, "1.10", false));
+        assert!(is_known_version(&vers, " V1.10 ", false));
+        assert!(is_known_version(&vers, "1.9.2", false));
+        assert!(is_known_version(&vers, "Nightly", false));
+        assert!(!is_known_version(&vers, "1.9", false));
+        assert!(!is_known_version(&vers, "v1.10", true));
+        for malformed in &["1.x", "1..10", "../1.10", "a/1.10", "1.10\\", ""] {
+            assert!(!is_known_version(&vers, malformed, false), "{:?}", malformed);
+        }
+    }
+
+    #[test]
+    fn validate_endpoint() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve_config(&config);
+
+        let (status, content_type, body) =
+            get(&format!("{}/api/versions/validate?v=1.10", base_url));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"{"exists":true}"#, body);
+        assert_eq!(r#"{"exists":false}"#,
+                   get(&format!("{}/api/versions/validate?v=2.0", base_url)).2);
+        assert_eq!(r#"{"exists":false}"#,
+                   get(&format!("{}/api/versions/validate?v=1.x", base_url)).2);
+        assert_eq!(StatusCode::BadRequest,
+                   get(&format!("{}/api/versions/validate", base_url)).0);
+    }
+
+    #[test]
+    fn assets_mount() {
+        let doc_root = make_doc_root(&["1.10"]);
+        let assets = TempDir::new().unwrap();
+        fs::write(assets.path().join("site.css"), "body {}").unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.assets_dir = assets.path().to_str().unwrap().to_string();
+        config.assets_path = "/static".to_string();
+        let base_url = serve_config(&config);
+
+        let (status, content_type, body) = get(&format!("{}/static/site.css", base_url));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType(MediaType::Css.into())), content_type);
+        assert_eq!("body {}", body);
+        assert_eq!(StatusCode::NotFound, get(&format!("{}/static/missing.css", base_url)).0);
+    }
+
+    #[test]
+    fn assets_path_over_a_version() {
+        let vers = vec![Version::new(1, 10, 0, "1.10")];
+        assert_eq!(Some("1.10".to_string()), assets_version("/1.10/assets", &vers));
+        assert_eq!(None, assets_version("/assets", &vers));
+
+        let _guard = EnvGuard::set("ASSETS_PATH", Some("/"));
+        assert!(get_assets_path().is_err());
+    }
+
+    #[test]
+    fn integrity_endpoint() {
+        let doc_root = make_doc_root(&["1.10"]);
+        fs::write(doc_root.path().join("1.10/index.html"), "abc").unwrap();
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve_config(&config);
+        let url = |path: &str| format!("{}/api/integrity?path={}", base_url, path);
+
+        let (status, content_type, body) = get(&url("/1.10/index.html"));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"{"path":"/1.10/index.html","sha256":"#.to_string() +
+                   r#""ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"}"#,
+                   body);
+
+        let (status, content_type, body) = get(&url("/1.10/missing.html"));
+        assert_eq!(StatusCode::NotFound, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"{"error":"not_found","path":"/api/integrity"}"#, body);
+
+        let (status, _, _) = get(&url("/1.10/../../etc/passwd"));
+        assert_eq!(StatusCode::BadRequest, status);
+        let (status, _, _) = get(&url(""));
+        assert_eq!(StatusCode::BadRequest, status);
+    }
+
+    #[test]
+    fn precompiled_home_template() {
+        let template = compile_template(HOME_TEMPLATE).unwrap();
+        let versions = vec![Version::new(1, 10, 0, "1.10"), Version::new(1, 9, 0, "1.9")];
+        let data = make_menu_data(&versions, DEFAULT_LABEL_FORMAT);
+        for _ in 0..2 {
+            let body = render_compiled(&template, HOME_TEMPLATE, &data).unwrap();
+            let html = String::from_utf8(body).unwrap();
+            assert!(html.contains("Rust 1.10") && html.contains("Rust 1.9"), "{}", html);
+        }
+    }
+
+    #[test]
+    fn maintenance_mode() {
+        let doc_root = make_doc_root(&["1.10"]);
+        fs::write(doc_root.path().join("1.10/index.html"), "<p>Rust 1.10</p>").unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+
+        // off
+        let base_url = serve(build_server(&config, versions.clone()));
+        assert_eq!(StatusCode::Ok, get(&format!("{}/1.10/index.html", base_url)).0);
+
+        config.maintenance = true;
+        let base_url = serve(build_server(&config, versions.clone()));
+        for path in &["/", "/1.10/index.html", "/api/versions"] {
+            let (status, content_type, body) = get(&format!("{}{}", base_url, path));
+            assert_eq!(StatusCode::ServiceUnavailable, status, "{}", path);
+            assert_eq!(Some(ContentType(MediaType::Html.into())), content_type);
+            assert!(body.contains("メンテナンス中"), "{}", body);
+        }
+        assert_eq!(StatusCode::Ok, get(&format!("{}/healthz", base_url)).0);
+
+        // while the file exists
+        let sentinel = doc_root.path().join("maintenance");
+        config.maintenance = false;
+        config.maintenance_file = Some(sentinel.to_str().unwrap().to_string());
+        let base_url = serve(build_server(&config, versions));
+        let url = format!("{}/1.10/index.html", base_url);
+        assert_eq!(StatusCode::Ok, get(&url).0);
+        fs::write(&sentinel, "").unwrap();
+        assert_eq!(StatusCode::ServiceUnavailable, get(&url).0);
+        fs::remove_file(&sentinel).unwrap();
+        assert_eq!(StatusCode::Ok, get(&url).0);
+    }
+
+    #[test]
+    fn newest_versions_by_major() {
+        let versions = vec![Version::new(1, 9, 0, "1.9"),
+                            Version::new(2, 0, 0, "2.0"),
+                            Version::new(1, 10, 2, "1.10.2"),
+                            Version::new(1, 10, 0, "1.10"),
+                            Version::channel("nightly")];
+        let majors = newest_by_major(&versions);
+        assert_eq!(2, majors.len());
+        assert_eq!(Some("1.10.2"), majors.get(&1).map(String::as_str));
+        assert_eq!(Some("2.0"), majors.get(&2).map(String::as_str));
+    }
+
+    #[test]
+    fn major_number_redirects() {
+        let doc_root = make_doc_root(&["1.6", "1.10", "1.9"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve_config(&config);
+
+        for path in &["/1", "/1/", "/1?q=1"] {
+            let (status, headers, _) =
+                get_with_headers(&format!("{}{}", base_url, path), Headers::new());
+            assert_eq!(StatusCode::Found, status, "{}", path);
+            assert_eq!(Some(&Location("/1.10/".to_string())), headers.get());
+        }
+        assert_eq!(StatusCode::NotFound, get(&format!("{}/9", base_url)).0);
+    }
+
+    #[test]
+    fn gzip_static_files() {
+        let doc_root = make_doc_root(&["1.10"]);
+        let html = "<p>Rust</p>".repeat(100);
+        fs::write(doc_root.path().join("1.10/index.html"), &html).unwrap();
+        fs::write(doc_root.path().join("1.10/logo.png"), b"\x89PNG").unwrap();
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve(build_server(&config, Vec::new()));
+
+        let mut gzip = Headers::new();
+        gzip.set(AcceptEncoding(vec![qitem(Encoding::Gzip)]));
+
+        // compressed when accepted
+        let url = format!("{}/1.10/index.html", base_url);
+        let (status, headers, body) = get_with_headers(&url, gzip.clone());
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(&ContentEncoding(vec![Encoding::Gzip])), headers.get());
+        assert!(body.len() < html.len());
+        let mut decompressed = String::new();
+        GzDecoder::new(&body[..]).read_to_string(&mut decompressed).unwrap();
+        assert_eq!(html, decompressed);
+
+        // not compressed when not accepted
+        let (status, headers, body) = get_with_headers(&url, Headers::new());
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(None, headers.get::<ContentEncoding>());
+        assert_eq!(html.as_bytes(), &body[..]);
+
+        // images are not compressed
+        let url = format!("{}/1.10/logo.png", base_url);
+        let (status, headers, body) = get_with_headers(&url, gzip);
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(None, headers.get::<ContentEncoding>());
+        assert_eq!(b"\x89PNG", &body[..]);
+    }
+
+    #[test]
+    fn range_requests() {
+        let doc_root = make_doc_root(&["1.10"]);
+        fs::write(doc_root.path().join("1.10/book.pdf"), "0123456789").unwrap();
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve_config(&config);
+        let url = format!("{}/1.10/book.pdf", base_url);
+        let range = |value: &str| {
+            let mut headers = Headers::new();
+            headers.set_raw("Range", vec![value.as_bytes().to_vec()]);
+            headers
+        };
+        let content_range = |headers: &Headers| {
+            headers.get_raw("Content-Range")
+                .map(|values| String::from_utf8_lossy(&values[0]).into_owned())
+        };
+
+        let (status, headers, body) = get_with_headers(&url, range("bytes=2-5"));
+        assert_eq!(StatusCode::PartialContent, status);
+        assert_eq!(Some("bytes 2-5/10".to_string()), content_range(&headers));
+        assert_eq!(b"2345".to_vec(), body);
+
+        let (status, headers, body) = get_with_headers(&url, range("bytes=7-"));
+        assert_eq!(StatusCode::PartialContent, status);
+        assert_eq!(Some("bytes 7-9/10".to_string()), content_range(&headers));
+        assert_eq!(b"789".to_vec(), body);
+
+        let (status, headers, _) = get_with_headers(&url, range("bytes=20-30"));
+        assert_eq!(StatusCode::RangeNotSatisfiable, status);
+        assert_eq!(Some("bytes */10".to_string()), content_range(&headers));
+
+        // the whole file without a Range
+        let (status, headers, body) = get_with_headers(&url, Headers::new());
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(&AcceptRanges(vec![RangeUnit::Bytes])), headers.get());
+        assert_eq!(b"0123456789".to_vec(), body);
+    }
+
+    #[test]
+    fn precompressed_static_files() {
+        let doc_root = make_doc_root(&["1.10"]);
+        let html = "<p>Rust</p>".repeat(100);
+        fs::write(doc_root.path().join("1.10/book.html"), &html).unwrap();
+        fs::write(doc_root.path().join("1.10/book.html.br"), b"brotli bytes").unwrap();
+        fs::write(doc_root.path().join("1.10/book.html.gz"), b"gzip bytes").unwrap();
+        fs::write(doc_root.path().join("1.10/other.html"), &html).unwrap();
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve(build_server(&config, Vec::new()));
+        let br = Encoding::EncodingExt("br".to_string());
+        let accept = |encodings: Vec<Encoding>| {
+            let mut headers = Headers::new();
+            headers.set(AcceptEncoding(encodings.into_iter().map(qitem).collect()));
+            headers
+        };
+        let url = format!("{}/1.10/book.html", base_url);
+
+        // the brotli file is preferred
+        let (status, headers, body) =
+            get_with_headers(&url, accept(vec![Encoding::Gzip, br.clone()]));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(&ContentEncoding(vec![br.clone()])), headers.get());
+        assert_eq!(Some(&ContentType(MediaType::Html.into())), headers.get());
+        assert_eq!(Some(&b"Accept-Encoding"[..]),
+                   headers.get_raw("Vary").map(|v| &v[0][..]));
+        assert_eq!(b"brotli bytes", &body[..]);
+
+        let (_, headers, body) = get_with_headers(&url, accept(vec![Encoding::Gzip]));
+        assert_eq!(Some(&ContentEncoding(vec![Encoding::Gzip])), headers.get());
+        assert_eq!(b"gzip bytes", &body[..]);
+
+        // the plain file when no encoding is accepted
+        let (status, headers, body) = get_with_headers(&url, Headers::new());
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(None, headers.get::<ContentEncoding>());
+        assert_eq!(html.as_bytes(), &body[..]);
+
+        // compressed on the fly without a precompressed file
+        let url = format!("{}/1.10/other.html", base_url);
+        let (_, headers, body) = get_with_headers(&url, accept(vec![Encoding::Gzip, br]));
+        assert_eq!(Some(&ContentEncoding(vec![Encoding::Gzip])), headers.get());
+        let mut decompressed = String::new();
+        GzDecoder::new(&body[..]).read_to_string(&mut decompressed).unwrap();
+        assert_eq!(html, decompressed);
+    }
+
+    #[test]
+    fn request_logger_sees_path() {
+        let entries = Arc::new(Mutex::new(Vec::new()));
+        let mut server = Nickel::new();
+        let logged = entries.clone();
+        server.utilize(RequestLogger::with_output(move |entry| {
+            logged.lock().unwrap().push(entry.clone());
+        }));
+        server.get("/1.10/index.html", middleware! { "Rust 1.10" });
+        let base_url = serve(server);
+
+        let (status, _, _) = get(&format!("{}/1.10/index.html?q=1", base_url));
+        assert_eq!(StatusCode::Ok, status);
+
+        let entries: Vec<LogEntry> = entries.lock().unwrap().clone();
+        assert_eq!(1, entries.len());
+        assert_eq!("GET", entries[0].method);
+        assert_eq!("/1.10/index.html", entries[0].path);
+        assert_eq!(200, entries[0].status);
+    }
+
+    #[test]
+    fn request_ids() {
+        let entries = Arc::new(Mutex::new(Vec::new()));
+        let mut server = Nickel::new();
+        let logged = entries.clone();
+        server.utilize(RequestId::new());
+        server.utilize(RequestLogger::with_output(move |entry| {
+            logged.lock().unwrap().push(entry.clone());
+        }));
+        server.get("/1.10/index.html", middleware! { "Rust 1.10" });
+        let base_url = serve(server);
+        let url = format!("{}/1.10/index.html", base_url);
+
+        // the router's id is kept
+        let mut headers = Headers::new();
+        headers.set_raw("X-Request-Id", vec![b"f0e1d2c3-router".to_vec()]);
+        let (_, headers, _) = get_with_headers(&url, headers);
+        assert_eq!(Some(&b"f0e1d2c3-router"[..]),
+                   headers.get_raw("X-Request-Id").map(|v| &v[0][..]));
+
+        // one is made when there is none
+        let (_, headers, _) = get_with_headers(&url, Headers::new());
+        let id = headers.get_raw("X-Request-Id")
+            .map(|values| String::from_utf8(values[0].clone()).unwrap());
+        assert_eq!(Some(36), id.as_ref().map(String::len));
+
+        let entries: Vec<LogEntry> = entries.lock().unwrap().clone();
+        assert_eq!(Some("f0e1d2c3-router"), entries[0].request_id.as_deref());
+        assert_eq!(id, entries[1].request_id);
+    }
+
+    #[test]
+    fn log_format_from_env() {
+        let _guard = EnvGuard::set("LOG_FORMAT", Some("json"));
+        assert_eq!(LogFormat::Json, get_log_format());
+    }
+
+    #[test]
+    fn log_format_default() {
+        let _guard = EnvGuard::set("LOG_FORMAT", None);
+        assert_eq!(LogFormat::Plain, get_log_format());
+    }
+
+    #[test]
+    fn cache_validators() {
+        let doc_root = make_doc_root(&["1.10"]);
+        fs::write(doc_root.path().join("1.10/index.html"), "Rust 1.10").unwrap();
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve(build_server(&config, Vec::new()));
+        let url = format!("{}/1.10/index.html", base_url);
+
+        // fresh response
+        let (status, headers, body) = get_with_headers(&url, Headers::new());
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(b"Rust 1.10", &body[..]);
+        let etag = headers.get::<ETag>().cloned().expect("ETag should be set.");
+        let last_modified = headers.get::<LastModified>().cloned()
+            .expect("Last-Modified should be set.");
+
+        // conditional responses
+        let mut if_none_match = Headers::new();
+        if_none_match.set(IfNoneMatch::Items(vec![etag.0.clone()]));
+        let (status, headers, body) = get_with_headers(&url, if_none_match);
+        assert_eq!(StatusCode::NotModified, status);
+        assert_eq!(Some(&etag), headers.get::<ETag>());
+        assert!(body.is_empty());
+
+        let mut if_modified_since = Headers::new();
+        if_modified_since.set(IfModifiedSince(last_modified.0));
+        let (status, _, body) = get_with_headers(&url, if_modified_since);
+        assert_eq!(StatusCode::NotModified, status);
+        assert!(body.is_empty());
+
+        // the file has changed
+        fs::write(doc_root.path().join("1.10/index.html"), "Rust 1.10.1").unwrap();
+        let mut if_none_match = Headers::new();
+        if_none_match.set(IfNoneMatch::Items(vec![etag.0.clone()]));
+        let (status, _, body) = get_with_headers(&url, if_none_match);
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(b"Rust 1.10.1", &body[..]);
+    }
+
+    #[test]
+    fn refresh_versions() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let dir = doc_root.path().to_str().unwrap();
+        let base_url = serve(build_server(&Config::new(dir), versions_in(dir)));
+
+        let (_, _, body) = get(&format!("{}/api/versions", base_url));
+        assert_eq!(r#"["1.9"]"#, body);
+
+        fs::create_dir(doc_root.path().join("1.10")).unwrap();
+        let (_, _, body) = get(&format!("{}/api/versions", base_url));
+        assert_eq!(r#"["1.9"]"#, body);
+
+        let (status, content_type, body) = get(&format!("{}/api/versions/refresh", base_url));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"["1.10","1.9"]"#, body);
+
+        let (_, _, body) = get(&format!("{}/api/versions", base_url));
+        assert_eq!(r#"["1.10","1.9"]"#, body);
+        let (_, _, body) = get(&format!("{}/", base_url));
+        assert!(body.contains("Rust 1.10"));
+    }
+
+    #[test]
+    fn routes_follow_the_refreshed_versions() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.aliases = vec![("/2.0/guide".to_string(), "/1.9/guide/".to_string())];
+        let base_url = serve_config(&config);
+        let location = |path: &str| {
+            let (status, headers, _) =
+                get_with_headers(&format!("{}{}", base_url, path), Headers::new());
+            (status, headers.get::<Location>().map(|loc| loc.0.clone()))
+        };
+
+        assert_eq!(StatusCode::NotFound, location("/2").0);
+        assert_eq!((StatusCode::Found, Some("/1.9/guide/".to_string())),
+                   location("/2.0/guide"));
+
+        fs::create_dir_all(doc_root.path().join("2.0").join("guide")).unwrap();
+        get(&format!("{}/api/versions/refresh", base_url));
+        assert_eq!((StatusCode::Found, Some("/2.0/".to_string())), location("/2"));
+        // the alias would hide the new version now
+        assert_eq!((StatusCode::MovedPermanently, Some("/2.0/guide/".to_string())),
+                   location("/2.0/guide"));
+
+        get(&format!("{}/2.0/", base_url));
+        let (_, _, body) = get(&format!("{}/api/stats", base_url));
+        assert_eq!(r#"{"1.9":0,"2.0":2}"#, body);
+    }
+
+    #[test]
+    fn version_meta() {
+        let doc_root = make_doc_root(&["1.10", "1.9", "1.8"]);
+        fs::write(doc_root.path().join("1.10/meta.json"),
+                  r#"{"release_date": "2016-07-07", "eol": false}"#).unwrap();
+        fs::write(doc_root.path().join("1.8/meta.json"), "not json").unwrap();
+        let versions = versions_in(doc_root.path().to_str().unwrap());
+        assert_eq!(vec!["1.10", "1.9", "1.8"],
+                   versions.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+
+        let data = make_menu_data(&versions, DEFAULT_LABEL_FORMAT);
+        let vers = data["versions"].as_array().unwrap();
+        assert_eq!(Some("1.10"), vers[0].find("version").and_then(|v| v.as_string()));
+        assert_eq!(Some("2016-07-07"),
+                   vers[0].find("release_date").and_then(|v| v.as_string()));
+        assert_eq!(Some(false), vers[0].find("eol").and_then(|v| v.as_boolean()));
+        // just "version", "label" and "is_latest"
+        assert_eq!(3, vers[1].as_object().unwrap().len());
+        assert_eq!(3, vers[2].as_object().unwrap().len());
+    }
+
+    #[test]
+    fn latest_redirect() {
+        let doc_root = make_doc_root(&["1.9", "1.10", "1.6"]);
+        let dir = doc_root.path().to_str().unwrap();
+        let versions = versions_in(dir);
+        let newest = versions[0].display.clone();
+        let base_url = serve(build_server(&Config::new(dir), versions));
+
+        for &(path, expected) in &[("/latest", "/index.html"),
+                                   ("/latest/", "/index.html"),
+                                   ("/latest/book/index.html", "/book/index.html"),
+                                   ("/latest/rust.css?v=1", "/rust.css")] {
+            let (status, headers, _) = get_with_headers(&format!("{}{}", base_url, path),
+                                                        Headers::new());
+            assert_eq!(StatusCode::Found, status, "{}", path);
+            assert_eq!(Some(&Location(format!("/{}{}", newest, expected))), headers.get());
+        }
+    }
+
+    #[test]
+    fn latest_without_versions() {
+        let doc_root = make_doc_root(&[]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve(build_server(&config, Vec::new()));
+
+        let (status, _, body) = get(&format!("{}/latest/index.html", base_url));
+        assert_eq!(StatusCode::NotFound, status);
+        assert!(body.contains("/latest/index.html"));
+    }
+
+    #[test]
+    fn version_list() {
+        assert_eq!(vec!["1.6", "1.7.0", "nightly"], parse_version_list(" 1.6, 1.7.0,,nightly "));
+        assert!(parse_version_list("").is_empty());
+    }
+
+    #[test]
+    fn exclude_specific_versions() {
+        let mut versions =
+            vec![Version::new(1, 10, 0, "1.10"),
+                 Version::new(1,  7, 0, "1.7.0"),
+                 Version::new(1,  7, 0, "1.7"),
+                 Version::new(1,  6, 0, "1.6")];
+
+        exclude_versions(&mut versions, &parse_version_list("1.6,1.7.0,nightly"));
+        assert_eq!(vec![Version::new(1, 10, 0, "1.10"), Version::new(1, 7, 0, "1.7")],
+                   versions);
+    }
+
+    #[test]
+    fn version_dir_index() {
+        let doc_root = make_doc_root(&["1.10"]);
+        fs::write(doc_root.path().join("1.10/index.html"), "Rust 1.10").unwrap();
+        let dir = doc_root.path().to_str().unwrap();
+        let base_url = serve(build_server(&Config::new(dir), versions_in(dir)));
+
+        let (status, headers, _) = get_with_headers(&format!("{}/1.10", base_url),
+                                                    Headers::new());
+        assert_eq!(StatusCode::MovedPermanently, status);
+        assert_eq!(Some(&Location("/1.10/".to_string())), headers.get());
+
+        let (status, _, body) = get(&format!("{}/1.10/", base_url));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!("Rust 1.10", body);
+
+        let (status, _, _) = get(&format!("{}/1.9", base_url));
+        assert_eq!(StatusCode::NotFound, status);
+    }
+
+    #[test]
+    fn home_template_from_env() {
+        let _guard = EnvGuard::set("HOME_TEMPLATE", Some("assets/dark/home.mustache"));
+        assert_eq!(Ok("assets/dark/home.mustache".to_string()), resolve_home_template());
+    }
+
+    #[test]
+    fn home_template_default() {
+        let _guard = EnvGuard::set("HOME_TEMPLATE", None);
+        assert_eq!(Ok("assets/home.mustache".to_string()), resolve_home_template());
+    }
+
+    #[test]
+    fn theme_templates() {
+        let themes = TempDir::new().unwrap();
+        fs::create_dir(themes.path().join("dark")).unwrap();
+        fs::write(themes.path().join("dark/home.mustache"), "{{#versions}}{{/versions}}").unwrap();
+        assert_eq!(Ok(themes.path().join("dark/home.mustache").to_string_lossy().into_owned()),
+                   theme_template(themes.path(), "dark"));
+        let e = theme_template(themes.path(), "light").unwrap_err();
+        assert!(e.contains("Invalid THEME value 'light'"), "{}", e);
+        assert!(theme_template(themes.path(), "../dark").is_err());
+    }
+
+    #[test]
+    fn unknown_theme_is_an_error() {
+        let _guard = EnvGuard::set("THEME", Some("no-such-theme"));
+        assert!(resolve_home_template().is_err());
+    }
+
+    #[test]
+    fn check_templates() {
+        assert_eq!(Ok(()), check_template("assets/home.mustache"));
+        assert_eq!(Ok(()), check_template("assets/404.mustache"));
+        assert_eq!(Ok(()), check_template("assets/version_404.mustache"));
+
+        let dir = TempDir::new().unwrap();
+        let missing = dir.path().join("missing.mustache");
+        assert!(check_template(missing.to_str().unwrap()).unwrap_err().contains("does not exist"));
+
+        let broken = dir.path().join("broken.mustache");
+        fs::write(&broken, "{{#versions}}<td>{{version}}</td>").unwrap();
+        assert!(check_template(broken.to_str().unwrap()).unwrap_err().contains("compile"));
+    }
+
+    #[test]
+    fn channels_and_numbered_versions() {
+        let doc_root = make_doc_root(&["1.9", "nightly", "1.10", "beta", "assets"]);
+        let versions = versions_in(doc_root.path().to_str().unwrap());
+        assert_eq!(vec![Version::channel("nightly"),
+                        Version::channel("beta"),
+                        Version::new(1, 10, 0, "1.10"),
+                        Version::new(1, 9, 0, "1.9")],
+                   versions);
+
+        let data = make_menu_data(&versions, DEFAULT_LABEL_FORMAT);
+        let vers = data["versions"].as_array().unwrap();
+        assert_eq!(Some("nightly"), vers[0].find("version").and_then(|v| v.as_string()));
+        assert_eq!(Some("nightly"), vers[0].find("channel").and_then(|v| v.as_string()));
+        assert_eq!(Some("1.10"), vers[2].find("version").and_then(|v| v.as_string()));
+        assert_eq!(None, vers[2].find("channel"));
+    }
+
+    #[test]
+    fn version_list_has_one_version_per_line() {
+        let doc_root = make_doc_root(&["1.9", "1.10.2", "nightly"]);
+        let versions = versions_in(doc_root.path().to_str().unwrap());
+        assert_eq!("nightly\n1.10.2\n1.9\n", format_version_list(&versions));
+        assert_eq!("", format_version_list(&[]));
+    }
+
+    #[test]
+    fn retry_bind_gives_up_after_the_attempts() {
+        let mut calls = 0;
+        let result: io::Result<()> = retry_bind(3, Duration::from_millis(0), || {
+            calls += 1;
+            Err(io::Error::new(io::ErrorKind::AddrInUse, "in use"))
+        });
+        assert_eq!(io::ErrorKind::AddrInUse, result.unwrap_err().kind());
+        assert_eq!(3, calls);
+    }
+
+    #[test]
+    fn retry_bind_stops_on_success() {
+        let mut calls = 0;
+        let result = retry_bind(3, Duration::from_millis(0), || {
+            calls += 1;
+            if calls < 2 {
+                Err(io::Error::new(io::ErrorKind::AddrInUse, "in use"))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(2, result.unwrap());
+
+        calls = 0;
+        let result: io::Result<()> = retry_bind(0, Duration::from_millis(0), || {
+            calls += 1;
+            Err(io::Error::new(io::ErrorKind::AddrInUse, "in use"))
+        });
+        assert!(result.is_err());
+        assert_eq!(1, calls);
+    }
+
+    #[test]
+    fn bind_retries_from_env() {
+        let _guard = EnvGuard::set("BIND_RETRIES", Some("5"));
+        assert_eq!(Ok(5), get_bind_retries());
+    }
+
+    #[test]
+    fn bind_retries_default() {
+        let _guard = EnvGuard::set("BIND_RETRIES", None);
+        assert_eq!(Ok(3), get_bind_retries());
+    }
+
+    #[test]
+    fn invalid_bind_retries() {
+        let _guard = EnvGuard::set("BIND_RETRIES", Some("many"));
+        assert!(get_bind_retries().is_err());
+    }
+
+    #[test]
+    fn sitemap_lists_every_version() {
+        let doc_root = make_doc_root(&["1.9", "1.10.2", "nightly"]);
+        let versions = versions_in(doc_root.path().to_str().unwrap());
+        let sitemap = make_sitemap("http://example.com", &versions);
+        for ver in &versions {
+            let loc = format!("<loc>http://example.com/{}/index.html</loc>", ver.display);
+            assert!(sitemap.contains(&loc), "{} is missing in {}", loc, sitemap);
+        }
+        assert_eq!(versions.len(), sitemap.matches("<url>").count());
+    }
+
+    #[test]
+    fn sitemap_route() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+        let base = serve(build_server(&config, versions));
+
+        let (status, _, body) = get(&format!("{}/sitemap.xml", base));
+        assert_eq!(StatusCode::Ok, status);
+        assert!(body.contains(&format!("<loc>{}/1.10/index.html</loc>", base)), "{}", body);
+        assert!(body.contains(&format!("<loc>{}/1.9/index.html</loc>", base)), "{}", body);
+    }
+
+    #[test]
+    fn sitemap_behind_a_proxy() {
+        let doc_root = make_doc_root(&["1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        let mut headers = Headers::new();
+        headers.set_raw("X-Forwarded-Proto", vec![b"https".to_vec()]);
+        headers.set_raw("X-Forwarded-Host", vec![b"docs.example.com".to_vec()]);
+
+        let base = serve_config(&config);
+        let (_, _, body) = get_with_headers(&format!("{}/sitemap.xml", base), headers.clone());
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.contains(&format!("<loc>{}/1.10/index.html</loc>", base)), "{}", body);
+
+        config.trust_proxy = true;
+        let base = serve_config(&config);
+        let (_, _, body) = get_with_headers(&format!("{}/sitemap.xml", base), headers);
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.contains("<loc>https://docs.example.com/1.10/index.html</loc>"), "{}", body);
+    }
+
+    #[test]
+    fn redirects_behind_a_proxy() {
+        let doc_root = make_doc_root(&["1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.trust_proxy = true;
+        config.base_path = "/docs".to_string();
+        let base = serve_config(&config);
+
+        let mut headers = Headers::new();
+        headers.set_raw("X-Forwarded-Proto", vec![b"https".to_vec()]);
+        headers.set_raw("X-Forwarded-Host", vec![b"docs.example.com".to_vec()]);
+        let (_, headers, _) = get_with_headers(&format!("{}/docs/latest", base), headers);
+        assert_eq!(Some(&Location("https://docs.example.com/docs/1.10/index.html".to_string())),
+                   headers.get());
+
+        // no forwarded headers, no change
+        let (_, headers, _) = get_with_headers(&format!("{}/docs/latest", base), Headers::new());
+        assert_eq!(Some(&Location("/docs/1.10/index.html".to_string())), headers.get());
+    }
+
+    #[test]
+    fn robots_txt_sources() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let dir = doc_root.path().to_str().unwrap();
+        assert_eq!("User-agent: *\nDisallow:\n", robots_txt(None, dir));
+
+        fs::write(doc_root.path().join("robots.txt"), "User-agent: *\nDisallow: /1.9/\n")
+            .unwrap();
+        assert_eq!("User-agent: *\nDisallow: /1.9/\n", robots_txt(None, dir));
+        assert_eq!("User-agent: *\nDisallow: /\n",
+                   robots_txt(Some("User-agent: *\nDisallow: /\n"), dir));
+    }
+
+    #[test]
+    fn cache_control_on_versions_and_home() {
+        let doc_root = make_doc_root(&["1.9"]);
+        fs::write(doc_root.path().join("1.9/index.html"), "<h1>1.9</h1>").unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.static_max_age = 600;
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+        let base = serve(build_server(&config, versions));
+
+        let (status, headers, _) = get_with_headers(&format!("{}/1.9/index.html", base),
+                                                    Headers::new());
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(&CacheControl(vec![CacheDirective::Public, CacheDirective::MaxAge(600)])),
+                   headers.get::<CacheControl>());
+
+        let (status, headers, _) = get_with_headers(&format!("{}/", base), Headers::new());
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(&CacheControl(vec![CacheDirective::NoCache])),
+                   headers.get::<CacheControl>());
+    }
+
+    #[test]
+    fn static_max_age_from_env() {
+        let _guard = EnvGuard::set("STATIC_MAX_AGE", Some("86400"));
+        assert_eq!(Ok(86400), get_static_max_age());
+    }
+
+    #[test]
+    fn static_max_age_default() {
+        let _guard = EnvGuard::set("STATIC_MAX_AGE", None);
+        assert_eq!(Ok(3600), get_static_max_age());
+    }
+
+    #[test]
+    fn stream_threshold_from_env() {
+        let _guard = EnvGuard::set("STREAM_THRESHOLD", Some("65536"));
+        assert_eq!(Ok(65536), get_stream_threshold());
+    }
+
+    #[test]
+    fn gzip_levels_from_env() {
+        assert_eq!(DEFAULT_GZIP_LEVEL, parse_gzip_level(None));
+        assert_eq!(1, parse_gzip_level(Some("1")));
+        assert_eq!(9, parse_gzip_level(Some(" 9 ")));
+        assert_eq!(1, parse_gzip_level(Some("0")));
+        assert_eq!(1, parse_gzip_level(Some("-3")));
+        assert_eq!(9, parse_gzip_level(Some("12")));
+        assert_eq!(DEFAULT_GZIP_LEVEL, parse_gzip_level(Some("best")));
+        assert_eq!(DEFAULT_GZIP_LEVEL, parse_gzip_level(Some("")));
+    }
+
+    #[test]
+    fn invalid_stream_threshold() {
+        let _guard = EnvGuard::set("STREAM_THRESHOLD", Some("1MB"));
+        assert!(get_stream_threshold().is_err());
+    }
+
+    #[test]
+    fn streams_large_files_gzip_compressed() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let page = "<p>The Rust Programming Language</p>\n".repeat(10_000);
+        fs::write(doc_root.path().join("1.9").join("big.html"), &page).unwrap();
+        fs::write(doc_root.path().join("1.9").join("small.html"), "<p>small</p>").unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.stream_threshold = 1000;
+        let base = serve_config(&config);
+
+        let mut headers = Headers::new();
+        headers.set(AcceptEncoding(vec![qitem(Encoding::Gzip)]));
+        let url = format!("{}/1.9/big.html", base);
+        let (status, res_headers, body) = get_with_headers(&url, headers.clone());
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(&ContentEncoding(vec![Encoding::Gzip])), res_headers.get());
+        let mut decompressed = String::new();
+        GzDecoder::new(&body[..]).read_to_string(&mut decompressed).unwrap();
+        assert_eq!(page, decompressed);
+
+        // small files are compressed in memory
+        let url = format!("{}/1.9/small.html", base);
+        let (_, res_headers, body) = get_with_headers(&url, headers);
+        assert_eq!(Some(&ContentEncoding(vec![Encoding::Gzip])), res_headers.get());
+        let mut decompressed = String::new();
+        GzDecoder::new(&body[..]).read_to_string(&mut decompressed).unwrap();
+        assert_eq!("<p>small</p>", decompressed);
+    }
+
+    #[test]
+    fn overrides_content_types_by_extension() {
+        let doc_root = make_doc_root(&["1.9"]);
+        fs::write(doc_root.path().join("1.9").join("app.wasm"), b"\0asm").unwrap();
+        fs::write(doc_root.path().join("1.9").join("data.dat"), "1,2,3").unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.mime_types.insert("dat".to_string(), "text/csv".parse().unwrap());
+        let base = serve_config(&config);
+
+        let (status, content_type, _) = get(&format!("{}/1.9/app.wasm", base));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!("application/wasm", content_type.unwrap().to_string());
+        let (_, content_type, _) = get(&format!("{}/1.9/data.dat", base));
+        assert_eq!("text/csv", content_type.unwrap().to_string());
+
+        // the 404 page stays HTML
+        let (status, content_type, _) = get(&format!("{}/1.9/missing.wasm", base));
+        assert_eq!(StatusCode::NotFound, status);
+        assert_eq!("text/html", content_type.unwrap().to_string());
+    }
+
+    #[test]
+    fn invalid_mime_types() {
+        let _guard = EnvGuard::set("MIME_TYPES", Some("wasm"));
+        assert!(Config::from_env().is_err());
+    }
+
+    #[test]
+    fn healthy_setup() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        assert_eq!(Vec::<String>::new(), check_setup(&config, "0.0.0.0"));
+    }
+
+    #[test]
+    fn setup_with_problems() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let dir = doc_root.path().to_str().unwrap();
+        let mut config = Config::new(&format!("{},{}/missing", dir, dir));
+        config.home_template = "assets/missing.mustache".to_string();
+        let problems = check_setup(&config, "not an address");
+        assert_eq!(3, problems.len(), "{:?}", problems);
+        assert!(problems[0].contains("LISTEN_ADDRESS"), "{}", problems[0]);
+        assert!(problems[1].contains("missing"), "{}", problems[1]);
+        assert!(problems[2].contains("assets/missing.mustache"), "{}", problems[2]);
+    }
+
+    #[test]
+    fn setup_without_html() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.disable_html = true;
+        config.home_template = "assets/missing.mustache".to_string();
+        assert_eq!(Vec::<String>::new(), check_setup(&config, "0.0.0.0"));
+    }
+
+    #[test]
+    fn html_disabled() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.disable_html = true;
+        // not needed, so not loaded
+        config.home_template = "assets/missing.mustache".to_string();
+        let base_url = serve_config(&config);
+
+        let (status, content_type, body) = get(&format!("{}/", base_url));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"["1.10","1.9"]"#, body);
+
+        let (status, content_type, body) = get(&format!("{}/1.9/missing.html", base_url));
+        assert_eq!(StatusCode::NotFound, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"{"error":"not_found","path":"/1.9/missing.html"}"#, body);
+
+        assert_eq!(r#"["1.10","1.9"]"#, get(&format!("{}/api/versions", base_url)).2);
+    }
+
+    #[test]
+    fn counts_hits_per_version() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        fs::write(doc_root.path().join("1.9").join("index.html"), "the 1.9 docs").unwrap();
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base = serve_config(&config);
+
+        let (_, _, body) = get(&format!("{}/api/stats", base));
+        assert_eq!(r#"{"1.10":0,"1.9":0}"#, body);
+        get(&format!("{}/1.9/index.html", base));
+        get(&format!("{}/1.9/missing.html", base));
+        get(&format!("{}/", base));
+        let (status, content_type, body) = get(&format!("{}/api/stats", base));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"{"1.10":0,"1.9":2}"#, body);
+    }
+
+    /// Starts a server with 1.9/book/index.html and the TRAILING_SLASH policy,
+    /// and returns the status and the Location for each path.
+    fn trailing_slash_redirects(policy: TrailingSlash, paths: &[&str])
+                                -> Vec<(StatusCode, Option<String>)> {
+        let doc_root = make_doc_root(&["1.9"]);
+        fs::create_dir(doc_root.path().join("1.9").join("book")).unwrap();
+        fs::write(doc_root.path().join("1.9").join("index.html"), "the 1.9 docs").unwrap();
+        fs::write(doc_root.path().join("1.9").join("book").join("index.html"), "the book")
+            .unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.trailing_slash = policy;
+        let base = serve_config(&config);
+
+        paths.iter()
+            .map(|path| {
+                let (status, headers, _) =
+                    get_with_headers(&format!("{}{}", base, path), Headers::new());
+                (status, headers.get::<Location>().map(|loc| loc.0.clone()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn trailing_slash_add() {
+        let paths = ["/1.9/book?x=1", "/1.9", "/1.9/", "/1.9/book/index.html", "/api/versions"];
+        let moved = |path: &str| (StatusCode::MovedPermanently, Some(path.to_string()));
+        assert_eq!(vec![moved("/1.9/book/?x=1"),
+                        moved("/1.9/"),
+                        (StatusCode::Ok, None),
+                        (StatusCode::Ok, None),
+                        (StatusCode::Ok, None)],
+                   trailing_slash_redirects(TrailingSlash::Add, &paths));
+    }
+
+    #[test]
+    fn trailing_slash_remove() {
+        let paths = ["/1.9/book/", "/1.9/", "/1.9", "/1.9/book/index.html", "/"];
+        let moved = |path: &str| (StatusCode::MovedPermanently, Some(path.to_string()));
+        assert_eq!(vec![moved("/1.9/book"),
+                        moved("/1.9"),
+                        (StatusCode::Ok, None),
+                        (StatusCode::Ok, None),
+                        (StatusCode::Ok, None)],
+                   trailing_slash_redirects(TrailingSlash::Remove, &paths));
+    }
+
+    #[test]
+    fn trailing_slash_ignore() {
+        let paths = ["/1.9", "/1.9/", "/1.9/book/index.html"];
+        assert_eq!(vec![(StatusCode::Ok, None); 3],
+                   trailing_slash_redirects(TrailingSlash::Ignore, &paths));
+    }
+
+    #[test]
+    fn invalid_trailing_slash() {
+        let _guard = EnvGuard::set("TRAILING_SLASH", Some("sometimes"));
+        assert!(get_trailing_slash().is_err());
+    }
+
+    #[test]
+    fn default_version_redirects_home() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.default_version = Some("1.9".to_string());
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+        let base = serve(build_server(&config, versions));
+
+        let (status, headers, _) = get_with_headers(&format!("{}/", base), Headers::new());
+        assert_eq!(StatusCode::Found, status);
+        assert_eq!(Some(&Location("/1.9/".to_string())), headers.get::<Location>());
+    }
+
+    #[test]
+    fn unknown_default_version_shows_menu() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.default_version = Some("2.0".to_string());
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+        let base = serve(build_server(&config, versions));
+
+        let (status, _, body) = get(&format!("{}/", base));
+        assert_eq!(StatusCode::Ok, status);
+        assert!(body.contains("1.10"), "{}", body);
+    }
+
+    #[test]
+    fn no_default_version_shows_menu() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        assert_eq!(None, config.default_version);
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+        let base = serve(build_server(&config, versions));
+
+        let (status, headers, _) = get_with_headers(&format!("{}/", base), Headers::new());
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(None, headers.get::<Location>());
+    }
+
+    #[test]
+    fn home_negotiates_json_or_html() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+        let base = serve(build_server(&config, versions));
+
+        let mut headers = Headers::new();
+        headers.set(Accept(vec![qitem(Mime(TopLevel::Application, SubLevel::Json, vec![]))]));
+        let (status, headers, body) = get_with_headers(&format!("{}/", base), headers);
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(&ContentType::json()), headers.get::<ContentType>());
+        assert_eq!(r#"["1.10","1.9"]"#, String::from_utf8(body).unwrap());
+
+        let mut headers = Headers::new();
+        headers.set(Accept(vec![qitem(Mime(TopLevel::Text, SubLevel::Html, vec![]))]));
+        let (status, headers, body) = get_with_headers(&format!("{}/", base), headers);
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(&ContentType(MediaType::Html.into())), headers.get::<ContentType>());
+        assert!(String::from_utf8(body).unwrap().contains("<a href=\"1.10/index.html\">"));
+    }
+
+    #[test]
+    fn accept_header_ranking() {
+        let json = Mime(TopLevel::Application, SubLevel::Json, vec![]);
+        let html = Mime(TopLevel::Text, SubLevel::Html, vec![]);
+        let any = Mime(TopLevel::Star, SubLevel::Star, vec![]);
+
+        assert!(!prefers_json(None));
+        assert!(prefers_json(Some(&Accept(vec![qitem(json.clone())]))));
+        assert!(!prefers_json(Some(&Accept(vec![qitem(any.clone())]))));
+        assert!(!prefers_json(Some(&Accept(vec![qitem(html.clone()),
+                                                 QualityItem::new(json.clone(), q(0.9))]))));
+        assert!(prefers_json(Some(&Accept(vec![qitem(json.clone()),
+                                                QualityItem::new(any, q(0.1))]))));
+        assert!(!prefers_json(Some(&Accept(vec![QualityItem::new(json, q(0.0))]))));
+    }
+
+    #[test]
+    fn security_headers_on_rendered_and_static_responses() {
+        let doc_root = make_doc_root(&["1.9"]);
+        fs::write(doc_root.path().join("1.9/index.html"), "<h1>1.9</h1>").unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.content_security_policy = Some("default-src 'self'".to_string());
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+        let base = serve(build_server(&config, versions));
+
+        for path in &["/", "/1.9/index.html"] {
+            let (status, headers, _) = get_with_headers(&format!("{}{}", base, path),
+                                                        Headers::new());
+            assert_eq!(StatusCode::Ok, status, "{}", path);
+            let raw = |name: &str| headers.get_raw(name).map(|v| v[0].clone());
+            assert_eq!(Some(b"nosniff".to_vec()), raw("X-Content-Type-Options"), "{}", path);
+            assert_eq!(Some(b"DENY".to_vec()), raw("X-Frame-Options"), "{}", path);
+            assert_eq!(Some(b"default-src 'self'".to_vec()),
+                       raw("Content-Security-Policy"),
+                       "{}",
+                       path);
+        }
+    }
+
+    #[test]
+    fn version_dir_edge_cases() {
+        let parse = |name: &str| parse_version_dir(Path::new(name), false);
+        assert_eq!(Some(Version::new(1, 2, 3, "1.2.3")), parse("1.2.3"));
+        assert_eq!(Some(Version::new(4294967295, 0, 0, "4294967295.0")), parse("4294967295.0"));
+        assert_eq!(None, parse("9999999999.0"));
+        assert_eq!(None, parse("1.99999999999"));
+        assert_eq!(None, parse("1.2.99999999999"));
+        assert_eq!(None, parse("1."));
+        assert_eq!(None, parse("1"));
+        assert_eq!(None, parse(".1"));
+        // trailing garbage is not dropped
+        assert_eq!(None, parse("1.2.3.4"));
+        assert_eq!(None, parse("1.10abc"));
+        assert_eq!(None, parse("1.10-rc1"));
+        assert_eq!(None, parse("v1.10.2-beta"));
+    }
+
+    #[test]
+    fn dirs_with_trailing_garbage_are_skipped() {
+        let doc_root = make_doc_root(&["1.10", "1.10-rc1", "1.2.3.4"]);
+        let mut report = scan_version_dirs(doc_root.path(), false, false).unwrap();
+        assert_eq!(vec![Version::new(1, 10, 0, "1.10")], report.included);
+        report.skipped.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(vec![("1.10-rc1".to_string(), SkipReason::NoSemverMatch),
+                        ("1.2.3.4".to_string(), SkipReason::NoSemverMatch)],
+                   report.skipped);
+    }
+
+    #[test]
+    fn overflowing_dirs_are_skipped() {
+        let doc_root = make_doc_root(&["9999999999.0", "1.9"]);
+        let versions = versions_in(doc_root.path().to_str().unwrap());
+        assert_eq!(vec![Version::new(1, 9, 0, "1.9")], versions);
+    }
+
+    #[test]
+    fn dir_names_with_whitespace_and_casing() {
+        let doc_root = make_doc_root(&["1.10 ", " 1.9", "Nightly", "1.x", "assets"]);
+        let versions = versions_in(doc_root.path().to_str().unwrap());
+        assert_eq!(vec![Version::channel("nightly"),
+                        Version::new(1, 10, 0, "1.10"),
+                        Version::new(1, 9, 0, "1.9")],
+                   versions);
+        assert_eq!(vec!["Nightly", "1.10 ", " 1.9"],
+                   versions.iter().map(|ver| ver.dir_name.as_str()).collect::<Vec<_>>());
+
+        assert_eq!(Some("/1.10 /book/index.html?q=1".to_string()),
+                   to_dir_uri(&versions, "/1.10/book/index.html?q=1"));
+        assert_eq!(Some("/Nightly".to_string()), to_dir_uri(&versions, "/nightly"));
+        assert_eq!(None, to_dir_uri(&versions, "/1.6/index.html"));
+        assert_eq!(None, to_dir_uri(&versions, "/"));
+    }
+
+    #[test]
+    fn serves_dirs_with_whitespace() {
+        let doc_root = make_doc_root(&["1.10 "]);
+        fs::write(doc_root.path().join("1.10 /index.html"), "<h1>1.10</h1>").unwrap();
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+        let base = serve(build_server(&config, versions));
+
+        let (status, _, body) = get(&format!("{}/1.10/index.html", base));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!("<h1>1.10</h1>", body);
+        let (status, _, body) = get(&format!("{}/1.10/", base));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!("<h1>1.10</h1>", body);
+    }
+
+    #[test]
+    fn metrics_in_prometheus_format() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+        let base = serve(build_server(&config, versions));
+
+        get(&format!("{}/1.9/no-such-page.html", base));
+        let (status, _, body) = get(&format!("{}/metrics", base));
+        assert_eq!(StatusCode::Ok, status);
+
+        let mut names = Vec::new();
+        for line in body.lines().filter(|line| !line.starts_with('#')) {
+            let (name, value) = line.split_at(line.rfind(' ').expect(line));
+            assert!(value.trim().parse::<f64>().is_ok(), "{}", line);
+            names.push(name.split('{').next().unwrap().to_string());
+        }
+        for name in &["http_requests_total", "http_responses_total", "doc_versions"] {
+            assert!(names.iter().any(|n| n == name), "{} is missing in {}", name, body);
+        }
+        assert!(body.contains("http_responses_total{class=\"4xx\"} 1\n"), "{}", body);
+        assert!(body.contains("doc_versions 2\n"), "{}", body);
+    }
+
+    #[test]
+    fn draining_server_turns_requests_away() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let shutdown = config.shutdown.clone();
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+        let base = serve(build_server(&config, versions));
+
+        let (status, _, _) = get(&format!("{}/healthz", base));
+        assert_eq!(StatusCode::Ok, status);
+        // the request is done with just after the client has the response
+        assert!(wait_for_drain(|| shutdown.in_flight(),
+                               Duration::from_secs(1),
+                               Duration::from_millis(5)));
+
+        shutdown.start_draining();
+        let (status, _, _) = get(&format!("{}/healthz", base));
+        assert_eq!(StatusCode::ServiceUnavailable, status);
+    }
+
+    /// Sends "first " and then, 400 ms later, "second" as a streamed body.
+    fn streamed_body(response: Response) -> MiddlewareResult {
+        let mut stream = response.start()?;
+        let sent = stream.write_all(b"first ")
+            .and_then(|_| stream.flush())
+            .and_then(|_| {
+                thread::sleep(Duration::from_millis(400));
+                stream.write_all(b"second")
+            });
+        match sent {
+            Ok(()) => Ok(Action::Halt(stream)),
+            Err(e) => stream.bail(format!("Failed to send: {}", e)),
+        }
+    }
+
+    #[test]
+    fn streamed_bodies_are_in_flight_until_the_end() {
+        let shutdown = Arc::new(Shutdown::default());
+        let mut server = Nickel::new();
+        server.utilize(ShutdownGuard::new(shutdown.clone()));
+        server.get("/stream", handler(|_, response| streamed_body(response)));
+        let base = serve(server);
+
+        let url = format!("{}/stream", base);
+        let client = thread::spawn(move || get(&url));
+        thread::sleep(Duration::from_millis(150));
+        // the headers and the first chunk are out, but not the rest
+        assert_eq!(1, shutdown.in_flight());
+
+        assert_eq!("first second", client.join().unwrap().2);
+        assert!(wait_for_drain(|| shutdown.in_flight(),
+                               Duration::from_secs(1),
+                               Duration::from_millis(5)));
+    }
+
+    #[test]
+    fn drain_timeout_from_env() {
+        let _guard = EnvGuard::set("DRAIN_TIMEOUT", Some("25"));
+        assert_eq!(Ok(25), get_drain_timeout());
+    }
+
+    #[test]
+    fn doc_root_lists() {
+        assert_eq!(vec!["public"], split_doc_roots("public"));
+        assert_eq!(vec!["public", "/srv/archive"], split_doc_roots("public, /srv/archive,"));
+    }
+
+    #[test]
+    fn merge_doc_roots() {
+        let stable = make_doc_root(&["1.10", "1.9"]);
+        let archive = make_doc_root(&["1.9", "1.6", "0.12"]);
+        fs::write(stable.path().join("1.9/meta.json"), r#"{"from": "stable"}"#).unwrap();
+        fs::write(archive.path().join("1.9/meta.json"), r#"{"from": "archive"}"#).unwrap();
+        let doc_root = format!("{},{}", stable.path().display(), archive.path().display());
+
+        let versions = versions_in(&doc_root);
+        assert_eq!(vec!["1.10", "1.9", "1.6", "0.12"],
+                   versions.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+        assert_eq!(Some("stable"), versions[1].meta.get("from").and_then(|v| v.as_string()));
+    }
+
+    #[test]
+    fn serves_files_from_each_doc_root() {
+        let stable = make_doc_root(&["1.10", "1.9"]);
+        let archive = make_doc_root(&["1.9", "1.6"]);
+        fs::write(stable.path().join("1.9/index.html"), "stable 1.9").unwrap();
+        fs::write(archive.path().join("1.9/index.html"), "archive 1.9").unwrap();
+        fs::write(archive.path().join("1.9/old.html"), "archive old").unwrap();
+        fs::write(archive.path().join("1.6/index.html"), "archive 1.6").unwrap();
+        let config = Config::new(&format!("{},{}",
+                                          stable.path().display(),
+                                          archive.path().display()));
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+        let base = serve(build_server(&config, versions));
+
+        assert_eq!("stable 1.9", get(&format!("{}/1.9/index.html", base)).2);
+        assert_eq!("archive old", get(&format!("{}/1.9/old.html", base)).2);
+        assert_eq!("archive 1.6", get(&format!("{}/1.6/", base)).2);
+        assert_eq!(StatusCode::NotFound, get(&format!("{}/1.10/index.html", base)).0);
+    }
+
+    #[test]
+    fn home_falls_back_when_the_template_fails() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.home_template =
+            doc_root.path().join("missing.mustache").to_str().unwrap().to_string();
+        let versions = get_versions(&config.doc_root, &config.scan).unwrap();
+        let base = serve(build_server(&config, versions));
+
+        let (status, content_type, body) = get(&format!("{}/", base));
+        assert_eq!(StatusCode::InternalServerError, status);
+        assert_eq!(Some(ContentType(MediaType::Html.into())), content_type);
+        assert!(body.contains("<a href=\"1.10/index.html\">1.10</a>"), "{}", body);
+        assert!(body.contains("<a href=\"1.9/index.html\">1.9</a>"), "{}", body);
+    }
+
+    #[test]
+    fn favicon_from_doc_root_env_or_none() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base = serve_config(&config);
+        let (status, _, body) = get(&format!("{}/favicon.ico", base));
+        assert_eq!(StatusCode::NoContent, status);
+        assert_eq!("", body);
+
+        fs::write(doc_root.path().join("favicon.ico"), "doc root icon").unwrap();
+        let (status, _, body) = get(&format!("{}/favicon.ico", base));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!("doc root icon", body);
+
+        let icon = doc_root.path().join("1.9/icon.ico");
+        fs::write(&icon, "configured icon").unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.favicon = Some(icon.to_str().unwrap().to_string());
+        let base = serve_config(&config);
+        let (status, _, body) = get(&format!("{}/favicon.ico", base));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!("configured icon", body);
+    }
+
+    #[test]
+    fn basic_auth_credentials() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.basic_auth = Some(("docs".to_string(), "s3cret".to_string()));
+        let base = serve_config(&config);
+        let url = format!("{}/api/versions", base);
+        let with_auth = |password: &str| {
+            let mut headers = Headers::new();
+            headers.set(Authorization(Basic {
+                username: "docs".to_string(),
+                password: Some(password.to_string()),
+            }));
+            headers
+        };
+
+        let (status, headers, _) = get_with_headers(&url, Headers::new());
+        assert_eq!(StatusCode::Unauthorized, status);
+        assert_eq!(Some(&[b"Basic realm=\"Documents\"".to_vec()][..]),
+                   headers.get_raw("WWW-Authenticate"));
+
+        let (status, _, _) = get_with_headers(&url, with_auth("wrong"));
+        assert_eq!(StatusCode::Unauthorized, status);
+
+        let (status, _, body) = get_with_headers(&url, with_auth("s3cret"));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(b"[\"1.9\"]".to_vec(), body);
+
+        let (status, _, _) = get_with_headers(&format!("{}/healthz", base), Headers::new());
+        assert_eq!(StatusCode::Ok, status);
+    }
+
+    #[test]
+    fn v_prefixed_dirs() {
+        let doc_root = make_doc_root(&["v1.9", "1.10", "V1.10.1", "v1.x"]);
+        let dir = doc_root.path().to_str().unwrap();
+        let versions = versions_in(dir);
+        assert_eq!(vec!["1.10.1", "1.10", "1.9"],
+                   versions.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+        assert_eq!(Some("/V1.10.1/index.html".to_string()),
+                   to_dir_uri(&versions, "/1.10.1/index.html"));
+
+        let mut versions = list_version_dirs(doc_root.path(), true, false).unwrap();
+        sort_versions(&mut versions, SortOrder::Asc);
+        versions.reverse();
+        assert_eq!(vec!["v1.10.1", "1.10", "v1.9"],
+                   versions.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn release_notes() {
+        let doc_root = make_doc_root(&["1.9", "1.10", "1.11"]);
+        fs::write(doc_root.path().join("1.10").join("RELEASE.md"),
+                  "\n# Rust 1.10 is out\n\nDetails.\n")
+            .unwrap();
+        fs::write(doc_root.path().join("1.11").join("notes.txt"), "Faster builds\n").unwrap();
+        let mut versions = list_version_dirs(doc_root.path(), true, false).unwrap();
+        sort_versions(&mut versions, SortOrder::Asc);
+
+        let data = make_menu_data(&versions, DEFAULT_LABEL_FORMAT);
+        let maps = data["versions"].as_array().unwrap();
+        assert_eq!(None, maps[0].find("notes"));
+        assert_eq!(Some("Rust 1.10 is out"), maps[1].find("notes").and_then(|n| n.as_string()));
+        assert_eq!(Some("Faster builds"), maps[2].find("notes").and_then(|n| n.as_string()));
+    }
+
+    #[test]
+    fn unprefixed_dirs_keep_their_names() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        let versions = list_version_dirs(doc_root.path(), true, false).unwrap();
+        assert!(versions.iter().all(|ver| ver.display == ver.dir_name));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn broken_symlinks_are_skipped() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        std::os::unix::fs::symlink(doc_root.path().join("gone"), doc_root.path().join("1.11"))
+            .unwrap();
+        let mut versions = list_version_dirs(doc_root.path(), true, false).unwrap();
+        sort_versions(&mut versions, SortOrder::Asc);
+        assert_eq!(vec!["1.9", "1.10"],
+                   versions.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_dirs_are_aliases() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        std::os::unix::fs::symlink(doc_root.path().join("1.10"), doc_root.path().join("stable"))
+            .unwrap();
+        let mut versions = list_version_dirs(doc_root.path(), true, true).unwrap();
+        sort_versions(&mut versions, SortOrder::Asc);
+        assert_eq!(vec!["1.9", "1.10", "stable"],
+                   versions.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+        let stable = &versions[2];
+        assert_eq!(Some("stable"), stable.channel_name());
+        assert_eq!(Some("1.10"), stable.alias_of.as_deref());
+        let map = version_object(stable);
+        assert_eq!(Some("1.10"), map.get("alias_of").and_then(|v| v.as_string()));
+
+        // the alias is not the latest, though it sorts above 1.10
+        assert_eq!(Some("/1.10/index.html".to_string()), latest_path(&versions, None));
+        let data = make_menu_data(&versions, DEFAULT_LABEL_FORMAT);
+        let is_latest: Vec<bool> = data["versions"].as_array().unwrap().iter()
+            .map(|ver| ver.find("is_latest").and_then(|v| v.as_boolean()).unwrap())
+            .collect();
+        assert_eq!(vec![false, true, false], is_latest);
+        assert_eq!(Some(&"1.10".to_string()), newest_by_major(&versions).get(&1));
+
+        // a symlink with a version name is the version itself
+        std::os::unix::fs::symlink(doc_root.path().join("1.10"), doc_root.path().join("1.11"))
+            .unwrap();
+        let versions = list_version_dirs(doc_root.path(), true, true).unwrap();
+        let ver = versions.iter().find(|ver| ver.display == "1.11").unwrap();
+        assert_eq!(None, ver.alias_of);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn latest_is_not_a_symlink_alias() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        std::os::unix::fs::symlink(doc_root.path().join("1.10"), doc_root.path().join("stable"))
+            .unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.scan.follow_symlinks = true;
+        let base_url = serve_config(&config);
+
+        let (status, headers, _) =
+            get_with_headers(&format!("{}/latest/", base_url), Headers::new());
+        assert_eq!(StatusCode::Found, status);
+        assert_eq!(Some(&Location("/1.10/index.html".to_string())), headers.get());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_dirs_are_skipped_by_default() {
+        let doc_root = make_doc_root(&["1.10"]);
+        std::os::unix::fs::symlink(doc_root.path().join("1.10"), doc_root.path().join("stable"))
+            .unwrap();
+        let versions = list_version_dirs(doc_root.path(), true, false).unwrap();
+        assert_eq!(vec!["1.10"],
+                   versions.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn skip_reasons() {
+        let doc_root = make_doc_root(&["1.10", "Nightly", "assets", "99999999999.1"]);
+        fs::write(doc_root.path().join("1.9"), "a file").unwrap();
+        let mut report = scan_version_dirs(doc_root.path(), false, false).unwrap();
+        report.included.sort();
+        assert_eq!(vec!["1.10", "nightly"],
+                   report.included.iter().map(|ver| ver.display.as_str()).collect::<Vec<_>>());
+        report.skipped.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(vec![("1.9".to_string(), SkipReason::NotADir),
+                        ("99999999999.1".to_string(), SkipReason::ParseOverflow),
+                        ("assets".to_string(), SkipReason::NoSemverMatch)],
+                   report.skipped);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn broken_symlinks_are_unreadable() {
+        let doc_root = make_doc_root(&["1.10"]);
+        std::os::unix::fs::symlink(doc_root.path().join("gone"), doc_root.path().join("1.11"))
+            .unwrap();
+        let report = scan_version_dirs(doc_root.path(), false, false).unwrap();
+        assert_eq!(1, report.skipped.len());
+        assert_eq!("1.11", report.skipped[0].0);
+        match report.skipped[0].1 {
+            SkipReason::Unreadable(_) => {}
+            ref other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn scan_report() {
+        let doc_root = make_doc_root(&["1.10", "1.9 ", "assets"]);
+        let dir = doc_root.path().to_str().unwrap();
+        let report = format_scan_report(dir, &ScanOptions::default()).unwrap();
+        assert_eq!(format!("{}\n  + 1.10\n  + 1.9 (\"1.9 \")\n  - assets: not a version number or \
+                            a channel\n",
+                           dir),
+                   report);
+    }
+
+    #[test]
+    fn missing_doc_root_is_an_error() {
+        let doc_root = make_doc_root(&[]);
+        assert!(list_version_dirs(&doc_root.path().join("missing"), true, false).is_err());
+    }
+
+    #[test]
+    fn slow_handler_times_out() {
+        let mut server = Nickel::new();
+        server.get("/slow",
+                   handler(|_, response| {
+            let work = || {
+                thread::sleep(Duration::from_millis(500));
+                "done"
+            };
+            match run_with_timeout(Some(Duration::from_millis(20)), work) {
+                Ok(body) => response.send(body),
+                Err(_) => timed_out(response),
+            }
+        }));
+        server.handle_error(ErrorResponses::new(Arc::new(RwLock::new(Vec::new())), "", false));
+        let base = serve(server);
+
+        let (status, _, body) = get(&format!("{}/slow", base));
+        assert_eq!(StatusCode::ServiceUnavailable, status);
+        assert_eq!("The request took too long", body);
+    }
+
+    #[test]
+    fn access_log_file() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        let log_path = doc_root.path().join("access.log");
+        config.access_log_file = Some(log_path.to_str().unwrap().to_string());
+        let base = serve_config(&config);
+
+        get(&format!("{}/healthz", base));
+        let log = fs::read_to_string(&log_path).unwrap();
+        assert!(log.starts_with("GET /healthz 200 "), "{}", log);
+    }
+
+    #[test]
+    fn access_log_max_bytes_from_env() {
+        let _guard = EnvGuard::set("ACCESS_LOG_MAX_BYTES", Some("1024"));
+        assert_eq!(Ok(1024), get_access_log_max_bytes());
+    }
+
+    #[test]
+    fn default_document() {
+        let doc_root = make_doc_root(&["1.9"]);
+        fs::create_dir(doc_root.path().join("1.9/book")).unwrap();
+        fs::write(doc_root.path().join("1.9/README.html"), "the 1.9 readme").unwrap();
+        fs::write(doc_root.path().join("1.9/book/README.html"), "the book readme").unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.default_document = "README.html".to_string();
+        let base = serve_config(&config);
+
+        let (status, _, body) = get(&format!("{}/1.9/", base));
+        assert_eq!((StatusCode::Ok, "the 1.9 readme"), (status, body.as_str()));
+        let (status, _, body) = get(&format!("{}/1.9/book/?q=1", base));
+        assert_eq!((StatusCode::Ok, "the book readme"), (status, body.as_str()));
+    }
+
+    #[test]
+    fn missing_default_document() {
+        let doc_root = make_doc_root(&["1.9"]);
+        fs::write(doc_root.path().join("1.9/index.html"), "the 1.9 docs").unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.default_document = "README.html".to_string();
+        let base = serve_config(&config);
+
+        let (status, _, _) = get(&format!("{}/1.9/", base));
+        assert_eq!(StatusCode::NotFound, status);
+    }
+
+    #[test]
+    fn default_document_from_env() {
+        let _guard = EnvGuard::set("DEFAULT_DOCUMENT", Some("README.html"));
+        assert_eq!("README.html", Config::from_env().unwrap().default_document);
+    }
+
+    #[test]
+    fn rescan_updates_the_versions() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let dir = doc_root.path().to_str().unwrap();
+        let versions: SharedVersions = Arc::new(RwLock::new(versions_in(dir)));
+        let scan = ScanOptions::default();
+        let rescan = || rescan_if_changed(dir, &scan, &versions).map_err(|e| e.kind());
+
+        assert_eq!(Ok(false), rescan());
+        fs::create_dir(doc_root.path().join("1.10")).unwrap();
+        assert_eq!(Ok(true), rescan());
+        assert_eq!("1.10\n1.9\n", format_version_list(&versions.read().unwrap()));
+        fs::remove_dir(doc_root.path().join("1.9")).unwrap();
+        assert_eq!(Ok(true), rescan());
+        assert_eq!("1.10\n", format_version_list(&versions.read().unwrap()));
+    }
+
+    #[test]
+    fn watcher_updates_the_menu() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.watch_interval = Some(Duration::from_millis(20));
+        let base = serve_config(&config);
+
+        fs::create_dir(doc_root.path().join("1.10")).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        let (_, _, body) = get(&format!("{}/api/versions", base));
+        assert_eq!(r#"["1.10","1.9"]"#, body);
+    }
+
+    #[test]
+    fn watch_interval_from_env() {
+        let _guard = EnvGuard::set("WATCH_DOC_ROOT", Some("true"));
+        assert_eq!(Ok(Some(Duration::from_secs(10))), get_watch_interval());
+    }
+
+    #[test]
+    fn no_watching_by_default() {
+        let _guard = EnvGuard::set("WATCH_DOC_ROOT", None);
+        assert_eq!(Ok(None), get_watch_interval());
+    }
+
+    #[test]
+    fn alias_redirects() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.aliases = vec![("/guide".to_string(), "/1.10/guide/index.html".to_string()),
+                              ("/1.9".to_string(), "/1.10/index.html".to_string())];
+        let base = serve_config(&config);
+
+        let (status, headers, _) =
+            get_with_headers(&format!("{}/guide?lang=ja", base), Headers::new());
+        assert_eq!(StatusCode::Found, status);
+        assert_eq!(Some(&Location("/1.10/guide/index.html?lang=ja".to_string())),
+                   headers.get());
+
+        // the version wins over the alias
+        let (status, headers, _) = get_with_headers(&format!("{}/1.9", base), Headers::new());
+        assert_eq!(StatusCode::MovedPermanently, status);
+        assert_eq!(Some(&Location("/1.9/".to_string())), headers.get());
+    }
+
+    #[test]
+    fn aliases_cannot_hide_versions() {
+        let vers = vec![Version::new(1, 10, 0, "1.10")];
+        let aliases = vec![("/guide".to_string(), "/1.10/guide/".to_string()),
+                           ("/1.10/guide".to_string(), "/1.10/guide/".to_string())];
+        assert_eq!(vec!["/1.10/guide"], shadowing_aliases(&aliases, &vers));
+    }
+
+    #[test]
+    fn large_bodies_are_rejected() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.max_body_bytes = 100;
+        let base = serve_config(&config);
+
+        let send = |len: usize| {
+            let mut stream = TcpStream::connect(&base["http://".len()..]).unwrap();
+            write!(stream,
+                   "POST /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+                    Content-Length: {}\r\n\r\n{}",
+                   len,
+                   // a body left unread would make the server reset the connection
+                   if len <= 100 { "x".repeat(len) } else { String::new() })
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response.lines().next().unwrap().to_string()
+        };
+        let status_line = send(1_000_000);
+        assert!(status_line.contains("413"), "{}", status_line);
+        let status_line = send(100);
+        assert!(!status_line.contains("413"), "{}", status_line);
+
+        let (status, _, _) = get(&format!("{}/healthz", base));
+        assert_eq!(StatusCode::Ok, status);
+    }
+
+    #[test]
+    fn max_body_bytes_from_env() {
+        let _guard = EnvGuard::set("MAX_BODY_BYTES", Some("4096"));
+        assert_eq!(Ok(4096), get_max_body_bytes());
+    }
+
+    #[test]
+    fn server_threads() {
+        assert_eq!(Some(8), parse_server_threads(Some("8")));
+        assert_eq!(Some(2), parse_server_threads(Some(" 2 ")));
+        assert_eq!(None, parse_server_threads(None));
+        assert_eq!(None, parse_server_threads(Some("0")));
+        assert_eq!(None, parse_server_threads(Some("-1")));
+        assert_eq!(None, parse_server_threads(Some("many")));
+    }
+
+    #[test]
+    fn request_timeout_from_env() {
+        let _guard = EnvGuard::set("REQUEST_TIMEOUT_MS", Some("1500"));
+        assert_eq!(Ok(Some(Duration::from_millis(1500))), get_request_timeout());
+    }
+
+    #[test]
+    fn no_request_timeout_by_default() {
+        let _guard = EnvGuard::set("REQUEST_TIMEOUT_MS", None);
+        assert_eq!(Ok(None), get_request_timeout());
+    }
+
+    #[test]
+    fn search_by_substring() {
+        let versions = vec![Version::channel("nightly"),
+                            Version::new(1, 10, 0, "1.10"),
+                            Version::new(1, 9, 0, "1.9"),
+                            Version::new(0, 12, 1, "0.12.1")];
+        let displays = |vers: Vec<Version>| {
+            vers.into_iter().map(|ver| ver.display).collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["0.12.1"], displays(search_versions(&versions, "12")));
+        assert_eq!(vec!["1.10"], displays(search_versions(&versions, "1.1")));
+        assert_eq!(vec!["nightly"], displays(search_versions(&versions, "NIGHT")));
+        assert!(search_versions(&versions, "2.0").is_empty());
+        assert_eq!(4, search_versions(&versions, "").len());
+    }
+
+    #[test]
+    fn search_route() {
+        let doc_root = make_doc_root(&["1.9", "1.10", "1.6"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base = serve_config(&config);
+
+        assert_eq!(r#"["1.10"]"#, get(&format!("{}/api/search?q=1.1", base)).2);
+        assert_eq!("[]", get(&format!("{}/api/search?q=2.0", base)).2);
+        assert_eq!(r#"["1.10","1.9","1.6"]"#, get(&format!("{}/api/search?q=", base)).2);
+        let (status, content_type, body) = get(&format!("{}/api/search", base));
+        assert_eq!(StatusCode::Ok, status);
+        assert_eq!(Some(ContentType::json()), content_type);
+        assert_eq!(r#"["1.10","1.9","1.6"]"#, body);
+    }
+
+    #[test]
+    fn version_sort_from_env() {
+        let _guard = EnvGuard::set("VERSION_SORT", Some("ASC"));
+        assert_eq!(SortOrder::Asc, ScanOptions::from_env().sort);
+    }
+
+    #[test]
+    fn version_sort_orders() {
+        let doc_root = make_doc_root(&["1.9", "1.10", "1.6", "1.10.2"]);
+        let dir = doc_root.path().to_str().unwrap();
+        let displays = |vers: &[Version]| {
+            vers.iter().map(|ver| ver.display.clone()).collect::<Vec<_>>()
+        };
+
+        let desc = versions_in(dir);
+        assert_eq!(vec!["1.10.2", "1.10", "1.9", "1.6"], displays(&desc));
+
+        let scan = ScanOptions { sort: SortOrder::Asc, ..ScanOptions::default() };
+        let asc = get_versions(dir, &scan).unwrap();
+        assert_eq!(vec!["1.6", "1.9", "1.10", "1.10.2"], displays(&asc));
+
+        let menu = make_menu_data(&asc, DEFAULT_LABEL_FORMAT);
+        let menu_versions = menu["versions"].as_array().unwrap();
+        assert_eq!(Some("1.6"), menu_versions[0].find("version").and_then(|v| v.as_string()));
+        assert_eq!(Some("/1.10.2/index.html".to_string()), latest_path(&asc, None));
+    }
+
+    #[test]
+    fn not_found_page_escapes_the_path() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base = serve_config(&config);
+
+        let (status_line, body) = raw_get(&base, "/1.9/<script>alert(1)</script>");
+        assert!(status_line.contains("404"), "{}", status_line);
+        assert!(body.contains("&lt;script&gt;alert(1)&lt;/script&gt;"), "{}", body);
+        assert!(!body.contains("<script>"), "{}", body);
+    }
+
+    #[test]
+    fn paths_cannot_escape_the_doc_root() {
+        let parent = TempDir::new().unwrap();
+        fs::write(parent.path().join("secret.txt"), "top secret").unwrap();
+        let doc_root = parent.path().join("public");
+        fs::create_dir_all(doc_root.join("1.9")).unwrap();
+        let config = Config::new(doc_root.to_str().unwrap());
+        let base = serve_config(&config);
+
+        for path in &["/../secret.txt",
+                      "/1.9/../../secret.txt",
+                      "/1.9/%2e%2e/%2e%2e/secret.txt",
+                      "/../../../../../../etc/passwd"] {
+            let (status_line, body) = raw_get(&base, path);
+            assert!(!status_line.contains("200"), "{}: {}", path, status_line);
+            assert!(!body.contains("top secret") && !body.contains("root:"), "{}: {}", path, body);
+        }
+    }
+
+    #[test]
+    fn routes_resolve_under_the_base_path() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        fs::write(doc_root.path().join("1.9").join("index.html"), "the 1.9 docs").unwrap();
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.base_path = "/docs".to_string();
+        let base = serve_config(&config);
+
+        let (status, _, body) = get(&format!("{}/docs/", base));
+        assert_eq!(StatusCode::Ok, status);
+        assert!(body.contains(r#"<a href="1.10/index.html">"#), "{}", body);
+        let (status, _, body) = get(&format!("{}/docs/1.9/index.html", base));
+        assert_eq!((StatusCode::Ok, "the 1.9 docs"), (status, body.as_str()));
+        let (status, _, body) = get(&format!("{}/docs/api/versions", base));
+        assert_eq!((StatusCode::Ok, r#"["1.10","1.9"]"#), (status, body.as_str()));
+        let (status, _, _) = get(&format!("{}/docs/healthz", base));
+        assert_eq!(StatusCode::Ok, status);
+
+        // outside of the base path
+        let (status, _, _) = get(&format!("{}/1.9/index.html", base));
+        assert_eq!(StatusCode::NotFound, status);
+        let (status, _, _) = get(&format!("{}/docsx/", base));
+        assert_eq!(StatusCode::NotFound, status);
+    }
+
+    #[test]
+    fn force_https() {
+        let doc_root = make_doc_root(&["1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.force_https = true;
+        config.trust_proxy = true;
+        let base_url = serve_config(&config);
+
+        let mut headers = Headers::new();
+        headers.set_raw("X-Forwarded-Proto", vec![b"http".to_vec()]);
+        headers.set_raw("X-Forwarded-Host", vec![b"docs.example.com".to_vec()]);
+        let (status, res_headers, _) =
+            get_with_headers(&format!("{}/1.10/?q=1", base_url), headers);
+        assert_eq!(StatusCode::MovedPermanently, status);
+        assert_eq!(Some(&Location("https://docs.example.com/1.10/?q=1".to_string())),
+                   res_headers.get());
+
+        let mut headers = Headers::new();
+        headers.set_raw("X-Forwarded-Proto", vec![b"https".to_vec()]);
+        assert_eq!(StatusCode::Ok,
+                   get_with_headers(&format!("{}/", base_url), headers).0);
+        assert_eq!(StatusCode::Ok, get(&format!("{}/", base_url)).0);
+    }
+
+    #[test]
+    fn force_https_ignores_the_forwarded_host_of_an_untrusted_proxy() {
+        let doc_root = make_doc_root(&["1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.force_https = true;
+        let base_url = serve_config(&config);
+
+        let mut headers = Headers::new();
+        headers.set_raw("X-Forwarded-Proto", vec![b"http".to_vec()]);
+        headers.set_raw("X-Forwarded-Host", vec![b"evil.example.com".to_vec()]);
+        let (status, res_headers, _) =
+            get_with_headers(&format!("{}/1.10/", base_url), headers);
+        assert_eq!(StatusCode::MovedPermanently, status);
+        assert_eq!(Some(&Location("https://127.0.0.1/1.10/".to_string())), res_headers.get());
+    }
+
+    #[test]
+    fn https_is_not_forced_by_default() {
+        let doc_root = make_doc_root(&["1.10"]);
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base_url = serve_config(&config);
+
+        let mut headers = Headers::new();
+        headers.set_raw("X-Forwarded-Proto", vec![b"http".to_vec()]);
+        assert_eq!(StatusCode::Ok, get_with_headers(&format!("{}/", base_url), headers).0);
+    }
+
+    #[test]
+    fn redirects_keep_the_base_path() {
+        let doc_root = make_doc_root(&["1.9", "1.10"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.base_path = "/docs".to_string();
+        let base = serve_config(&config);
+
+        for &(path, expected) in &[("/docs", "/docs/"),
+                                   ("/docs/1.9", "/docs/1.9/"),
+                                   ("/docs/latest", "/docs/1.10/index.html"),
+                                   ("/docs/1", "/docs/1.10/")] {
+            let (_, headers, _) = get_with_headers(&format!("{}{}", base, path), Headers::new());
+            assert_eq!(Some(&Location(expected.to_string())), headers.get(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn not_found_page_links_under_the_base_path() {
+        let doc_root = make_doc_root(&["1.9"]);
+        let mut config = Config::new(doc_root.path().to_str().unwrap());
+        config.base_path = "/docs".to_string();
+        let base = serve_config(&config);
+
+        let (status, _, body) = get(&format!("{}/docs/no-such-page.html", base));
+        assert_eq!(StatusCode::NotFound, status);
+        assert!(body.contains(r#"<a href="/docs/1.9/index.html">"#), "{}", body);
+        assert!(body.contains("/docs/no-such-page.html"), "{}", body);
+    }
+
+    #[test]
+    fn base_path_from_env() {
+        let _env = EnvGuard::set("BASE_PATH", Some("docs/"));
+        assert_eq!("/docs", Config::from_env().unwrap().base_path);
+    }
+
+    #[test]
+    fn head_requests_have_no_body() {
+        let doc_root = make_doc_root(&["1.9"]);
+        fs::write(doc_root.path().join("1.9").join("index.html"), "the 1.9 docs").unwrap();
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base = serve_config(&config);
+
+        let html = ContentType(Mime(TopLevel::Text, SubLevel::Html, vec![]));
+        let json = ContentType(Mime(TopLevel::Application, SubLevel::Json, vec![]));
+        for &(path, status, content_type) in &[("/", StatusCode::Ok, &html),
+                                                   ("/1.9/index.html", StatusCode::Ok, &html),
+                                                   ("/api/versions", StatusCode::Ok, &json),
+                                                   ("/no-such-page", StatusCode::NotFound, &html)] {
+            let (actual_status, headers, body) = head(&format!("{}{}", base, path));
+            assert_eq!(status, actual_status, "{}", path);
+            assert_eq!(Some(content_type), headers.get::<ContentType>(), "{}", path);
+            assert!(body.is_empty(), "{}: {:?}", path, body);
+        }
+
+        // the connection is still usable for a GET
+        let (status, _, body) = get(&format!("{}/1.9/index.html", base));
+        assert_eq!((StatusCode::Ok, "the 1.9 docs"), (status, body.as_str()));
+    }
+
+    #[test]
+    fn head_requests_have_the_content_length_of_the_get() {
+        let doc_root = make_doc_root(&["1.9"]);
+        fs::write(doc_root.path().join("1.9").join("index.html"), "the 1.9 docs").unwrap();
+        let config = Config::new(doc_root.path().to_str().unwrap());
+        let base = serve_config(&config);
+
+        for path in &["/1.9/index.html", "/api/versions"] {
+            let url = format!("{}{}", base, path);
+            let (_, get_headers, get_body) = get_with_headers(&url, Headers::new());
+            let (_, head_headers, head_body) = head(&url);
+            let get_length = get_headers.get::<ContentLength>().cloned();
+            assert_eq!(Some(ContentLength(get_body.len() as u64)), get_length, "{}", path);
+            assert_eq!(get_length.as_ref(), head_headers.get::<ContentLength>(), "{}", path);
+            assert!(head_body.is_empty(), "{}: {:?}", path, head_body);
+        }
+    }
+}
@@ -0,0 +1,283 @@
+// -*- coding:utf-8-unix -*-
+
+//! Request logging middleware. Writes one line per request.
+//!
+//! Also has the leveled diagnostics, `error`, `warn`, `info` and `debug`. The
+//! ones below the `LOG_LEVEL` env var are left out. Errors and warnings go to
+//! stderr, the others to stdout.
+
+use lazy_static;
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+
+use request_id;
+
+use rustc_serialize::json::{self, ToJson};
+
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The levels of the diagnostics, from the most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    /// e.g. the skipped directories. Only wanted while investigating.
+    Debug,
+}
+
+impl Level {
+    /// Returns the level for the name ("error", "warn", "info" or "debug", case
+    /// insensitive), if it is one.
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" | "warning" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+
+    /// Returns true if the messages at this level are printed with the
+    /// `LOG_LEVEL`.
+    pub fn is_enabled(self) -> bool {
+        passes(self, *MAX_LEVEL)
+    }
+}
+
+lazy_static! {
+    /// The `LOG_LEVEL`, `Info` when it is not set or unknown. Read once, by
+    /// `init` at startup or by the first message otherwise.
+    static ref MAX_LEVEL: Level = env::var("LOG_LEVEL")
+        .ok()
+        .and_then(|name| Level::from_name(&name))
+        .unwrap_or(Level::Info);
+}
+
+/// Reads the `LOG_LEVEL`. Changes to it after this call are not seen.
+pub fn init() {
+    lazy_static::initialize(&MAX_LEVEL);
+}
+
+/// Returns true if a message at `level` is printed with the `max_level`.
+fn passes(level: Level, max_level: Level) -> bool {
+    level <= max_level
+}
+
+/// Prints the message with its level, e.g. `[warn] Skipping ...`, unless it is
+/// below the `LOG_LEVEL`. Errors and warnings go to stderr.
+pub fn log(level: Level, message: &str) {
+    if !level.is_enabled() {
+        return;
+    }
+    match level {
+        Level::Error | Level::Warn => eprintln!("[{}] {}", level.name(), message),
+        Level::Info | Level::Debug => println!("[{}] {}", level.name(), message),
+    }
+}
+
+pub fn error(message: &str) {
+    log(Level::Error, message);
+}
+
+pub fn warn(message: &str) {
+    log(Level::Warn, message);
+}
+
+pub fn info(message: &str) {
+    log(Level::Info, message);
+}
+
+pub fn debug(message: &str) {
+    log(Level::Debug, message);
+}
+
+/// The format of the request log lines.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// e.g. `GET /1.10/index.html 200 0.412ms request_id=5a2e6f0b-...`
+    Plain,
+    /// e.g. `{"elapsed_ms":0.412,"method":"GET","path":"/1.10/index.html","status":200}`
+    Json,
+}
+
+impl LogFormat {
+    /// Returns the format for the name ("plain" or "json", case insensitive).
+    /// Unknown names fall back to `Plain`.
+    pub fn from_name(name: &str) -> LogFormat {
+        if name.eq_ignore_ascii_case("json") {
+            LogFormat::Json
+        } else {
+            LogFormat::Plain
+        }
+    }
+}
+
+/// What gets logged for a request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub elapsed: Duration,
+    /// The `X-Request-Id`, when `RequestId` is registered in front.
+    pub request_id: Option<String>,
+}
+
+impl LogEntry {
+    pub fn format(&self, format: LogFormat) -> String {
+        let elapsed_ms = self.elapsed.as_secs_f64() * 1000.0;
+        match format {
+            LogFormat::Plain => {
+                let line =
+                    format!("{} {} {} {:.3}ms", self.method, self.path, self.status, elapsed_ms);
+                match self.request_id {
+                    Some(ref id) => format!("{} request_id={}", line, id),
+                    None => line,
+                }
+            }
+            LogFormat::Json => {
+                let mut obj = BTreeMap::new();
+                obj.insert("method".to_string(), self.method.to_json());
+                obj.insert("path".to_string(), self.path.to_json());
+                obj.insert("status".to_string(), self.status.to_json());
+                obj.insert("elapsed_ms".to_string(), elapsed_ms.to_json());
+                if let Some(ref id) = self.request_id {
+                    obj.insert("request_id".to_string(), id.to_json());
+                }
+                json::encode(&obj).unwrap()
+            }
+        }
+    }
+}
+
+/// Logs every request once the response status is known. Register it first,
+/// right after `RequestId`, so that it sees all requests. The elapsed time is
+/// measured until the response headers are sent.
+pub struct RequestLogger {
+    output: Arc<dyn Fn(&LogEntry) + Send + Sync>,
+}
+
+impl RequestLogger {
+    /// Creates a logger writing lines in the format to stdout.
+    pub fn new(format: LogFormat) -> RequestLogger {
+        RequestLogger::with_output(move |entry| println!("{}", entry.format(format)))
+    }
+
+    /// Creates a logger passing each entry to the given function.
+    pub fn with_output<F>(output: F) -> RequestLogger
+        where F: Fn(&LogEntry) + Send + Sync + 'static
+    {
+        RequestLogger { output: Arc::new(output) }
+    }
+}
+
+impl<D> Middleware<D> for RequestLogger {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        let start = Instant::now();
+        let method = req.origin.method.to_string();
+        let path = req.path_without_query().unwrap_or("").to_string();
+        let request_id = request_id::request_id(req);
+        let output = self.output.clone();
+
+        res.on_send(move |res| {
+            let entry = LogEntry {
+                method: method.clone(),
+                path: path.clone(),
+                status: res.status().to_u16(),
+                elapsed: start.elapsed(),
+                request_id: request_id.clone(),
+            };
+            output(&entry);
+        });
+        res.next_middleware()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{passes, Level, LogEntry, LogFormat};
+
+    use rustc_serialize::json::Json;
+
+    use std::time::Duration;
+
+    fn entry() -> LogEntry {
+        LogEntry {
+            method: "GET".to_string(),
+            path: "/1.10/index.html".to_string(),
+            status: 404,
+            elapsed: Duration::from_micros(1500),
+            request_id: None,
+        }
+    }
+
+    #[test]
+    fn log_format_names() {
+        assert_eq!(LogFormat::Json, LogFormat::from_name("json"));
+        assert_eq!(LogFormat::Json, LogFormat::from_name("JSON"));
+        assert_eq!(LogFormat::Plain, LogFormat::from_name("plain"));
+        assert_eq!(LogFormat::Plain, LogFormat::from_name("yaml"));
+    }
+
+    #[test]
+    fn level_names() {
+        assert_eq!(Some(Level::Error), Level::from_name("error"));
+        assert_eq!(Some(Level::Warn), Level::from_name("WARN"));
+        assert_eq!(Some(Level::Warn), Level::from_name("warning"));
+        assert_eq!(Some(Level::Info), Level::from_name(" info "));
+        assert_eq!(Some(Level::Debug), Level::from_name("Debug"));
+        assert_eq!(None, Level::from_name("trace"));
+        assert_eq!(None, Level::from_name(""));
+    }
+
+    #[test]
+    fn levels_below_the_max_are_left_out() {
+        assert!(passes(Level::Error, Level::Error));
+        assert!(!passes(Level::Warn, Level::Error));
+        assert!(passes(Level::Error, Level::Info));
+        assert!(passes(Level::Warn, Level::Info));
+        assert!(passes(Level::Info, Level::Info));
+        assert!(!passes(Level::Debug, Level::Info));
+        assert!(passes(Level::Debug, Level::Debug));
+    }
+
+    #[test]
+    fn plain_line() {
+        assert_eq!("GET /1.10/index.html 404 1.500ms", entry().format(LogFormat::Plain));
+    }
+
+    #[test]
+    fn lines_with_request_id() {
+        let entry = LogEntry { request_id: Some("abc-123".to_string()), ..entry() };
+        assert_eq!("GET /1.10/index.html 404 1.500ms request_id=abc-123",
+                   entry.format(LogFormat::Plain));
+        let line = Json::from_str(&entry.format(LogFormat::Json)).unwrap();
+        assert_eq!(Some("abc-123"), line.find("request_id").and_then(|v| v.as_string()));
+    }
+
+    #[test]
+    fn json_line() {
+        let line = Json::from_str(&entry().format(LogFormat::Json)).unwrap();
+        assert_eq!(Some("GET"), line.find("method").and_then(|v| v.as_string()));
+        assert_eq!(Some("/1.10/index.html"), line.find("path").and_then(|v| v.as_string()));
+        assert_eq!(Some(404), line.find("status").and_then(|v| v.as_u64()));
+        assert_eq!(Some(1.5), line.find("elapsed_ms").and_then(|v| v.as_f64()));
+    }
+}
@@ -3,179 +3,228 @@
 #![cfg_attr(feature="clippy", feature(plugin))]
 #![cfg_attr(feature="clippy", plugin(clippy))]
 
-#[macro_use]
-extern crate lazy_static;
+extern crate hello_heroku;
+extern crate signal_hook;
 
-#[macro_use]
-extern crate nickel;
+use hello_heroku::{build_server, check_doc_roots, check_setup, check_template, export_site,
+                   format_scan_report, format_version_list, get_bind_address, get_bind_retries,
+                   get_listen_address, get_versions, has_version, init_logger, is_quiet, log,
+                   resolve_doc_root, retry_bind, startup_summary, wait_for_drain, Config, Level,
+                   ScanOptions, Shutdown, StartupError, BIND_BACKOFF_MS};
 
-extern crate regex;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
 
-use nickel::{Nickel, HttpRouter, Mountable, StaticFilesHandler};
-
-use regex::Regex;
-
-use std::cmp::Ordering;
-use std::collections::HashMap;
 use std::env;
-use std::fs;
-use std::io;
+use std::net::TcpListener;
 use std::path::Path;
-
-const DOC_ROOT: &'static str = "public";
-
-const LISTEN_ADDRESS: &'static str = "0.0.0.0";
-const DEFAULT_PORT: &'static str = "6767";
-
-const HOME_TEMPLATE: &'static str = "assets/home.mustache";
-
-// Disable unreachable waring for `server.get("/", middleware! { ... })`
-#[allow(unreachable_code)]
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 fn main() {
-    let versions;
-    match get_versions(DOC_ROOT) {
-        Err(e) => {
-            println!("An error occured while scanning the doc root directory. Exiting. \
-                      Error: {}, Dir: {}",
-                     e.to_string(),
-                     DOC_ROOT);
-            return;
-        }
-        Ok(vers) => {
-            versions = vers;
-        }
+    init_logger();
+    if env::args().skip(1).any(|arg| arg == "--list-versions") {
+        list_versions();
+        return;
+    }
+    if env::args().skip(1).any(|arg| arg == "--scan-report") {
+        scan_report();
+        return;
+    }
+    if env::args().skip(1).any(|arg| arg == "--check") {
+        check();
+        return;
+    }
+    if let Some(i) = env::args().position(|arg| arg == "--export") {
+        export(env::args().nth(i + 1));
+        return;
     }
-    let menu_data = make_menu_data(&versions);
-
-    let mut server = Nickel::new();
-
-    // the home (menu) page
-    server.get("/",
-               middleware! {|_, response|
-        return response.render(HOME_TEMPLATE, &menu_data); // need `return`
-    });
-
-    // set "public" folder as the document root
-    server.mount("/", StaticFilesHandler::new(DOC_ROOT));
-
-    // if there is no matching page in the previous mount, return "not found" message.
-    // @TODO: Use a template with status 404
-    server.mount("/",
-                 middleware! { |req|
-        let path = req.path_without_query().unwrap();
-        format!("No static file with path '{}'!", path)
-    });
 
-    server.listen((LISTEN_ADDRESS, get_server_port()));
+    if let Err(e) = start() {
+        log(Level::Error, &format!("{}. Exiting.", e));
+        process::exit(e.exit_code());
+    }
 }
 
+/// Checks the setup and starts the server, which doesn't return once it is
+/// listening.
+fn start() -> Result<(), StartupError> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (address, port) = get_bind_address(&args).map_err(StartupError::BadPort)?;
+    let config = Config::from_env().map_err(StartupError::BadConfig)?;
+    let doc_root = &config.doc_root;
+    check_doc_roots(doc_root).map_err(StartupError::DocRootMissing)?;
+    if !config.disable_html {
+        check_template(&config.home_template).map_err(StartupError::BadTemplate)?;
+    }
 
-// NOTE: &str.to_string() vs &str.to_owned()
-//
-// In Rust 1.9 or newer, both methods should yeild the same performance,
-// therefore to_string() will be more preferable than to_owned() for clarity.
-// In older releases, you should replace these &str.to_string() calls with
-// &str.to_owned() for better performance.
+    let versions = get_versions(doc_root, &config.scan).map_err(|error| StartupError::ScanFailed {
+        dir: doc_root.to_string(),
+        error,
+    })?;
+    if versions.is_empty() {
+        log(Level::Warn,
+            &format!("No version directories were found in the doc root directory. Dir: {}",
+                     doc_root));
+    }
 
+    if let Some(ref version) = config.default_version {
+        if !has_version(&versions, version) {
+            log(Level::Warn,
+                &format!("DEFAULT_VERSION '{}' was not found in the doc root directory. \
+                          Showing the menu instead. Dir: {}",
+                         version,
+                         doc_root));
+        }
+    }
 
-/// For Heroku deployment
-fn get_server_port() -> u16 {
-    env::var("PORT").unwrap_or(DEFAULT_PORT.to_string()).parse().unwrap()
-}
+    let attempts = get_bind_retries().map_err(StartupError::BadConfig)?;
+    // Nickel panics when it cannot bind, so make sure the port is free first.
+    // The previous dyno may still hold it for a moment after a restart.
+    let probe = retry_bind(attempts,
+                           Duration::from_millis(BIND_BACKOFF_MS),
+                           || TcpListener::bind((address.as_str(), port)));
+    let listener = probe.map_err(|error| StartupError::BindFailed {
+        address: address.clone(),
+        port,
+        attempts: attempts.max(1),
+        error,
+    })?;
+    drop(listener);
+
+    handle_shutdown_signals(config.shutdown.clone(),
+                            Duration::from_secs(config.drain_timeout as u64));
+
+    if !is_quiet() && Level::Info.is_enabled() {
+        print!("{}", startup_summary(&config, &address, port, &versions));
+    }
 
+    let server = build_server(&config, versions);
+    server.listen((address.as_str(), port));
+    Ok(())
+}
 
-/// Returns vec of version strings. e.g. vec!["1.10", "1.9", "1.6"]
-fn get_versions(dir: &str) -> io::Result<Vec<String>> {
-    let mut versions = try!(list_version_dirs(&Path::new(dir)));
-    sort_versions(&mut versions);
-    versions.reverse();
-    Ok(versions.into_iter().map(|(_, _, ver)| ver).collect())
+/// On SIGTERM or SIGINT, stops serving new requests, waits up to `drain_timeout`
+/// for the requests in flight, and exits with status 0.
+fn handle_shutdown_signals(shutdown: Arc<Shutdown>, drain_timeout: Duration) {
+    let mut signals = match Signals::new([SIGTERM, SIGINT]) {
+        Err(e) => {
+            log(Level::Warn,
+                &format!("Could not install the signal handler. Shutdowns will not wait for \
+                          requests in flight. Error: {}",
+                         e));
+            return;
+        }
+        Ok(signals) => signals,
+    };
+    thread::spawn(move || {
+        if signals.forever().next().is_none() {
+            return;
+        }
+        log(Level::Info,
+            &format!("Shutting down. Waiting up to {}s for {} request(s) in flight.",
+                     drain_timeout.as_secs(),
+                     shutdown.in_flight()));
+        shutdown.start_draining();
+        if !wait_for_drain(|| shutdown.in_flight(), drain_timeout, Duration::from_millis(100)) {
+            log(Level::Warn,
+                &format!("Gave up waiting for {} request(s) in flight.", shutdown.in_flight()));
+        }
+        process::exit(0);
+    });
 }
 
-/// Returns vec of version tuples. e.g. vec![(1. 9, "1.9"), (1, 10, "1.10")]
-fn list_version_dirs(dir: &Path) -> io::Result<Vec<(u32, u32, String)>> {
-    lazy_static! {
-        // NOTE: Assuming dir names are like 1.10, not 1.10.0
-        static ref RE_SEM_VER: Regex = Regex::new(r".*/(\d+)\.(\d+)").unwrap();
-    }
-
-    let mut versions = Vec::new();
-
-    if try!(fs::metadata(dir)).is_dir() {
-        for entry in try!(fs::read_dir(dir)) {
-            let entry = try!(entry);
-            let metadata = try!(fs::metadata(entry.path()));
-            if metadata.is_dir() {
-                if let Some(path) = entry.path().to_str() {
-                    if let Some(cap) = RE_SEM_VER.captures(path) {
-                        let v1 = cap.at(1).unwrap().to_string();
-                        let v2 = cap.at(2).unwrap().to_string();
-
-                        // these `unwrap()` should not panic. we can trust regex `\d+`, can't we?
-                        let ver =
-                            (v1.parse().unwrap(), v2.parse().unwrap(), format!("{}.{}", v1, v2));
-                        versions.push(ver);
-                    }
-                }
-            }
+/// Prints the versions found in the doc root, one per line, for the
+/// `--list-versions` flag. Exits with status 1 if the scan fails.
+fn list_versions() {
+    let doc_root = resolve_doc_root();
+    match get_versions(&doc_root, &ScanOptions::from_env()) {
+        Err(e) => {
+            log(Level::Error,
+                &format!("An error occured while scanning the doc root directory. \
+                          Error: {}, Dir: {}",
+                         e,
+                         doc_root));
+            process::exit(1);
         }
+        Ok(vers) => print!("{}", format_version_list(&vers)),
     }
-
-    Ok(versions)
 }
 
-fn sort_versions(versions: &mut [(u32, u32, String)]) {
-    versions.sort_by(|&(a0, a1, _), &(b0, b1, _)| {
-        match a0.cmp(&b0) {
-            Ordering::Equal => a1.cmp(&b1),
-            other => other,
+/// Prints the entries of each doc root, whether they are versions, and why the
+/// others were skipped, for the `--scan-report` flag. Exits with status 1 if
+/// the scan fails.
+fn scan_report() {
+    let doc_root = resolve_doc_root();
+    match format_scan_report(&doc_root, &ScanOptions::from_env()) {
+        Err(e) => {
+            log(Level::Error,
+                &format!("An error occured while scanning the doc root directory. \
+                          Error: {}, Dir: {}",
+                         e,
+                         doc_root));
+            process::exit(1);
         }
-    });
+        Ok(report) => print!("{}", report),
+    }
 }
 
-/// Returns a map for mustache template.
-/// e.g. {"versions", [{"version", "1.10"}, {"version", "1.9"}, {"version", "1.6"}]}
-fn make_menu_data(vers: &[String]) -> HashMap<String, Vec<HashMap<String, String>>> {
-    let version_maps = vers.into_iter()
-        .map(|ver| {
-            let mut map = HashMap::new();
-            map.insert("version".to_string(), ver.to_string());
-            map
-        })
-        .collect();
-    let mut menu_data = HashMap::new();
-    menu_data.insert("versions".to_string(), version_maps);
-    menu_data
+/// Writes the menu and the version directories under `out_dir` as static
+/// files, for the `--export <dir>` flag. Exits with status 1 if it fails.
+fn export(out_dir: Option<String>) {
+    let out_dir = match out_dir {
+        Some(dir) => dir,
+        None => {
+            log(Level::Error, "--export needs the output directory. e.g. --export site");
+            process::exit(1);
+        }
+    };
+    let doc_root = resolve_doc_root();
+    match export_site(&doc_root, &ScanOptions::from_env(), Path::new(&out_dir)) {
+        Ok(count) => println!("Exported {} version(s) to {}.", count, out_dir),
+        Err(e) => {
+            log(Level::Error,
+                &format!("Failed to export the site. Error: {}, Dir: {}, Output: {}",
+                         e,
+                         doc_root,
+                         out_dir));
+            process::exit(1);
+        }
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::{make_menu_data, sort_versions};
-
-    #[test]
-    fn sort_three_versions() {
-        let mut versions =
-            vec![(1, 10, "1.10".to_string()),
-                 (1,  6, "1.6".to_string()),
-                 (1,  9, "1.9".to_string())];
-        let expectation =
-            vec![(1,  6, "1.6".to_string()),
-                 (1,  9, "1.9".to_string()),
-                 (1, 10, "1.10".to_string())];
-
-        sort_versions(&mut versions);
-        assert_eq!(expectation, versions)
-    }
-
-    #[test]
-    fn menu_data() {
-        let versions = vec!["1.10".to_string(), "1.9".to_string(), "1.6".to_string()];
-        let data = make_menu_data(&versions);
-
-        let vers = data.get("versions").expect("versions should not be None.");
-        for (expected, actual) in versions.iter().zip(vers.iter()) {
-            assert_eq!(Some(expected), actual.get("version"));
+/// Runs all the startup checks without starting the server, for the `--check`
+/// flag. Prints every problem found, and exits with status 1 if there is any.
+fn check() {
+    let mut problems = Vec::new();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let address = match get_bind_address(&args) {
+        Ok((address, _)) => Some(address),
+        Err(e) => {
+            problems.push(e);
+            None
+        }
+    };
+    if let Err(e) = get_bind_retries() {
+        problems.push(e);
+    }
+    match Config::from_env() {
+        Ok(config) => {
+            let address = address.unwrap_or_else(get_listen_address);
+            problems.extend(check_setup(&config, &address));
         }
+        Err(e) => problems.push(e),
+    }
+
+    if problems.is_empty() {
+        println!("No problems found.");
+        return;
+    }
+    eprintln!("{} problem(s) found:", problems.len());
+    for problem in &problems {
+        eprintln!("- {}", problem);
     }
+    process::exit(1);
 }
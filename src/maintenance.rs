@@ -0,0 +1,66 @@
+// -*- coding:utf-8-unix -*-
+
+//! The maintenance mode for the deploys: every page but `/healthz` answers
+//! `503 Service Unavailable` with the maintenance page. It is on with
+//! `MAINTENANCE=true`, or while the `MAINTENANCE_FILE` exists, which can be
+//! created and removed without a restart.
+
+use head::Body;
+
+use hyper::header::{CacheControl, CacheDirective};
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::mimes::MediaType;
+use nickel::status::StatusCode;
+
+use logger;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use {render_template, MAINTENANCE_TEMPLATE};
+
+/// Answers the maintenance page while the maintenance is on. Register it
+/// after `BasePath`, in front of the routes.
+pub struct Maintenance {
+    always: bool,
+    sentinel: Option<PathBuf>,
+}
+
+impl Maintenance {
+    /// `always` turns it on for good. Otherwise it is on while `sentinel`
+    /// exists.
+    pub fn new(always: bool, sentinel: Option<&str>) -> Maintenance {
+        Maintenance {
+            always,
+            sentinel: sentinel.map(PathBuf::from),
+        }
+    }
+
+    fn is_on(&self) -> bool {
+        self.always || self.sentinel.as_ref().is_some_and(|path| path.exists())
+    }
+}
+
+impl<D> Middleware<D> for Maintenance {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        // the dyno is fine, so the router must keep sending the traffic to it
+        if req.path_without_query() == Some("/healthz") || !self.is_on() {
+            return res.next_middleware();
+        }
+        let body = render_template(MAINTENANCE_TEMPLATE, &HashMap::<String, String>::new())
+            .unwrap_or_else(|e| {
+                logger::error(&e);
+                "Under maintenance".as_bytes().to_vec()
+            });
+        res.set(MediaType::Html);
+        // so that the CDNs don't keep serving it after the maintenance
+        res.set(CacheControl(vec![CacheDirective::NoStore]));
+        // sent as is, not through the error handlers
+        res.set(StatusCode::ServiceUnavailable);
+        res.send(Body(body))
+    }
+}
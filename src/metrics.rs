@@ -0,0 +1,95 @@
+// -*- coding:utf-8-unix -*-
+
+//! Request counters for the `/metrics` endpoint, in the Prometheus text
+//! exposition format.
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The status classes counted by `Metrics`, by the first digit of the status.
+const STATUS_CLASSES: [&str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+
+/// Counters shared by `MetricsRecorder` and the `/metrics` handler.
+#[derive(Default)]
+pub struct Metrics {
+    requests: AtomicUsize,
+    responses: [AtomicUsize; 5],
+}
+
+impl Metrics {
+    /// Counts a response with the status code.
+    pub fn record(&self, status: u16) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let class = (status / 100) as usize;
+        if class >= 1 && class <= STATUS_CLASSES.len() {
+            self.responses[class - 1].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the counters and the number of versions in the Prometheus text
+    /// format.
+    pub fn render(&self, version_count: usize) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP http_requests_total The number of HTTP requests.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        out.push_str(&format!("http_requests_total {}\n", self.requests.load(Ordering::Relaxed)));
+
+        out.push_str("# HELP http_responses_total The number of HTTP responses by status class.\n");
+        out.push_str("# TYPE http_responses_total counter\n");
+        for (class, count) in STATUS_CLASSES.iter().zip(&self.responses) {
+            out.push_str(&format!("http_responses_total{{class=\"{}\"}} {}\n",
+                                  class,
+                                  count.load(Ordering::Relaxed)));
+        }
+
+        out.push_str("# HELP doc_versions The number of versions found in the doc root.\n");
+        out.push_str("# TYPE doc_versions gauge\n");
+        out.push_str(&format!("doc_versions {}\n", version_count));
+        out
+    }
+}
+
+/// Counts every response in the `Metrics` once its status is known. Register it
+/// in front of the other middleware so that it sees all requests.
+pub struct MetricsRecorder {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsRecorder {
+    pub fn new(metrics: Arc<Metrics>) -> MetricsRecorder {
+        MetricsRecorder { metrics }
+    }
+}
+
+impl<D> Middleware<D> for MetricsRecorder {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          _req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        let metrics = self.metrics.clone();
+        res.on_send(move |res| metrics.record(res.status().to_u16()));
+        res.next_middleware()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+
+    #[test]
+    fn counts_by_status_class() {
+        let metrics = Metrics::default();
+        for status in &[200, 200, 304, 404, 500, 999] {
+            metrics.record(*status);
+        }
+        let out = metrics.render(3);
+        assert!(out.contains("http_requests_total 6\n"), "{}", out);
+        assert!(out.contains("http_responses_total{class=\"2xx\"} 2\n"), "{}", out);
+        assert!(out.contains("http_responses_total{class=\"3xx\"} 1\n"), "{}", out);
+        assert!(out.contains("http_responses_total{class=\"4xx\"} 1\n"), "{}", out);
+        assert!(out.contains("http_responses_total{class=\"5xx\"} 1\n"), "{}", out);
+        assert!(out.contains("doc_versions 3\n"), "{}", out);
+    }
+}
@@ -0,0 +1,103 @@
+// -*- coding:utf-8-unix -*-
+
+//! Content types for the file extensions that nickel doesn't know, or gets
+//! wrong, like `.wasm`. Browsers refuse to compile WebAssembly or to run
+//! JavaScript modules served as `application/octet-stream`.
+
+use hyper::header::ContentType;
+use hyper::mime::Mime;
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::status::StatusCode;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The overrides that are always there. `MIME_TYPES` can replace them.
+const DEFAULT_MIME_TYPES: &[(&str, &str)] = &[("wasm", "application/wasm"),
+                                              ("mjs", "text/javascript")];
+
+/// Sets the content type of successful responses by the extension of the
+/// request path. It is set when the response is sent, so it wins over the
+/// one `StaticFilesHandler` picks. Register it in front of the static files.
+pub struct MimeTypes {
+    types: HashMap<String, Mime>,
+}
+
+impl MimeTypes {
+    /// `types` maps lowercase extensions without the dot to content types.
+    pub fn new(types: HashMap<String, Mime>) -> MimeTypes {
+        MimeTypes { types }
+    }
+}
+
+impl<D> Middleware<D> for MimeTypes {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        let mime = req.path_without_query()
+            .and_then(|path| Path::new(path).extension())
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.types.get(&ext.to_lowercase()))
+            .cloned();
+        if let Some(mime) = mime {
+            res.on_send(move |res| {
+                // not for the 404 page and other errors
+                if matches!(res.status(),
+                            StatusCode::Ok | StatusCode::PartialContent | StatusCode::NotModified) {
+                    res.set(ContentType(mime.clone()));
+                }
+            });
+        }
+        res.next_middleware()
+    }
+}
+
+/// Returns the default overrides with the ones from `spec` on top.
+/// `spec` is a comma separated list like "wasm=application/wasm,dat=text/plain".
+pub fn parse_mime_types(spec: &str) -> Result<HashMap<String, Mime>, String> {
+    let mut types: HashMap<String, Mime> = DEFAULT_MIME_TYPES.iter()
+        .map(|&(ext, mime)| (ext.to_string(), mime.parse().unwrap()))
+        .collect();
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let parsed = entry.split_once('=')
+            .map(|(ext, mime)| (ext.trim().trim_start_matches('.').to_lowercase(), mime.trim()))
+            .filter(|(ext, _)| !ext.is_empty())
+            .and_then(|(ext, mime)| mime.parse::<Mime>().ok().map(|mime| (ext, mime)));
+        match parsed {
+            Some((ext, mime)) => {
+                types.insert(ext, mime);
+            }
+            None => return Err(format!("Invalid MIME_TYPES entry '{}'", entry)),
+        }
+    }
+    Ok(types)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_mime_types;
+
+    #[test]
+    fn defaults() {
+        let types = parse_mime_types("").unwrap();
+        assert_eq!("application/wasm", types["wasm"].to_string());
+        assert_eq!("text/javascript", types["mjs"].to_string());
+    }
+
+    #[test]
+    fn overrides() {
+        let types = parse_mime_types(" .DAT=text/plain , mjs=application/javascript").unwrap();
+        assert_eq!("text/plain", types["dat"].to_string());
+        assert_eq!("application/javascript", types["mjs"].to_string());
+        assert_eq!("application/wasm", types["wasm"].to_string());
+    }
+
+    #[test]
+    fn invalid_entries() {
+        assert!(parse_mime_types("wasm").is_err());
+        assert!(parse_mime_types("=text/plain").is_err());
+        assert!(parse_mime_types("dat=not a type").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+// -*- coding:utf-8-unix -*-
+
+//! The URL of the site as the clients see it, behind a proxy like Heroku's
+//! router. The proxy tells the original scheme and host with the
+//! `X-Forwarded-Proto` and `X-Forwarded-Host` headers. Anyone can send those,
+//! so the host is only taken from them with `TRUST_PROXY=true`. The scheme
+//! alone is used for `FORCE_HTTPS=true`, which needs the proxy to set it.
+
+use hyper::header::{Headers, Host, Location};
+use hyper::uri::RequestUri::AbsolutePath;
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::status::StatusCode;
+
+/// Returns the scheme and host of the site, e.g. "https://example.com", from
+/// the forwarded headers when the proxy is trusted, or else from `Host`.
+/// Returns `None` if there is no host to tell.
+pub fn site_url(headers: &Headers, trust_proxy: bool) -> Option<String> {
+    let forwarded = if trust_proxy { forwarded(headers) } else { (None, None) };
+    let scheme = forwarded.0.unwrap_or_else(|| "http".to_string());
+    let host = match forwarded.1 {
+        Some(host) => host,
+        None => {
+            let host = headers.get::<Host>()?;
+            match host.port {
+                Some(port) => format!("{}:{}", host.hostname, port),
+                None => host.hostname.clone(),
+            }
+        }
+    };
+    Some(format!("{}://{}", scheme, host))
+}
+
+/// Returns the scheme and the host from the `X-Forwarded-Proto` and
+/// `X-Forwarded-Host` headers. With several proxies, the first one's wins, as
+/// it is the one the client connected to.
+fn forwarded(headers: &Headers) -> (Option<String>, Option<String>) {
+    let first = |name: &str| {
+        headers.get_raw(name)
+            .and_then(|values| values.first())
+            .and_then(|value| String::from_utf8(value.clone()).ok())
+            .and_then(|value| value.split(',').next().map(|v| v.trim().to_string()))
+            .filter(|value| !value.is_empty())
+    };
+    let scheme = first("X-Forwarded-Proto")
+        .map(|scheme| scheme.to_lowercase())
+        .filter(|scheme| scheme == "http" || scheme == "https");
+    (scheme, first("X-Forwarded-Host"))
+}
+
+/// Makes the redirects absolute with the forwarded scheme and host, so that
+/// a client behind the proxy is not sent to the internal URL. Requests without
+/// the forwarded headers keep their relative redirects. Register it in front
+/// of `BasePath`, so the redirects have the prefix already.
+pub struct ForwardedRedirects;
+
+impl ForwardedRedirects {
+    pub fn new() -> ForwardedRedirects {
+        ForwardedRedirects
+    }
+}
+
+impl<D> Middleware<D> for ForwardedRedirects {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        if forwarded(&req.origin.headers) != (None, None) {
+            if let Some(site) = site_url(&req.origin.headers, true) {
+                res.on_send(move |res| {
+                    let location = res.headers().get::<Location>().map(|loc| loc.0.clone());
+                    if let Some(location) = location {
+                        if location.starts_with('/') && !location.starts_with("//") {
+                            res.set(Location(format!("{}{}", site, location)));
+                        }
+                    }
+                });
+            }
+        }
+        res.next_middleware()
+    }
+}
+
+/// Returns the `https://` URL for a request that came to the proxy over plain
+/// HTTP, i.e. with `X-Forwarded-Proto: http`. The host is the forwarded one
+/// when the proxy is trusted, or else the `Host` without its port, which was
+/// the HTTP one. Returns `None` for the other requests.
+fn https_url(headers: &Headers, uri: &str, trust_proxy: bool) -> Option<String> {
+    let (scheme, host) = forwarded(headers);
+    if scheme.as_deref() != Some("http") {
+        return None;
+    }
+    let host = match host.filter(|_| trust_proxy) {
+        Some(host) => host,
+        None => headers.get::<Host>()?.hostname.clone(),
+    };
+    Some(format!("https://{}{}", host, uri))
+}
+
+/// Redirects the requests that came over plain HTTP to the same URL on HTTPS
+/// with `301 Moved Permanently`, for `FORCE_HTTPS=true`. The requests without
+/// `X-Forwarded-Proto`, e.g. from inside the dyno, are passed on. Unless the
+/// proxy is trusted, `X-Forwarded-Host` is ignored, so a client can't pick the
+/// host it is redirected to. Register it in front of `BasePath`, so the
+/// redirects keep the prefix.
+pub struct HttpsRedirect {
+    trust_proxy: bool,
+}
+
+impl HttpsRedirect {
+    pub fn new(trust_proxy: bool) -> HttpsRedirect {
+        HttpsRedirect { trust_proxy }
+    }
+}
+
+impl<D> Middleware<D> for HttpsRedirect {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        let target = match req.origin.uri {
+            AbsolutePath(ref uri) => https_url(&req.origin.headers, uri, self.trust_proxy),
+            _ => None,
+        };
+        match target {
+            Some(target) => {
+                res.set(Location(target));
+                res.send((StatusCode::MovedPermanently, ""))
+            }
+            None => res.next_middleware(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{https_url, site_url};
+
+    use hyper::header::{Headers, Host};
+
+    fn headers(forwarded: &[(&str, &str)]) -> Headers {
+        let mut headers = Headers::new();
+        headers.set(Host {
+            hostname: "internal".to_string(),
+            port: Some(6767),
+        });
+        for &(name, value) in forwarded {
+            headers.set_raw(name.to_string(), vec![value.as_bytes().to_vec()]);
+        }
+        headers
+    }
+
+    #[test]
+    fn host_header() {
+        assert_eq!(Some("http://internal:6767".to_string()), site_url(&headers(&[]), true));
+        assert_eq!(None, site_url(&Headers::new(), true));
+    }
+
+    #[test]
+    fn forwarded_headers() {
+        let headers = headers(&[("X-Forwarded-Proto", "https, http"),
+                                ("X-Forwarded-Host", "docs.example.com, internal")]);
+        assert_eq!(Some("https://docs.example.com".to_string()), site_url(&headers, true));
+        assert_eq!(Some("http://internal:6767".to_string()), site_url(&headers, false));
+    }
+
+    #[test]
+    fn https_urls() {
+        let http = headers(&[("X-Forwarded-Proto", "http")]);
+        assert_eq!(Some("https://internal/1.10/?q=1".to_string()),
+                   https_url(&http, "/1.10/?q=1", false));
+
+        let forwarded = headers(&[("X-Forwarded-Proto", "HTTP"),
+                                  ("X-Forwarded-Host", "docs.example.com")]);
+        assert_eq!(Some("https://docs.example.com/".to_string()),
+                   https_url(&forwarded, "/", true));
+        assert_eq!(Some("https://internal/".to_string()), https_url(&forwarded, "/", false));
+
+        let https = headers(&[("X-Forwarded-Proto", "https")]);
+        assert_eq!(None, https_url(&https, "/", true));
+        assert_eq!(None, https_url(&headers(&[]), "/", true));
+    }
+
+    #[test]
+    fn unknown_schemes_are_ignored() {
+        let headers = headers(&[("X-Forwarded-Proto", "javascript")]);
+        assert_eq!(Some("http://internal:6767".to_string()), site_url(&headers, true));
+    }
+}
@@ -0,0 +1,142 @@
+// -*- coding:utf-8-unix -*-
+
+//! Byte ranges of the static files, for resumable downloads of the large doc
+//! archives and PDFs. Only single ranges are served; a request for several
+//! ranges gets the whole file, which the spec allows.
+
+use head;
+
+use hyper::header::{AcceptRanges, ByteRangeSpec, ContentLength, ContentRange, ContentRangeSpec,
+                    ContentType, IfRange, Range, RangeUnit};
+use hyper::method::Method;
+
+use nickel::{Action, Middleware, MiddlewareResult, Request, Response};
+use nickel::status::StatusCode;
+
+use static_files;
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Answers the `Range` requests for the static files under the root path with
+/// `206 Partial Content`, or `416 Range Not Satisfiable` when the range is not
+/// in the file. Other requests get `Accept-Ranges: bytes` and are passed on.
+/// Mount it after `CacheValidation`, so `304 Not Modified` wins, and in front
+/// of the compression, as the ranges are of the file as is.
+pub struct RangeRequests {
+    root_path: PathBuf,
+}
+
+impl RangeRequests {
+    pub fn new<P: AsRef<Path>>(root_path: P) -> RangeRequests {
+        RangeRequests { root_path: root_path.as_ref().to_path_buf() }
+    }
+}
+
+impl<D> Middleware<D> for RangeRequests {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        if req.origin.method != Method::Get {
+            return res.next_middleware();
+        }
+        let path = match static_files::requested_file(&self.root_path, req) {
+            Some(path) => path,
+            None => return res.next_middleware(),
+        };
+        res.set(AcceptRanges(vec![RangeUnit::Bytes]));
+
+        let spec = match req.origin.headers.get::<Range>() {
+            Some(Range::Bytes(specs)) if specs.len() == 1 => specs[0].clone(),
+            _ => return res.next_middleware(),
+        };
+        // the range is of the version the client has, which can't be told from
+        // the weak ETags of CacheValidation. so it gets the whole file
+        if req.origin.headers.has::<IfRange>() {
+            return res.next_middleware();
+        }
+
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(_) => return res.next_middleware(),
+        };
+        let len = match file.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return res.next_middleware(),
+        };
+        let (first, last) = match byte_range(&spec, len) {
+            Some(range) => range,
+            None => {
+                res.headers_mut().set(ContentRange(ContentRangeSpec::Bytes {
+                    range: None,
+                    instance_length: Some(len),
+                }));
+                // sent as is, not through the error handlers
+                res.set(StatusCode::RangeNotSatisfiable);
+                return res.send("");
+            }
+        };
+        if file.seek(SeekFrom::Start(first)).is_err() {
+            return res.next_middleware();
+        }
+
+        res.set(StatusCode::PartialContent);
+        res.set(ContentType(static_files::media_type(&path)));
+        res.set(ContentLength(last - first + 1));
+        res.headers_mut().set(ContentRange(ContentRangeSpec::Bytes {
+            range: Some((first, last)),
+            instance_length: Some(len),
+        }));
+        let mut stream = res.start()?;
+        if head::is_head(&stream) {
+            return Ok(Action::Halt(stream));
+        }
+        match io::copy(&mut file.take(last - first + 1), &mut stream) {
+            Ok(_) => Ok(Action::Halt(stream)),
+            // the headers are sent already, so all we can do is to cut it short
+            Err(e) => stream.bail(format!("Failed to send file: {}", e)),
+        }
+    }
+}
+
+/// Returns the first and the last byte of the range in a file of `len` bytes,
+/// or `None` if the range is not in the file. A range past the end is cut at
+/// the end.
+fn byte_range(spec: &ByteRangeSpec, len: u64) -> Option<(u64, u64)> {
+    match *spec {
+        ByteRangeSpec::FromTo(first, last) if first <= last && first < len => {
+            Some((first, last.min(len - 1)))
+        }
+        ByteRangeSpec::AllFrom(first) if first < len => Some((first, len - 1)),
+        ByteRangeSpec::Last(n) if n > 0 && len > 0 => Some((len - n.min(len), len - 1)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::byte_range;
+
+    use hyper::header::ByteRangeSpec::{AllFrom, FromTo, Last};
+
+    #[test]
+    fn byte_ranges() {
+        assert_eq!(Some((2, 5)), byte_range(&FromTo(2, 5), 10));
+        assert_eq!(Some((0, 0)), byte_range(&FromTo(0, 0), 10));
+        assert_eq!(Some((8, 9)), byte_range(&FromTo(8, 100), 10));
+        assert_eq!(Some((7, 9)), byte_range(&AllFrom(7), 10));
+        assert_eq!(Some((7, 9)), byte_range(&Last(3), 10));
+        assert_eq!(Some((0, 9)), byte_range(&Last(30), 10));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(None, byte_range(&FromTo(10, 20), 10));
+        assert_eq!(None, byte_range(&FromTo(5, 2), 10));
+        assert_eq!(None, byte_range(&AllFrom(10), 10));
+        assert_eq!(None, byte_range(&Last(0), 10));
+        assert_eq!(None, byte_range(&Last(1), 0));
+    }
+}
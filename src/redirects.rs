@@ -0,0 +1,134 @@
+// -*- coding:utf-8-unix -*-
+
+//! Permanent redirects for the pages that were moved or renamed, from the
+//! file named by the `REDIRECTS` env var, so that the old links keep working.
+//! Each line of the file is an old path and its new path:
+//!
+//! ```text
+//! # comments and empty lines are ignored
+//! /1.10/book/README.html /1.10/book/index.html
+//! /nomicon/ /1.10/nomicon/
+//! ```
+
+use hyper::header::Location;
+use hyper::uri::RequestUri::AbsolutePath;
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::status::StatusCode;
+
+use logger;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Redirects the old paths to the new ones with `301 Moved Permanently`,
+/// keeping the query. Register it in front of the static files.
+pub struct Redirects {
+    targets: HashMap<String, String>,
+}
+
+impl Redirects {
+    /// `redirects` are (old, new) pairs. When an old path is listed twice, the
+    /// first one wins.
+    pub fn new(redirects: &[(String, String)]) -> Redirects {
+        let mut targets = HashMap::new();
+        for (old, new) in redirects {
+            targets.entry(old.clone()).or_insert_with(|| new.clone());
+        }
+        Redirects { targets }
+    }
+}
+
+impl<D> Middleware<D> for Redirects {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        let target = match req.origin.uri {
+            AbsolutePath(ref uri) => {
+                let (path, query) = uri.split_at(uri.find('?').unwrap_or(uri.len()));
+                self.targets.get(path).map(|target| format!("{}{}", target, query))
+            }
+            _ => None,
+        };
+        match target {
+            Some(target) => {
+                res.set(Location(target));
+                res.send((StatusCode::MovedPermanently, ""))
+            }
+            None => res.next_middleware(),
+        }
+    }
+}
+
+/// Reads the redirects file. Only a missing or unreadable file is an error.
+pub fn load_redirects<P: AsRef<Path>>(path: P) -> io::Result<Vec<(String, String)>> {
+    Ok(parse_redirects(&fs::read_to_string(path)?))
+}
+
+/// Returns the (old, new) pairs from the lines of a redirects file. Malformed
+/// lines are skipped with a warning: both paths must be absolute, and the old
+/// one can't be `/` or have a query. The new one may be a full URL.
+pub fn parse_redirects(contents: &str) -> Vec<(String, String)> {
+    let mut redirects = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields[..] {
+            [old, new] if is_valid_old_path(old) && is_valid_target(new) => {
+                redirects.push((old.to_string(), new.to_string()))
+            }
+            _ => {
+                logger::warn(&format!("Skipping the malformed line {} of the redirects file: \
+                                       {:?}",
+                                      i + 1,
+                                      line))
+            }
+        }
+    }
+    redirects
+}
+
+fn is_valid_old_path(path: &str) -> bool {
+    path.starts_with('/') && path != "/" && !path.contains('?')
+}
+
+fn is_valid_target(target: &str) -> bool {
+    target.starts_with('/') || target.starts_with("https://") || target.starts_with("http://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_redirects;
+
+    #[test]
+    fn redirect_lines() {
+        let contents = "# moved in 1.10\n\
+                        \n\
+                        /1.10/book/README.html  /1.10/book/index.html\n\
+                        /nomicon/ https://doc.rust-lang.org/nomicon/\n";
+        assert_eq!(vec![("/1.10/book/README.html".to_string(),
+                         "/1.10/book/index.html".to_string()),
+                        ("/nomicon/".to_string(),
+                         "https://doc.rust-lang.org/nomicon/".to_string())],
+                   parse_redirects(contents));
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let contents = "/old.html\n\
+                        /a.html /b.html /c.html\n\
+                        old.html /new.html\n\
+                        / /1.10/\n\
+                        /old.html?q=1 /new.html\n\
+                        /old.html new.html\n\
+                        /ok.html /new.html\n";
+        assert_eq!(vec![("/ok.html".to_string(), "/new.html".to_string())],
+                   parse_redirects(contents));
+    }
+}
@@ -0,0 +1,95 @@
+// -*- coding:utf-8-unix -*-
+
+//! The `X-Request-Id` of each request, to find the log lines of a request from
+//! the Heroku router's ones. The router sends the header; requests without it
+//! get a new id.
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const HEADER: &str = "X-Request-Id";
+
+/// Heroku's router accepts ids of 20 to 200 characters. Shorter ones are
+/// taken too, but longer ones are replaced so that they can't flood the log.
+const MAX_ID_LEN: usize = 200;
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Stores the id in the request headers, so the middleware after this one
+/// (e.g. `RequestLogger`) can read it with `request_id`, and sends it back in
+/// the response. Register it first.
+pub struct RequestId;
+
+impl RequestId {
+    pub fn new() -> RequestId {
+        RequestId
+    }
+}
+
+impl<D> Middleware<D> for RequestId {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        let id = request_id(req).unwrap_or_else(generate);
+        req.origin.headers.set_raw(HEADER, vec![id.as_bytes().to_vec()]);
+        res.on_send(move |res| {
+            res.headers_mut().set_raw(HEADER, vec![id.as_bytes().to_vec()]);
+        });
+        res.next_middleware()
+    }
+}
+
+/// Returns the `X-Request-Id` of the request if it is a usable one.
+pub fn request_id<D>(req: &Request<D>) -> Option<String> {
+    req.origin.headers.get_raw(HEADER)
+        .and_then(|values| values.first())
+        .and_then(|value| String::from_utf8(value.clone()).ok())
+        .filter(|id| is_valid_id(id))
+}
+
+/// Printable ASCII without spaces, so it stays one word in the log line.
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Returns a new id shaped like a UUID, e.g.
+/// "17f3c2a1-9b04-5e21-00c8-000000000001", from the time, the process id and a
+/// counter. It is unique, but not random, which is all the logs need.
+fn generate() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            nanos >> 32,
+            (nanos >> 16) & 0xffff,
+            nanos & 0xffff,
+            process::id() & 0xffff,
+            count & 0xffff_ffff_ffff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate, is_valid_id};
+
+    #[test]
+    fn generated_ids_differ() {
+        let (id1, id2) = (generate(), generate());
+        assert_ne!(id1, id2);
+        assert_eq!(36, id1.len());
+        assert!(is_valid_id(&id1));
+    }
+
+    #[test]
+    fn valid_ids() {
+        assert!(is_valid_id("5a2e6f0b-4c1d-4f0e-9b8a-1c2d3e4f5a6b"));
+        assert!(!is_valid_id(""));
+        assert!(!is_valid_id("two words"));
+        assert!(!is_valid_id(&"x".repeat(201)));
+    }
+}
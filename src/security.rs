@@ -0,0 +1,61 @@
+// -*- coding:utf-8-unix -*-
+
+//! Security headers set on every response.
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+
+/// Sets `X-Content-Type-Options: nosniff`, `X-Frame-Options: DENY`, and the
+/// `Content-Security-Policy` if one is given, on every response. The headers
+/// are set just before the response is sent, so they also apply to the
+/// responses of the middleware registered after this one.
+pub struct SecurityHeaders {
+    content_security_policy: Option<String>,
+}
+
+impl SecurityHeaders {
+    pub fn new(content_security_policy: Option<String>) -> SecurityHeaders {
+        SecurityHeaders { content_security_policy }
+    }
+
+    fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![("X-Content-Type-Options", "nosniff".to_string()),
+                               ("X-Frame-Options", "DENY".to_string())];
+        if let Some(ref csp) = self.content_security_policy {
+            headers.push(("Content-Security-Policy", csp.clone()));
+        }
+        headers
+    }
+}
+
+impl<D> Middleware<D> for SecurityHeaders {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          _req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        let headers = self.headers();
+        res.on_send(move |res| {
+            for &(name, ref value) in &headers {
+                res.headers_mut().set_raw(name, vec![value.as_bytes().to_vec()]);
+            }
+        });
+        res.next_middleware()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SecurityHeaders;
+
+    #[test]
+    fn csp_is_optional() {
+        let names = |headers: Vec<(&'static str, String)>| {
+            headers.into_iter().map(|(name, _)| name).collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["X-Content-Type-Options", "X-Frame-Options"],
+                   names(SecurityHeaders::new(None).headers()));
+
+        let headers = SecurityHeaders::new(Some("default-src 'self'".to_string())).headers();
+        assert_eq!(("Content-Security-Policy", "default-src 'self'".to_string()),
+                   headers[2]);
+    }
+}
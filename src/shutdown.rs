@@ -0,0 +1,130 @@
+// -*- coding:utf-8-unix -*-
+
+//! Graceful shutdown. Nickel's `listen` blocks forever and can't be stopped,
+//! so on SIGTERM the server keeps listening but turns new requests away, and
+//! the process exits once the requests in flight are done.
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::status::StatusCode;
+
+use plugin::Extensible;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use typemap::Key;
+
+/// Whether the server is shutting down, and how many requests are in flight.
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+}
+
+impl Shutdown {
+    /// Makes `ShutdownGuard` turn new requests away.
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+}
+
+/// Counts a request as in flight while it is kept in the request's extensions.
+/// Nickel drops the request once the response is ended, so a streamed body is
+/// counted until its last byte is written, not only until the headers go out.
+struct InFlight(Arc<Shutdown>);
+
+impl Key for InFlight {
+    type Value = InFlight;
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Counts the requests in flight, and answers `503 Service Unavailable` with
+/// `Connection: close` once the shutdown has started. Register it in front of
+/// the other middleware.
+pub struct ShutdownGuard {
+    shutdown: Arc<Shutdown>,
+}
+
+impl ShutdownGuard {
+    pub fn new(shutdown: Arc<Shutdown>) -> ShutdownGuard {
+        ShutdownGuard { shutdown }
+    }
+}
+
+impl<D> Middleware<D> for ShutdownGuard {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        if self.shutdown.is_draining() {
+            res.headers_mut().set_raw("Connection", vec![b"close".to_vec()]);
+            return res.send((StatusCode::ServiceUnavailable, "The server is shutting down"));
+        }
+
+        self.shutdown.in_flight.fetch_add(1, Ordering::SeqCst);
+        req.extensions_mut().insert::<InFlight>(InFlight(self.shutdown.clone()));
+        res.next_middleware()
+    }
+}
+
+/// Waits until `in_flight` returns 0, checking every `poll`, for up to
+/// `timeout`. Returns false if there still were requests in flight at the
+/// timeout.
+pub fn wait_for_drain<F>(in_flight: F, timeout: Duration, poll: Duration) -> bool
+    where F: Fn() -> usize
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        if in_flight() == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        thread::sleep(poll.min(deadline - now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::wait_for_drain;
+
+    use std::cell::Cell;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn drains_when_requests_finish() {
+        let remaining = Cell::new(3);
+        let in_flight = || {
+            remaining.set(remaining.get() - 1);
+            remaining.get()
+        };
+        assert!(wait_for_drain(in_flight, Duration::from_secs(5), Duration::from_millis(1)));
+        assert_eq!(0, remaining.get());
+    }
+
+    #[test]
+    fn gives_up_at_the_timeout() {
+        let start = Instant::now();
+        assert!(!wait_for_drain(|| 1, Duration::from_millis(50), Duration::from_millis(10)));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(50), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(1), "{:?}", elapsed);
+    }
+}
@@ -0,0 +1,116 @@
+// -*- coding:utf-8-unix -*-
+
+//! The reasons the server can't start, each with its own exit status, so that
+//! the scripts starting it can tell them apart.
+
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum StartupError {
+    /// An env var with an invalid value, e.g. `BIND_RETRIES=x`.
+    BadConfig(String),
+    /// An invalid `PORT`, `BIND` or `--port`.
+    BadPort(String),
+    /// One of the `DOC_ROOT` directories is not there.
+    DocRootMissing(String),
+    /// The doc root could not be scanned for the versions.
+    ScanFailed { dir: String, error: io::Error },
+    /// A template is missing or doesn't compile.
+    BadTemplate(String),
+    /// The port is still taken after all the `BIND_RETRIES`.
+    BindFailed {
+        address: String,
+        port: u16,
+        attempts: u32,
+        error: io::Error,
+    },
+}
+
+impl StartupError {
+    /// The exit status for the error. A failed bind keeps 1, which it had
+    /// before the others got their own.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            StartupError::BindFailed { .. } => 1,
+            StartupError::BadConfig(_) => 2,
+            StartupError::BadPort(_) => 3,
+            StartupError::DocRootMissing(_) => 4,
+            StartupError::ScanFailed { .. } => 5,
+            StartupError::BadTemplate(_) => 6,
+        }
+    }
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StartupError::BadConfig(ref e) |
+            StartupError::BadPort(ref e) |
+            StartupError::DocRootMissing(ref e) |
+            StartupError::BadTemplate(ref e) => write!(f, "{}", e),
+            StartupError::ScanFailed { ref dir, ref error } => {
+                write!(f,
+                       "An error occured while scanning the doc root directory. Error: {}, \
+                        Dir: {}",
+                       error,
+                       dir)
+            }
+            StartupError::BindFailed { ref address, port, attempts, ref error } => {
+                write!(f,
+                       "Could not bind to {}:{} after {} attempt(s). Error: {}",
+                       address,
+                       port,
+                       attempts,
+                       error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StartupError;
+
+    use std::collections::HashSet;
+    use std::io;
+
+    fn all_errors() -> Vec<StartupError> {
+        vec![StartupError::BadConfig("Invalid BIND_RETRIES value 'x'".to_string()),
+             StartupError::BadPort("Invalid PORT value 'x'".to_string()),
+             StartupError::DocRootMissing("The doc root directory does not exist. Dir: public"
+                 .to_string()),
+             StartupError::ScanFailed {
+                 dir: "public".to_string(),
+                 error: io::Error::new(io::ErrorKind::PermissionDenied, "denied"),
+             },
+             StartupError::BadTemplate("The template file does not exist. File: x.mustache"
+                 .to_string()),
+             StartupError::BindFailed {
+                 address: "0.0.0.0".to_string(),
+                 port: 6767,
+                 attempts: 3,
+                 error: io::Error::new(io::ErrorKind::AddrInUse, "in use"),
+             }]
+    }
+
+    #[test]
+    fn messages() {
+        let messages: Vec<String> = all_errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(vec!["Invalid BIND_RETRIES value 'x'",
+                        "Invalid PORT value 'x'",
+                        "The doc root directory does not exist. Dir: public",
+                        "An error occured while scanning the doc root directory. Error: denied, \
+                         Dir: public",
+                        "The template file does not exist. File: x.mustache",
+                        "Could not bind to 0.0.0.0:6767 after 3 attempt(s). Error: in use"],
+                   messages);
+    }
+
+    #[test]
+    fn distinct_exit_codes() {
+        let codes: HashSet<i32> = all_errors().iter().map(|e| e.exit_code()).collect();
+        assert_eq!(all_errors().len(), codes.len());
+        assert!(!codes.contains(&0));
+    }
+}
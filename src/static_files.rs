@@ -0,0 +1,106 @@
+// -*- coding:utf-8-unix -*-
+
+//! The static files under the doc root, and the helpers shared by the
+//! middleware that work on them in front of (or instead of) `StaticFiles`.
+
+use head;
+
+use hyper::mime::Mime;
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::mimes::MediaType;
+use nickel::status::StatusCode;
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Serves the files under the root, like nickel's `StaticFilesHandler`, but
+/// with their `Content-Length` and without the body for HEAD requests. The
+/// paths that would escape the root are "400 Bad Request", the others that
+/// are not files are passed on.
+pub struct StaticFiles {
+    root_path: PathBuf,
+}
+
+impl StaticFiles {
+    pub fn new<P: AsRef<Path>>(root_path: P) -> StaticFiles {
+        StaticFiles { root_path: root_path.as_ref().to_path_buf() }
+    }
+}
+
+impl<D> Middleware<D> for StaticFiles {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        let unsafe_path = req.path_without_query()
+            .is_some_and(|path| path != "/" && !is_safe_path(Path::new(&path[1..])));
+        if unsafe_path {
+            return res.error(StatusCode::BadRequest, "The path was denied access.");
+        }
+        match requested_file(&self.root_path, req) {
+            Some(path) => head::send_file(res, &path),
+            None => res.next_middleware(),
+        }
+    }
+}
+
+/// Returns the path of the file under `root_path` that the request targets,
+/// the same way `StaticFiles` resolves it. Returns `None` when there is
+/// no such file, or when the path tries to escape `root_path`.
+pub fn requested_file<D>(root_path: &Path, req: &Request<D>) -> Option<PathBuf> {
+    let relative_path = match req.path_without_query() {
+        Some("/") => "index.html",
+        Some(path) => &path[1..],
+        None => return None,
+    };
+
+    if !is_safe_path(Path::new(relative_path)) {
+        return None;
+    }
+
+    let path = root_path.join(relative_path);
+    match fs::metadata(&path) {
+        Ok(ref metadata) if metadata.is_file() => Some(path),
+        _ => None,
+    }
+}
+
+/// Returns false if the relative path contains anything but normal components
+/// (e.g. `..`), so that it can't point outside of the doc root.
+pub fn is_safe_path(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::CurDir | Component::Normal(_)))
+}
+
+/// Returns the content type for the file, determined by its extension in the
+/// same way as nickel's `StaticFilesHandler`. Defaults to `application/octet-stream`.
+pub fn media_type(path: &Path) -> Mime {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(|ext| ext.parse().ok())
+        .unwrap_or(MediaType::Bin)
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_safe_path, media_type};
+
+    use std::path::Path;
+
+    #[test]
+    fn safe_paths() {
+        assert!(is_safe_path(Path::new("1.10/book/index.html")));
+        assert!(is_safe_path(Path::new("./1.10/")));
+        assert!(!is_safe_path(Path::new("../../etc/passwd")));
+        assert!(!is_safe_path(Path::new("1.10/../../etc/passwd")));
+        assert!(!is_safe_path(Path::new("/etc/passwd")));
+    }
+
+    #[test]
+    fn media_types() {
+        assert_eq!("text/html", media_type(Path::new("1.10/index.html")).to_string());
+        assert_eq!("image/png", media_type(Path::new("logo.png")).to_string());
+        assert_eq!("application/octet-stream", media_type(Path::new("LICENSE")).to_string());
+    }
+}
@@ -0,0 +1,100 @@
+// -*- coding:utf-8-unix -*-
+
+//! Per version hit counters, to see which doc versions get traffic. Served as
+//! JSON at `/api/stats`.
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+
+use rustc_serialize::json::{self, ToJson};
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use {has_version, SharedVersions};
+
+/// The number of requests under each version. A version gets its counter at
+/// its first request, so the ones added by a rescan are counted too.
+#[derive(Debug, Default)]
+pub struct VersionHits {
+    counts: RwLock<BTreeMap<String, AtomicUsize>>,
+}
+
+impl VersionHits {
+    pub fn new() -> VersionHits {
+        VersionHits::default()
+    }
+
+    /// Counts a request for the version, as it appears in the URLs. e.g. "1.10"
+    pub fn record(&self, version: &str) {
+        if let Some(count) = self.counts.read().unwrap().get(version) {
+            count.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.counts
+            .write()
+            .unwrap()
+            .entry(version.to_string())
+            .or_insert_with(|| AtomicUsize::new(0))
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the counts of the versions as a JSON object, with 0 for the
+    /// ones without a request yet. The versions removed by a rescan are left
+    /// out. e.g. {"1.10":3,"1.9":0}
+    pub fn to_json_string<S: AsRef<str>>(&self, versions: &[S]) -> String {
+        let counts = self.counts.read().unwrap();
+        let counts: BTreeMap<String, json::Json> = versions.iter()
+            .map(|ver| {
+                let count = counts.get(ver.as_ref()).map_or(0, |c| c.load(Ordering::Relaxed));
+                (ver.as_ref().to_string(), count.to_json())
+            })
+            .collect();
+        json::encode(&counts).unwrap()
+    }
+}
+
+/// Counts the requests for the version paths, by the versions found by the
+/// latest scan. Register it in front of the routes, after anything that
+/// rewrites the path.
+pub struct HitCounter {
+    hits: Arc<VersionHits>,
+    versions: SharedVersions,
+}
+
+impl HitCounter {
+    pub fn new(hits: Arc<VersionHits>, versions: SharedVersions) -> HitCounter {
+        HitCounter { hits, versions }
+    }
+}
+
+impl<D> Middleware<D> for HitCounter {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        // e.g. "/1.10/book/index.html" counts for 1.10
+        if let Some(path) = req.path_without_query() {
+            let version = path.trim_start_matches('/').split('/').next().unwrap_or("");
+            if has_version(&self.versions.read().unwrap(), version) {
+                self.hits.record(version);
+            }
+        }
+        res.next_middleware()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VersionHits;
+
+    #[test]
+    fn counts_by_version() {
+        let hits = VersionHits::new();
+        hits.record("1.10");
+        hits.record("1.10");
+        hits.record("1.9");
+        assert_eq!(r#"{"1.10":2,"1.9":1}"#, hits.to_json_string(&["1.10", "1.9"]));
+        assert_eq!(r#"{"1.10":2,"2.0":0}"#, hits.to_json_string(&["2.0", "1.10"]));
+    }
+}
@@ -0,0 +1,65 @@
+// -*- coding:utf-8-unix -*-
+
+//! Time limits for the handlers that do real work, like rendering the menu or
+//! rescanning the doc root. Nickel can't interrupt a handler, so the work runs
+//! on its own thread, and the handler stops waiting for it at the timeout.
+//! Static files are not limited, as large files take long for good reasons.
+
+use nickel::{MiddlewareResult, Response};
+use nickel::status::StatusCode;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// The work did not finish within the time limit. It may still be running.
+#[derive(Debug, PartialEq)]
+pub struct TimedOut;
+
+/// Runs the work and returns its result, or `TimedOut` if it takes longer than
+/// the timeout. Without a timeout, the work runs on the calling thread.
+pub fn run_with_timeout<T, F>(timeout: Option<Duration>, work: F) -> Result<T, TimedOut>
+    where T: Send + 'static,
+          F: FnOnce() -> T + Send + 'static
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Ok(work()),
+    };
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        // the receiver is gone if the handler stopped waiting
+        let _ = sender.send(work());
+    });
+    receiver.recv_timeout(timeout).map_err(|_| TimedOut)
+}
+
+/// Answers `503 Service Unavailable` for work that timed out.
+pub fn timed_out<'mw>(response: Response<'mw>) -> MiddlewareResult<'mw> {
+    response.error(StatusCode::ServiceUnavailable, "The request took too long")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_with_timeout, TimedOut};
+
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn fast_work() {
+        assert_eq!(Ok(42), run_with_timeout(Some(Duration::from_secs(5)), || 42));
+        assert_eq!(Ok(42), run_with_timeout(None, || 42));
+    }
+
+    #[test]
+    fn slow_work_times_out() {
+        let start = Instant::now();
+        let result = run_with_timeout(Some(Duration::from_millis(20)), || {
+            thread::sleep(Duration::from_millis(500));
+            42
+        });
+        assert_eq!(Err(TimedOut), result);
+        assert!(start.elapsed() < Duration::from_millis(400), "{:?}", start.elapsed());
+    }
+}
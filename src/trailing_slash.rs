@@ -0,0 +1,140 @@
+// -*- coding:utf-8-unix -*-
+
+//! One canonical URL for each directory under the doc root: either with or
+//! without the trailing slash. The other form is redirected to it.
+
+use hyper::header::Location;
+use hyper::uri::RequestUri::AbsolutePath;
+
+use nickel::{Middleware, MiddlewareResult, Request, Response};
+use nickel::status::StatusCode;
+
+use static_files;
+
+use std::path::{Path, PathBuf};
+
+/// The `TRAILING_SLASH` setting.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrailingSlash {
+    /// `/1.10/book` -> `/1.10/book/`. The default, as the relative links in
+    /// the docs need the slash.
+    Add,
+    /// `/1.10/book/` -> `/1.10/book`
+    Remove,
+    /// Both forms are served as they are.
+    Ignore,
+}
+
+impl TrailingSlash {
+    /// Returns the policy for "add", "remove" or "ignore", ignoring case.
+    pub fn from_name(name: &str) -> Option<TrailingSlash> {
+        match name.trim().to_lowercase().as_str() {
+            "add" => Some(TrailingSlash::Add),
+            "remove" => Some(TrailingSlash::Remove),
+            "ignore" => Some(TrailingSlash::Ignore),
+            _ => None,
+        }
+    }
+
+    /// Returns the canonical form of the path when it is not canonical already.
+    /// `is_dir` tells if the path is a directory. Files and `/` are left alone.
+    pub fn redirect_target(&self, path: &str, is_dir: bool) -> Option<String> {
+        if !is_dir || path == "/" {
+            return None;
+        }
+        match *self {
+            TrailingSlash::Add if !path.ends_with('/') => Some(format!("{}/", path)),
+            TrailingSlash::Remove if path.ends_with('/') => {
+                Some(path.trim_end_matches('/').to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Redirects the requests for the directories under the doc roots to their
+/// canonical form with `301 Moved Permanently`. Register it in front of the
+/// routes.
+pub struct TrailingSlashRedirect {
+    root_paths: Vec<PathBuf>,
+    policy: TrailingSlash,
+}
+
+impl TrailingSlashRedirect {
+    pub fn new<P: AsRef<Path>>(root_paths: &[P], policy: TrailingSlash) -> TrailingSlashRedirect {
+        TrailingSlashRedirect {
+            root_paths: root_paths.iter().map(|p| p.as_ref().to_path_buf()).collect(),
+            policy,
+        }
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        let relative_path = Path::new(path.trim_matches('/'));
+        static_files::is_safe_path(relative_path) &&
+        self.root_paths.iter().any(|root| root.join(relative_path).is_dir())
+    }
+}
+
+impl<D> Middleware<D> for TrailingSlashRedirect {
+    fn invoke<'mw, 'conn>(&'mw self,
+                          req: &mut Request<'mw, 'conn, D>,
+                          mut res: Response<'mw, D>)
+                          -> MiddlewareResult<'mw, D> {
+        if self.policy == TrailingSlash::Ignore {
+            return res.next_middleware();
+        }
+        let target = match req.path_without_query() {
+            Some(path) => self.policy.redirect_target(path, self.is_dir(path)),
+            None => None,
+        };
+        match target {
+            Some(target) => {
+                let query = match req.origin.uri {
+                    AbsolutePath(ref uri) => uri.find('?').map(|i| uri[i..].to_string()),
+                    _ => None,
+                };
+                res.set(Location(target + query.as_deref().unwrap_or("")));
+                res.send((StatusCode::MovedPermanently, ""))
+            }
+            None => res.next_middleware(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrailingSlash;
+
+    #[test]
+    fn policy_names() {
+        assert_eq!(Some(TrailingSlash::Add), TrailingSlash::from_name("add"));
+        assert_eq!(Some(TrailingSlash::Remove), TrailingSlash::from_name(" Remove "));
+        assert_eq!(Some(TrailingSlash::Ignore), TrailingSlash::from_name("IGNORE"));
+        assert_eq!(None, TrailingSlash::from_name("keep"));
+    }
+
+    #[test]
+    fn add_policy() {
+        let policy = TrailingSlash::Add;
+        assert_eq!(Some("/1.10/book/".to_string()), policy.redirect_target("/1.10/book", true));
+        assert_eq!(None, policy.redirect_target("/1.10/book/", true));
+        assert_eq!(None, policy.redirect_target("/1.10/book.html", false));
+        assert_eq!(None, policy.redirect_target("/", true));
+    }
+
+    #[test]
+    fn remove_policy() {
+        let policy = TrailingSlash::Remove;
+        assert_eq!(Some("/1.10/book".to_string()), policy.redirect_target("/1.10/book/", true));
+        assert_eq!(None, policy.redirect_target("/1.10/book", true));
+        assert_eq!(None, policy.redirect_target("/1.10/book.html/", false));
+        assert_eq!(None, policy.redirect_target("/", true));
+    }
+
+    #[test]
+    fn ignore_policy() {
+        let policy = TrailingSlash::Ignore;
+        assert_eq!(None, policy.redirect_target("/1.10/book", true));
+        assert_eq!(None, policy.redirect_target("/1.10/book/", true));
+    }
+}
@@ -0,0 +1,121 @@
+// -*- coding:utf-8-unix -*-
+
+//! Boots the server on a free port and checks the routing with real HTTP
+//! requests.
+
+extern crate hello_heroku;
+extern crate hyper;
+extern crate nickel;
+extern crate tempfile;
+
+use hello_heroku::{build_server, get_versions, Config, Version};
+
+use hyper::Client;
+use hyper::client::RedirectPolicy;
+use hyper::status::StatusCode;
+
+use nickel::{Nickel, Options};
+
+use std::fs;
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use tempfile::TempDir;
+
+/// Listens on a free port and returns the base URL. e.g. "http://127.0.0.1:50123"
+fn serve(mut server: Nickel) -> String {
+    server.options = Options::default().output_on_listen(false);
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    thread::spawn(move || server.listen(("127.0.0.1", port)));
+    for _ in 0..100 {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    format!("http://127.0.0.1:{}", port)
+}
+
+/// Starts the server for a doc root with the given version directories, each
+/// with an index.html, and returns its base URL.
+fn start_server(doc_root: &TempDir, dir_names: &[&str]) -> String {
+    for name in dir_names {
+        let dir = doc_root.path().join(name);
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("index.html"), format!("<h1>{}</h1>", name)).unwrap();
+    }
+    let config = Config::new(doc_root.path().to_str().unwrap());
+    serve(build_server(&config, get_versions(&config.doc_root, &config.scan).unwrap()))
+}
+
+fn get(url: &str) -> (StatusCode, String) {
+    let mut client = Client::new();
+    client.set_redirect_policy(RedirectPolicy::FollowNone);
+    let mut res = client.get(url).send().unwrap();
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    (res.status, body)
+}
+
+#[test]
+fn home_lists_the_versions() {
+    let doc_root = TempDir::new().unwrap();
+    let base = start_server(&doc_root, &["1.9", "1.10"]);
+
+    let (status, body) = get(&format!("{}/", base));
+    assert_eq!(StatusCode::Ok, status);
+    let pos_1_10 = body.find("1.10/index.html").expect("1.10 is missing");
+    let pos_1_9 = body.find("1.9/index.html").expect("1.9 is missing");
+    assert!(pos_1_10 < pos_1_9, "newer versions come first");
+}
+
+#[test]
+fn serves_static_files() {
+    let doc_root = TempDir::new().unwrap();
+    let base = start_server(&doc_root, &["1.9"]);
+
+    let (status, body) = get(&format!("{}/1.9/index.html", base));
+    assert_eq!(StatusCode::Ok, status);
+    assert_eq!("<h1>1.9</h1>", body);
+}
+
+#[test]
+fn missing_path_is_not_found() {
+    let doc_root = TempDir::new().unwrap();
+    let base = start_server(&doc_root, &["1.9"]);
+
+    let (status, body) = get(&format!("{}/1.9/no-such-page.html", base));
+    assert_eq!(StatusCode::NotFound, status);
+    assert!(body.contains("/1.9/no-such-page.html"), "{}", body);
+    assert!(body.contains("/1.9/index.html"), "links to the versions: {}", body);
+}
+
+#[test]
+fn routes_use_the_given_versions() {
+    // the doc root is empty; the menu only has what build_server was given
+    let doc_root = TempDir::new().unwrap();
+    let config = Config::new(doc_root.path().to_str().unwrap());
+    let versions = vec![Version::new(1, 10, 0, "1.10"), Version::new(1, 9, 0, "1.9")];
+    let base = serve(build_server(&config, versions));
+
+    let (status, body) = get(&format!("{}/api/versions", base));
+    assert_eq!(StatusCode::Ok, status);
+    assert_eq!(r#"["1.10","1.9"]"#, body);
+}
+
+#[test]
+fn version_count_matches_the_given_versions() {
+    let doc_root = TempDir::new().unwrap();
+    let config = Config::new(doc_root.path().to_str().unwrap());
+    let versions = vec![Version::new(1, 10, 0, "1.10"),
+                        Version::new(1, 9, 0, "1.9"),
+                        Version::new(1, 6, 0, "1.6")];
+    let count = versions.len();
+    let base = serve(build_server(&config, versions));
+
+    let (status, body) = get(&format!("{}/api/version-count", base));
+    assert_eq!(StatusCode::Ok, status);
+    assert_eq!(format!(r#"{{"count":{}}}"#, count), body);
+}